@@ -1,11 +1,32 @@
 use crate::browser::FileBrowser;
-use crate::diff::{self, DiffLine};
+use crate::diff::{
+    self, Context, CopyMethod, DiffLine, DiffRow, FileStats, Granularity, LineEnding, LineRange,
+    PatchFormat, Side,
+};
+use crate::dirdiff::{ChangeKind, DirSummary};
+use crate::editor;
+use crate::external;
+use crate::glyphs::Glyphs;
+use crate::source::DiffSource;
+use crate::theme::Theme;
 use crate::ui;
 use arboard::Clipboard;
-use crossterm::event::{self, Event, KeyCode};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 use ratatui::Terminal;
-use std::fs;
+use ratatui::layout::{Position, Rect};
+use ratatui::text::Line;
+use similar::Algorithm;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::env;
 use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum AppMode {
@@ -13,12 +34,189 @@ pub enum AppMode {
     SelectingSource,
     SelectingTarget,
     SelectionMode,
+    /// The changed files of a recursive directory diff
+    DirSummary,
+}
+
+/// Characters of the first and last selected line shown in the selection summary.
+const SELECTION_PREVIEW_WIDTH: usize = 20;
+
+/// Number of unchanged lines kept around a change in the "changes only" view.
+const CHANGES_ONLY_CONTEXT: usize = 2;
+
+/// Number of unchanged lines kept around a change when folding.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// Columns between tab stops when expanding tabs for display.
+pub const DEFAULT_TAB_WIDTH: usize = 8;
+
+/// Largest number of lines a held arrow key scrolls at once.
+pub const DEFAULT_MAX_SCROLL_STEP: usize = 8;
+
+/// Arrow key presses closer together than this count as the key being held down.
+const SCROLL_REPEAT_WINDOW: Duration = Duration::from_millis(150);
+
+/// Key repeats before the scroll step doubles, about a third of a second at common
+/// repeat rates.
+const REPEATS_PER_DOUBLING: usize = 10;
+
+/// Inputs at least this large (both sides together) are diffed on a background thread.
+pub const ASYNC_DIFF_THRESHOLD: usize = 1024 * 1024;
+
+/// How often the screen is redrawn while waiting for a background diff or for the status
+/// message to time out.
+const REDRAW_TICK: Duration = Duration::from_millis(100);
+/// File pairs kept for undoing file selections
+const MAX_HISTORY: usize = 20;
+
+/// Errors stay in the status bar this many times longer than other messages.
+const ERROR_TIMEOUT_FACTOR: u32 = 3;
+
+/// A diff being computed on a background thread. Dropping it cancels the computation.
+pub struct PendingDiff {
+    receiver: Receiver<Vec<DiffLine>>,
+    cancel: Arc<AtomicBool>,
+    pub started: Instant,
+}
+
+impl PendingDiff {
+    fn spawn(
+        source: String,
+        target: String,
+        algorithm: Algorithm,
+        ignore_whitespace: bool,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let thread_cancel = Arc::clone(&cancel);
+
+        thread::spawn(move || {
+            if let Some(diff_lines) = diff::generate_diff_cancellable(
+                &source,
+                &target,
+                algorithm,
+                ignore_whitespace,
+                &thread_cancel,
+            ) {
+                // Nobody listening anymore is fine, the result is simply dropped
+                let _ = sender.send(diff_lines);
+            }
+        });
+
+        PendingDiff {
+            receiver,
+            cancel,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for PendingDiff {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A patch file that exports are appended to until the series is stopped.
+pub struct PatchSeries {
+    pub filename: String,
+    pub hunks: usize,
+}
+
+/// What an export would write, shown in a popup without writing anything.
+pub struct ExportPreview {
+    pub path: String,
+    /// The export would be added to the running patch series
+    pub appends: bool,
+    pub total_lines: usize,
+    pub excerpt: Vec<String>,
+}
+
+/// Speeds up scrolling while an arrow key is held down. The step doubles every few key
+/// repeats up to `max_step`, and goes back to one line when the direction changes or the
+/// key is let go.
+#[derive(Debug)]
+pub struct ScrollAccel {
+    pub max_step: usize,
+    /// Direction (true for down) and time of the previous scroll
+    last: Option<(bool, Instant)>,
+    repeats: usize,
+}
+
+impl ScrollAccel {
+    pub fn new(max_step: usize) -> Self {
+        ScrollAccel {
+            max_step,
+            last: None,
+            repeats: 0,
+        }
+    }
+
+    /// Lines to scroll for an arrow key pressed at `now`.
+    pub fn step(&mut self, down: bool, now: Instant) -> usize {
+        let held = self.last.is_some_and(|(last_down, at)| {
+            last_down == down && now.duration_since(at) <= SCROLL_REPEAT_WINDOW
+        });
+        self.repeats = if held { self.repeats + 1 } else { 0 };
+        self.last = Some((down, now));
+
+        let doublings = (self.repeats / REPEATS_PER_DOUBLING).min(16);
+        (1 << doublings).min(self.max_step.max(1))
+    }
+}
+
+/// A source and target that were diffed before, so a file selection can be undone.
+struct FilePair {
+    source: DiffSource,
+    target: DiffSource,
+}
+
+/// The diff of a file pair that isn't the active tab, parked until it's switched to.
+#[derive(Default)]
+pub struct Tab {
+    pub source: DiffSource,
+    pub target: DiffSource,
+    source_stats: Option<FileStats>,
+    target_stats: Option<FileStats>,
+    diff_lines: Vec<DiffLine>,
+    expanded_folds: HashSet<usize>,
+    binary: bool,
+    scroll_offset: usize,
+    target_scroll_offset: usize,
+    cursor_position: usize,
+    line_endings: Option<(LineEnding, LineEnding)>,
+    normalize_line_endings: bool,
+    ignore_whitespace: bool,
+    pending_diff: Option<PendingDiff>,
+    external_output: Option<Vec<Line<'static>>>,
+    truncated: bool,
+    last_selection: Option<(usize, usize)>,
+    history: Vec<FilePair>,
+}
+
+/// Options that change how the files are read and diffed. The app gets them when it's
+/// created, so the first diff is the only one.
+#[derive(Debug, Default)]
+pub struct AppOptions {
+    pub algorithm: Algorithm,
+    pub source_range: Option<LineRange>,
+    pub target_range: Option<LineRange>,
+    pub max_lines: Option<usize>,
+    pub external_command: Option<String>,
+    /// Where the file browser remembers its directory, `None` to leave the disk alone
+    pub state_path: Option<PathBuf>,
+    pub read_only: bool,
 }
 
 pub struct App {
-    pub source_file: String,
-    pub target_file: String,
+    /// Where each side comes from, sources that can't be read again keep their content
+    pub source: DiffSource,
+    pub target: DiffSource,
+    /// Size and line count of each side, gathered while reading them
+    pub source_stats: Option<FileStats>,
+    pub target_stats: Option<FileStats>,
     pub diff_lines: Vec<DiffLine>,
+    pub rows: Vec<DiffRow>,
     pub scroll_offset: usize,
     pub cursor_position: usize,
     pub status_message: Option<String>,
@@ -27,46 +225,165 @@ pub struct App {
     pub file_browser: FileBrowser,
     pub selection_start: Option<usize>,
     pub selection_end: Option<usize>,
+    pub selection_pending: bool,
+    /// The selection when selection mode was last left, restored when it's entered again
+    last_selection: Option<(usize, usize)>,
+    /// File pairs replaced by picking another source or target, the latest last
+    history: Vec<FilePair>,
+    /// Reload the files once the frame saying they're being read has been drawn
+    pub reload_requested: bool,
+    pub binary: bool,
+    pub theme: Theme,
+    pub wrap_around: bool,
+    /// Wrap long lines, otherwise they're clipped at the edge of the view
+    pub wrap: bool,
+    /// Start the wrapped rows of a line past the gutter, so they hang under its content
+    pub wrap_indent: bool,
+    /// Cut diff lines to this many columns, so each takes up a single row
+    pub max_line_length: Option<usize>,
+    /// A cut line shown in full, until any key closes it
+    pub line_popup: Option<String>,
+    pub tab_width: usize,
+    pub show_tabs: bool,
+    /// Mark trailing spaces and tabs, display only
+    pub show_whitespace: bool,
+    pub side_by_side: bool,
+    /// Scroll both side-by-side panes together, otherwise only the active pane scrolls
+    pub sync_scroll: bool,
+    pub active_pane: Side,
+    pub target_scroll_offset: usize,
+    pub context: Context,
+    pub algorithm: Algorithm,
+    /// Highlight the changed characters within changed lines
+    pub granularity: Granularity,
+    pub patch_format: PatchFormat,
+    pub folding: bool,
+    pub expanded_folds: HashSet<usize>,
+    /// Hide unchanged lines that aren't close to a change
+    pub changes_only: bool,
+    /// Show the hunks of the diff with their context and dividers between them
+    pub hunks_only: bool,
+    /// Show a strip next to the diff with where the changes are in the whole file
+    pub show_minimap: bool,
+    /// Leave out the header, status bar and borders around the diff, e.g. for screenshots
+    pub hide_chrome: bool,
+    /// Draw plain ASCII in place of arrows and other symbols, see `Glyphs`
+    pub ascii: bool,
+    /// Highlight a current line in the diff view that the arrows move
+    pub show_cursor: bool,
+    /// Width of the terminal, wrapped lines take up more than one row of the view
+    pub view_width: u16,
+    pub show_help: bool,
+    pub help_scroll: usize,
+    pub export_preview: Option<ExportPreview>,
+    /// Set after a first `g` press, a second `g` jumps to the top
+    pub pending_g: bool,
+    pub patch_series: Option<PatchSeries>,
+    /// Clear status messages after this long instead of on the next key press only
+    pub status_timeout: Option<Duration>,
+    /// The status message being timed and when it first showed up
+    status_shown: Option<(String, Instant)>,
+    /// All open file pairs, empty with a single pair. The slot of the active tab is left
+    /// empty, its state lives in the fields above.
+    pub tabs: Vec<Tab>,
+    pub active_tab: usize,
+    /// Ask before quitting while a selection or patch series is in progress
+    pub confirm_quit: bool,
+    /// Refuse everything that writes files, opens an editor or uses the clipboard
+    pub read_only: bool,
+    /// Whether copies can fall back to OSC 52, i.e. stdout is a terminal
+    pub osc52: bool,
+    pub scroll_accel: ScrollAccel,
+    /// Whether "Quit? (y/n)" is waiting for an answer
+    pub quit_prompt: bool,
+    /// Only these lines of the source are diffed
+    pub source_range: Option<LineRange>,
+    /// Only these lines of the target are diffed
+    pub target_range: Option<LineRange>,
+    /// Only diff this many lines of each file, so a huge log doesn't hang the terminal
+    pub max_lines: Option<usize>,
+    /// Either file was cut off at `max_lines`
+    pub truncated: bool,
+    /// The source and target line endings, set only when they differ
+    pub line_endings: Option<(LineEnding, LineEnding)>,
+    /// Strip the `\r` of CRLF line endings before diffing
+    pub normalize_line_endings: bool,
+    /// Treat lines that only differ in whitespace as unchanged
+    pub ignore_whitespace: bool,
+    /// Where exported patches are written, the current directory when `None`
+    pub export_dir: Option<PathBuf>,
+    /// The line number typed after `:`, while the jump prompt is open
+    pub line_prompt: Option<String>,
+    /// The directory typed after `g` in the file browser, while that prompt is open
+    pub path_prompt: Option<String>,
+    /// The diff of large files while it's computed in the background
+    pub pending_diff: Option<PendingDiff>,
+    pub async_diff_threshold: usize,
+    /// Diff tool to run instead of the built-in diff, e.g. `difft`
+    pub external_command: Option<String>,
+    /// The lines printed by the external diff tool, with their ANSI colors parsed
+    pub external_output: Option<Vec<Line<'static>>>,
+    /// The changed files when comparing two directories
+    pub dir_summary: Option<DirSummary>,
+    /// Rows styled in earlier frames
+    pub row_cache: RefCell<ui::RowCache>,
 }
 
 impl App {
+    /// Creates the app for two files on disk.
+    #[cfg(test)]
     pub fn new(
         source_file: String,
         target_file: String,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let source_content = fs::read_to_string(&source_file)?;
-        let target_content = fs::read_to_string(&target_file)?;
-
-        let diff_lines = diff::generate_diff(&source_content, &target_content);
+        Self::with_sources(
+            DiffSource::File(PathBuf::from(source_file)),
+            DiffSource::File(PathBuf::from(target_file)),
+            AppOptions::default(),
+        )
+    }
 
-        // Try to initialize clipboard, but allow it to fail gracefully
-        let clipboard = Clipboard::new().ok();
-        let file_browser = FileBrowser::new()?;
+    /// Creates the app for any two sources, e.g. a git revision against a file or content
+    /// that only exists in memory.
+    pub fn with_sources(
+        source: DiffSource,
+        target: DiffSource,
+        options: AppOptions,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut app = Self::with_options(AppMode::DiffView, options)?;
+        app.source = source;
+        app.target = target;
+        app.status_message = None;
+        app.regenerate_diff()?;
 
-        Ok(App {
-            source_file,
-            target_file,
-            diff_lines,
-            scroll_offset: 0,
-            cursor_position: 0,
-            status_message: None,
-            clipboard,
-            mode: AppMode::DiffView,
-            file_browser,
-            selection_start: None,
-            selection_end: None,
-        })
+        Ok(app)
     }
 
+    #[cfg(test)]
     pub fn new_empty(initial_mode: AppMode) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_options(initial_mode, AppOptions::default())
+    }
+
+    /// Creates the app without files, the options apply to the first pair picked.
+    pub fn with_options(
+        initial_mode: AppMode,
+        options: AppOptions,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         // Try to initialize clipboard, but allow it to fail gracefully
-        let clipboard = Clipboard::new().ok();
-        let file_browser = FileBrowser::new()?;
+        // Read-only mode leaves the clipboard and the remembered directory alone
+        let clipboard = (!options.read_only)
+            .then(|| Clipboard::new().ok())
+            .flatten();
+        let state_path = options.state_path.filter(|_| !options.read_only);
+        let file_browser = FileBrowser::new(state_path)?;
 
         Ok(App {
-            source_file: String::new(),
-            target_file: String::new(),
+            source: DiffSource::default(),
+            target: DiffSource::default(),
+            source_stats: None,
+            target_stats: None,
             diff_lines: Vec::new(),
+            rows: Vec::new(),
             scroll_offset: 0,
             cursor_position: 0,
             status_message: Some("Please select a file".to_string()),
@@ -75,492 +392,3391 @@ impl App {
             file_browser,
             selection_start: None,
             selection_end: None,
+            selection_pending: false,
+            last_selection: None,
+            history: Vec::new(),
+            reload_requested: false,
+            binary: false,
+            theme: Theme::default(),
+            wrap_around: false,
+            wrap: true,
+            wrap_indent: false,
+            max_line_length: None,
+            line_popup: None,
+            tab_width: DEFAULT_TAB_WIDTH,
+            show_tabs: false,
+            show_whitespace: false,
+            side_by_side: false,
+            sync_scroll: true,
+            active_pane: Side::Source,
+            target_scroll_offset: 0,
+            context: Context::symmetric(DEFAULT_CONTEXT),
+            algorithm: options.algorithm,
+            granularity: Granularity::Line,
+            patch_format: PatchFormat::Plain,
+            folding: false,
+            expanded_folds: HashSet::new(),
+            changes_only: false,
+            hunks_only: false,
+            show_minimap: false,
+            hide_chrome: false,
+            ascii: false,
+            show_cursor: false,
+            view_width: u16::MAX,
+            show_help: false,
+            export_preview: None,
+            help_scroll: 0,
+            pending_g: false,
+            patch_series: None,
+            status_timeout: None,
+            status_shown: None,
+            tabs: Vec::new(),
+            active_tab: 0,
+            confirm_quit: false,
+            read_only: options.read_only,
+            osc52: false,
+            scroll_accel: ScrollAccel::new(DEFAULT_MAX_SCROLL_STEP),
+            quit_prompt: false,
+            source_range: options.source_range,
+            target_range: options.target_range,
+            max_lines: options.max_lines,
+            truncated: false,
+            line_endings: None,
+            normalize_line_endings: false,
+            ignore_whitespace: false,
+            export_dir: None,
+            line_prompt: None,
+            path_prompt: None,
+            pending_diff: None,
+            async_diff_threshold: ASYNC_DIFF_THRESHOLD,
+            external_command: options.external_command,
+            external_output: None,
+            dir_summary: None,
+            row_cache: RefCell::default(),
         })
     }
 
     pub fn regenerate_diff(&mut self) -> Result<(), io::Error> {
-        let source_content = fs::read_to_string(&self.source_file)?;
-        let target_content = fs::read_to_string(&self.target_file)?;
+        // Reading stops a line past `max_lines`, which is enough to tell a file was cut off
+        let read_limit = |range: Option<LineRange>| {
+            self.max_lines
+                .map(|max_lines| range.map_or(0, |range| range.start - 1) + max_lines + 1)
+        };
+        let (source_limit, target_limit) =
+            (read_limit(self.source_range), read_limit(self.target_range));
+        // Only files are read again, anything else was kept in memory the first time
+        let (mut source, source_cut) = self
+            .source
+            .load_head(source_limit)
+            .map_err(io::Error::other)?;
+        let (mut target, target_cut) = self
+            .target
+            .load_head(target_limit)
+            .map_err(io::Error::other)?;
+
+        if let Some(range) = self.source_range {
+            source.text = diff::slice_lines(&source.text, range);
+        }
+        if let Some(range) = self.target_range {
+            target.text = diff::slice_lines(&target.text, range);
+        }
+
+        self.truncated = source_cut || target_cut;
+        if let Some(max_lines) = self.max_lines {
+            for content in [&mut source, &mut target] {
+                if content.text.split_inclusive('\n').nth(max_lines).is_some() {
+                    let range = LineRange {
+                        start: 1,
+                        end: max_lines,
+                    };
+                    content.text = diff::slice_lines(&content.text, range);
+                    self.truncated = true;
+                }
+            }
+        }
+
+        self.line_endings = match (
+            LineEnding::detect(&source.text),
+            LineEnding::detect(&target.text),
+        ) {
+            (Some(source), Some(target)) if source != target => Some((source, target)),
+            _ => None,
+        };
+        if self.normalize_line_endings {
+            source.text = diff::normalize_line_endings(&source.text);
+            target.text = diff::normalize_line_endings(&target.text);
+        }
+
+        self.source_stats = Some(source.stats);
+        self.target_stats = Some(target.stats);
+
+        if let Some(command) = &self.external_command {
+            let output = external::run_sources(command, &mut self.source, &mut self.target)
+                .map_err(io::Error::other)?;
+            self.external_output = Some(external::parse_ansi(&output));
+            self.diff_lines.clear();
+            self.rebuild_rows();
+            self.scroll_offset = 0;
+            self.cursor_position = 0;
+            return Ok(());
+        }
 
-        self.diff_lines = diff::generate_diff(&source_content, &target_content);
+        // Binary files are diffed as hex dumps. A text file compared against a binary one is
+        // dumped too so the rows line up, invalid UTF-8 in it has been replaced by then
+        self.binary = source.binary || target.binary;
+        if self.binary {
+            if !source.binary {
+                source.text = diff::hex_dump(source.text.as_bytes());
+            }
+            if !target.binary {
+                target.text = diff::hex_dump(target.text.as_bytes());
+            }
+        }
+        self.pending_diff = None;
+        self.diff_lines = if source.stats.bytes + target.stats.bytes >= self.async_diff_threshold {
+            // Large files would freeze the UI, the result is picked up by poll_pending_diff
+            self.pending_diff = Some(PendingDiff::spawn(
+                source.text,
+                target.text,
+                self.algorithm,
+                self.ignore_whitespace,
+            ));
+            Vec::new()
+        } else {
+            let mut diff_lines = if self.ignore_whitespace {
+                diff::generate_diff_ignoring_whitespace(&source.text, &target.text, self.algorithm)
+            } else {
+                diff::generate_diff(&source.text, &target.text, self.algorithm)
+            };
+            self.offset_target_lines(&mut diff_lines);
+            diff_lines
+        };
+        self.expanded_folds.clear();
+        // The old line numbers don't apply to the new diff
+        self.last_selection = None;
+        self.rebuild_rows();
         self.scroll_offset = 0;
+        self.target_scroll_offset = 0;
+        self.cursor_position = 0;
+
+        let lossy_files = match (source.lossy, target.lossy) {
+            (true, true) => Some("Source and target files are"),
+            (true, false) => Some("Source file is"),
+            (false, true) => Some("Target file is"),
+            (false, false) => None,
+        };
+        if let Some(files) = lossy_files {
+            self.status_message = Some(format!(
+                "Warning: {} not valid UTF-8, invalid bytes are shown as \u{FFFD}",
+                files
+            ));
+        }
+
         Ok(())
     }
 
-    pub fn scroll_up(&mut self) {
-        if self.scroll_offset > 0 {
-            self.scroll_offset -= 1;
+    /// Makes line numbers of a diff over a target range count from the top of the file.
+    fn offset_target_lines(&self, diff_lines: &mut [DiffLine]) {
+        let Some(range) = self.target_range else {
+            return;
+        };
+
+        for line in diff_lines.iter_mut() {
+            line.new_line = line.new_line.map(|number| number + range.start - 1);
         }
     }
 
-    pub fn scroll_down(&mut self, max_visible_lines: usize) {
-        if self.scroll_offset + max_visible_lines < self.diff_lines.len() {
-            self.scroll_offset += 1;
+    /// Installs the background diff once it's ready. Returns true when it was installed.
+    pub fn poll_pending_diff(&mut self) -> bool {
+        let Some(pending) = &self.pending_diff else {
+            return false;
+        };
+
+        match pending.receiver.try_recv() {
+            Ok(mut diff_lines) => {
+                self.pending_diff = None;
+                self.offset_target_lines(&mut diff_lines);
+                self.diff_lines = diff_lines;
+                self.rebuild_rows();
+                true
+            }
+            Err(TryRecvError::Empty) => false,
+            Err(TryRecvError::Disconnected) => {
+                self.pending_diff = None;
+                self.status_message = Some("Failed to compute the diff".to_string());
+                false
+            }
         }
     }
 
-    fn generate_patch(&self) -> String {
-        let line_range = self.get_selection_range();
-        diff::generate_patch(
-            &self.source_file,
-            &self.target_file,
-            &self.diff_lines,
-            line_range,
-        )
+    /// Recomputes the view rows from the diff lines and the current fold state.
+    pub fn rebuild_rows(&mut self) {
+        self.row_cache.get_mut().clear();
+        self.rows = if let Some(output) = &self.external_output {
+            // External output is shown line by line as it is
+            (0..output.len()).map(DiffRow::Line).collect()
+        } else if self.side_by_side {
+            diff::side_by_side_rows(&self.diff_lines)
+        } else if self.changes_only {
+            diff::changes_only_rows(&self.diff_lines, CHANGES_ONLY_CONTEXT)
+        } else if self.hunks_only {
+            diff::hunk_rows(&self.diff_lines, self.context)
+        } else if self.folding {
+            diff::fold_rows(&self.diff_lines, self.context, &self.expanded_folds)
+        } else {
+            diff::unfolded_rows(&self.diff_lines)
+        };
     }
 
-    pub fn copy_to_clipboard(&mut self) -> Result<(), String> {
-        let patch = self.generate_patch();
-        match &mut self.clipboard {
-            Some(clipboard) => diff::copy_to_clipboard(clipboard, &patch),
-            None => Err("Clipboard not available in this environment".to_string()),
-        }
+    /// Index of the row that shows the given diff line.
+    fn row_for_line(&self, line: usize) -> usize {
+        self.rows
+            .iter()
+            .position(|row| row.contains(line))
+            .unwrap_or(0)
     }
 
-    pub fn export_to_file(&self) -> Result<String, String> {
-        let patch = self.generate_patch();
-        diff::export_to_file(&patch)
+    /// Index of the row that shows the given diff line, or the next shown line when the
+    /// line itself is hidden.
+    fn nearest_row_for_line(&self, line: usize) -> usize {
+        self.rows
+            .iter()
+            .position(|row| row.line_range().1 >= line)
+            .unwrap_or(self.rows.len().saturating_sub(1))
     }
 
-    pub fn enter_selection_mode(&mut self) {
-        self.mode = AppMode::SelectionMode;
-        self.cursor_position = self.scroll_offset;
-        self.selection_start = None;
-        self.selection_end = None;
-        self.status_message =
-            Some("SELECTION MODE - Press Space to mark start/end, v to exit".to_string());
+    /// The first diff line shown by the given row.
+    fn first_line_of_row(&self, row: usize) -> usize {
+        self.rows.get(row).map(|r| r.line_range().0).unwrap_or(0)
     }
 
-    pub fn exit_selection_mode(&mut self) {
-        self.mode = AppMode::DiffView;
-        self.selection_start = None;
-        self.selection_end = None;
-        self.status_message = Some("Selection mode exited".to_string());
+    pub fn toggle_minimap(&mut self) {
+        self.show_minimap = !self.show_minimap;
+        self.status_message = Some(format!(
+            "Minimap {}",
+            if self.show_minimap { "on" } else { "off" }
+        ));
     }
 
-    pub fn toggle_selection_anchor(&mut self) {
-        if self.selection_start.is_none() {
-            // Set the start of selection at current cursor position
-            self.selection_start = Some(self.cursor_position);
-            self.selection_end = Some(self.cursor_position);
-            self.status_message = Some(format!("Selection start: line {}", self.cursor_position));
-        } else {
-            // Finalize the selection
-            if let Some(start) = self.selection_start {
-                self.status_message = Some(format!(
-                    "Selection: lines {}-{} ({} lines selected)",
-                    start.min(self.cursor_position),
-                    start.max(self.cursor_position),
-                    (start as i32 - self.cursor_position as i32).abs() + 1
-                ));
-            }
+    pub fn glyphs(&self) -> &'static Glyphs {
+        Glyphs::get(self.ascii)
+    }
+
+    pub fn toggle_chrome(&mut self) {
+        self.hide_chrome = !self.hide_chrome;
+    }
+
+    /// Whether the diff takes up the whole screen. The file browser and directory summary
+    /// always keep their chrome, they're hard to use without it, and so does the
+    /// go-to-line prompt which lives in the status bar.
+    pub fn chrome_hidden(&self) -> bool {
+        self.hide_chrome
+            && self.line_prompt.is_none()
+            && matches!(self.mode, AppMode::DiffView | AppMode::SelectionMode)
+    }
+
+    pub fn toggle_wrap(&mut self) {
+        self.wrap = !self.wrap;
+        self.status_message = Some(format!(
+            "Line wrapping {}",
+            if self.wrap { "on" } else { "off" }
+        ));
+    }
+
+    pub fn toggle_whitespace(&mut self) {
+        self.show_whitespace = !self.show_whitespace;
+        self.status_message = Some(format!(
+            "Whitespace markers {}",
+            if self.show_whitespace { "on" } else { "off" }
+        ));
+    }
+
+    pub fn toggle_side_by_side(&mut self) {
+        let top_line = self.first_line_of_row(self.scroll_offset);
+
+        self.side_by_side = !self.side_by_side;
+        // The side-by-side view shows every line, folds don't line up across the panes
+        self.folding = false;
+        self.changes_only = false;
+        self.hunks_only = false;
+        self.sync_scroll = true;
+        self.active_pane = Side::Source;
+        self.rebuild_rows();
+
+        self.scroll_offset = self.row_for_line(top_line);
+        self.cursor_position = self.scroll_offset;
+    }
+
+    /// Locks or unlocks the scrolling of the two side-by-side panes.
+    pub fn toggle_sync_scroll(&mut self) {
+        if !self.side_by_side {
+            return;
         }
+
+        self.sync_scroll = !self.sync_scroll;
+        self.target_scroll_offset = self.scroll_offset;
+        self.status_message = Some(if self.sync_scroll {
+            "Panes scroll together".to_string()
+        } else {
+            "Panes scroll independently - Tab switches the active pane".to_string()
+        });
     }
 
-    pub fn update_selection_end(&mut self) {
-        if self.selection_start.is_some() {
-            self.selection_end = Some(self.cursor_position);
+    /// Rows of the diff or the file list that fit on a terminal of the given size.
+    ///
+    /// Scrolling, cursor clamping and the browser all use this, so they agree with what
+    /// the UI draws.
+    pub fn visible_height(&self, terminal_area: Rect) -> usize {
+        ui::content_height(self, terminal_area)
+    }
+
+    /// Opens another file pair in a new tab and switches to it.
+    pub fn open_tab(&mut self, source_file: String, target_file: String) -> Result<(), io::Error> {
+        if self.tabs.is_empty() {
+            // The slot for the pair that's open now
+            self.tabs.push(Tab::default());
         }
+        self.tabs.push(Tab {
+            source: DiffSource::File(PathBuf::from(source_file)),
+            target: DiffSource::File(PathBuf::from(target_file)),
+            ..Tab::default()
+        });
+
+        self.switch_tab(self.tabs.len() - 1);
+        self.regenerate_diff()
     }
 
-    pub fn cursor_up(&mut self) {
-        if self.cursor_position > 0 {
-            self.cursor_position -= 1;
-            // Scroll up if cursor moves above visible area
-            if self.cursor_position < self.scroll_offset {
-                self.scroll_offset = self.cursor_position;
-            }
+    pub fn tab_count(&self) -> usize {
+        self.tabs.len().max(1)
+    }
+
+    pub fn next_tab(&mut self) {
+        if self.tab_count() > 1 {
+            self.switch_tab((self.active_tab + 1) % self.tab_count());
         }
     }
 
-    pub fn cursor_down(&mut self, max_visible_lines: usize) {
-        if self.cursor_position + 1 < self.diff_lines.len() {
-            self.cursor_position += 1;
-            // Scroll down if cursor moves below visible area
-            if self.cursor_position >= self.scroll_offset + max_visible_lines {
-                self.scroll_offset = self.cursor_position - max_visible_lines + 1;
-            }
+    pub fn previous_tab(&mut self) {
+        if self.tab_count() > 1 {
+            self.switch_tab((self.active_tab + self.tab_count() - 1) % self.tab_count());
         }
     }
 
-    pub fn get_selection_range(&self) -> Option<(usize, usize)> {
-        match (self.selection_start, self.selection_end) {
-            (Some(start), Some(end)) => {
-                let min = start.min(end);
-                let max = start.max(end);
-                Some((min, max))
-            }
-            _ => None,
+    pub fn switch_tab(&mut self, index: usize) {
+        if index == self.active_tab || index >= self.tabs.len() {
+            return;
         }
+
+        self.swap_tab(self.active_tab);
+        self.active_tab = index;
+        self.swap_tab(index);
+
+        // Folding and the side-by-side view are shared by all tabs, so lay out the rows again
+        self.rebuild_rows();
+        let last_row = self.rows.len().saturating_sub(1);
+        self.scroll_offset = self.scroll_offset.min(last_row);
+        self.target_scroll_offset = self.target_scroll_offset.min(last_row);
+        self.cursor_position = self.cursor_position.min(last_row);
     }
-}
 
-fn handle_file_selection(app: &mut App) {
-    match app.file_browser.enter_selected() {
-        Ok(Some(selected_file)) => {
-            // File was selected
-            if let Some(file_path) = selected_file.to_str() {
-                if app.mode == AppMode::SelectingSource {
-                    app.source_file = file_path.to_string();
+    /// Exchanges the diff state of the app with the tab in the given slot.
+    fn swap_tab(&mut self, index: usize) {
+        let tab = &mut self.tabs[index];
+        mem::swap(&mut self.source, &mut tab.source);
+        mem::swap(&mut self.target, &mut tab.target);
+        mem::swap(&mut self.source_stats, &mut tab.source_stats);
+        mem::swap(&mut self.target_stats, &mut tab.target_stats);
+        mem::swap(&mut self.diff_lines, &mut tab.diff_lines);
+        mem::swap(&mut self.expanded_folds, &mut tab.expanded_folds);
+        mem::swap(&mut self.binary, &mut tab.binary);
+        mem::swap(&mut self.scroll_offset, &mut tab.scroll_offset);
+        mem::swap(
+            &mut self.target_scroll_offset,
+            &mut tab.target_scroll_offset,
+        );
+        mem::swap(&mut self.cursor_position, &mut tab.cursor_position);
+        mem::swap(&mut self.line_endings, &mut tab.line_endings);
+        mem::swap(
+            &mut self.normalize_line_endings,
+            &mut tab.normalize_line_endings,
+        );
+        mem::swap(&mut self.ignore_whitespace, &mut tab.ignore_whitespace);
+        mem::swap(&mut self.pending_diff, &mut tab.pending_diff);
+        mem::swap(&mut self.external_output, &mut tab.external_output);
+        mem::swap(&mut self.truncated, &mut tab.truncated);
+        mem::swap(&mut self.last_selection, &mut tab.last_selection);
+        mem::swap(&mut self.history, &mut tab.history);
+    }
 
-                    // If target is not set, move to selecting target
-                    if app.target_file.is_empty() {
-                        app.mode = AppMode::SelectingTarget;
-                        app.status_message =
-                            Some(format!("Source: {} - Now select target file", file_path));
-                        let _ = app.file_browser.load_entries();
-                    } else {
-                        // Both files are set, regenerate diff
-                        if let Err(e) = app.regenerate_diff() {
-                            app.status_message = Some(format!("Error loading files: {}", e));
-                        } else {
-                            app.status_message =
-                                Some(format!("Source file updated: {}", file_path));
-                        }
-                        app.mode = AppMode::DiffView;
-                    }
-                } else {
-                    app.target_file = file_path.to_string();
+    /// The source and target file of every tab, in order.
+    pub fn tab_files(&self) -> Vec<(String, String)> {
+        if self.tabs.is_empty() {
+            return vec![(self.source.label(), self.target.label())];
+        }
 
-                    // If source is not set, move to selecting source
-                    if app.source_file.is_empty() {
-                        app.mode = AppMode::SelectingSource;
-                        app.status_message =
-                            Some(format!("Target: {} - Now select source file", file_path));
-                        let _ = app.file_browser.load_entries();
-                    } else {
-                        // Both files are set, regenerate diff
-                        if let Err(e) = app.regenerate_diff() {
-                            app.status_message = Some(format!("Error loading files: {}", e));
-                        } else {
-                            app.status_message =
-                                Some(format!("Target file updated: {}", file_path));
-                        }
-                        app.mode = AppMode::DiffView;
-                    }
+        self.tabs
+            .iter()
+            .enumerate()
+            .map(|(index, tab)| {
+                if index == self.active_tab {
+                    (self.source.label(), self.target.label())
+                } else {
+                    (tab.source.label(), tab.target.label())
                 }
-            }
-        }
-        Ok(None) => {
-            // Directory was entered, nothing to do
+            })
+            .collect()
+    }
+
+    /// Makes the other side-by-side pane the one that scrolls, when they're not synced.
+    pub fn switch_active_pane(&mut self) {
+        if self.side_by_side && !self.sync_scroll {
+            self.active_pane = match self.active_pane {
+                Side::Source => Side::Target,
+                Side::Target => Side::Source,
+            };
         }
-        Err(e) => {
-            app.status_message = Some(format!("Error: {}", e));
+    }
+
+    /// Scroll offset of the target pane in the side-by-side view.
+    pub fn target_pane_offset(&self) -> usize {
+        if self.sync_scroll {
+            self.scroll_offset
+        } else {
+            self.target_scroll_offset
         }
     }
-}
 
-fn handle_browser_input<B: ratatui::backend::Backend>(
-    app: &mut App,
-    key_code: KeyCode,
-    terminal: &Terminal<B>,
-) -> io::Result<bool> {
-    let content_height = terminal.size()?.height.saturating_sub(8) as usize;
+    /// The offset that scrolling moves, the target pane has its own when scrolled on its own.
+    fn scroll_offset_mut(&mut self) -> &mut usize {
+        if self.side_by_side && !self.sync_scroll && self.active_pane == Side::Target {
+            &mut self.target_scroll_offset
+        } else {
+            &mut self.scroll_offset
+        }
+    }
 
-    match key_code {
-        KeyCode::Up => {
-            app.file_browser.move_up();
+    pub fn toggle_folding(&mut self) {
+        if self.side_by_side {
+            self.status_message =
+                Some("Folding is not available in the side-by-side view".to_string());
+            return;
         }
-        KeyCode::Down => {
-            app.file_browser.move_down();
-            app.file_browser.update_scroll(content_height);
+
+        let top_line = self.first_line_of_row(self.scroll_offset);
+        let cursor_line = self.first_line_of_row(self.cursor_position);
+
+        self.folding = !self.folding;
+        self.rebuild_rows();
+
+        // Keep the same lines in view across the toggle
+        self.scroll_offset = self.row_for_line(top_line);
+        self.cursor_position = if self.folding && !self.show_cursor {
+            self.scroll_offset
+        } else {
+            self.row_for_line(cursor_line)
+        };
+
+        self.status_message = Some(if self.folding {
+            "Unchanged regions folded - Enter expands a fold".to_string()
+        } else {
+            "Folding disabled".to_string()
+        });
+    }
+
+    /// Whether the arrows move a cursor through the diff view instead of scrolling it. The
+    /// folded view always has one so folds can be expanded with Enter.
+    pub fn cursor_driven(&self) -> bool {
+        !self.side_by_side && (self.folding || self.show_cursor)
+    }
+
+    /// The target line under the cursor, or at the top of the view without one. Deleted
+    /// lines give the target line that follows them.
+    pub fn current_target_line(&self) -> Option<usize> {
+        let row = if self.cursor_driven() {
+            self.cursor_position
+        } else {
+            self.scroll_offset
+        };
+        let (first, _) = self.rows.get(row)?.line_range();
+        self.diff_lines
+            .get(first..)?
+            .iter()
+            .find_map(|line| line.new_line)
+    }
+
+    /// Shows or hides the cursor line, which starts in the middle of the view.
+    pub fn toggle_cursor(&mut self, max_visible_lines: usize) {
+        if self.side_by_side {
+            self.status_message =
+                Some("The cursor is not available in the side-by-side view".to_string());
+            return;
         }
-        KeyCode::Enter => {
-            handle_file_selection(app);
+
+        self.show_cursor = !self.show_cursor;
+        if self.show_cursor && !self.folding {
+            let last_row = self.rows.len().saturating_sub(1);
+            self.cursor_position = (self.scroll_offset + max_visible_lines / 2).min(last_row);
         }
-        KeyCode::Esc | KeyCode::Char('q') => {
-            // Only return to diff view if both files are set
-            if !app.source_file.is_empty() && !app.target_file.is_empty() {
-                app.mode = AppMode::DiffView;
+        self.status_message = Some(format!(
+            "Cursor line {}",
+            if self.show_cursor { "on" } else { "off" }
+        ));
+    }
+
+    /// Opens the diff of the file selected in the directory summary. Added and removed
+    /// files are compared against an empty file.
+    pub fn open_selected_change(&mut self) -> Result<(), io::Error> {
+        let Some(summary) = &self.dir_summary else {
+            return Ok(());
+        };
+        let Some(change) = summary.selected() else {
+            return Ok(());
+        };
+
+        let side = |dir: &Path, missing: bool| {
+            let path = dir.join(&change.path);
+            if missing {
+                DiffSource::Memory {
+                    label: path.display().to_string(),
+                    content: diff::decode_content(b""),
+                }
             } else {
-                // Exit the application if files aren't set
-                return Ok(true);
+                DiffSource::File(path)
             }
+        };
+        self.source = side(&summary.source_dir, change.kind == ChangeKind::Added);
+        self.target = side(&summary.target_dir, change.kind == ChangeKind::Removed);
+        self.mode = AppMode::DiffView;
+        self.regenerate_diff()
+    }
+
+    /// Switches between showing every line and only the changes with a little context.
+    pub fn toggle_changes_only(&mut self) {
+        if self.side_by_side {
+            self.status_message =
+                Some("Changes only is not available in the side-by-side view".to_string());
+            return;
         }
-        _ => {}
+
+        self.changes_only = !self.changes_only;
+        self.hunks_only = false;
+        self.rebuild_rows_in_place();
+
+        self.status_message = Some(if self.changes_only {
+            "Showing changes only".to_string()
+        } else {
+            "Showing all lines".to_string()
+        });
     }
 
-    Ok(false)
-}
+    /// Switches between showing every line and only the hunks, with a divider for the
+    /// lines skipped between them.
+    pub fn toggle_hunks_only(&mut self) {
+        if self.side_by_side {
+            self.status_message =
+                Some("The hunk view is not available in the side-by-side view".to_string());
+            return;
+        }
 
-fn handle_diffview_input<B: ratatui::backend::Backend>(
-    app: &mut App,
-    key_code: KeyCode,
-    terminal: &Terminal<B>,
-) -> io::Result<bool> {
-    match key_code {
-        KeyCode::Char('q') => return Ok(true),
-        KeyCode::Char('s') => {
-            app.mode = AppMode::SelectingSource;
-            let _ = app.file_browser.load_entries();
+        self.hunks_only = !self.hunks_only;
+        self.changes_only = false;
+        self.rebuild_rows_in_place();
+
+        self.status_message = Some(if self.hunks_only {
+            "Showing changed hunks".to_string()
+        } else {
+            "Showing all lines".to_string()
+        });
+    }
+
+    /// Rebuilds the rows after switching views, keeping the view near the same lines.
+    fn rebuild_rows_in_place(&mut self) {
+        let top_line = self.first_line_of_row(self.scroll_offset);
+        let cursor_line = self.first_line_of_row(self.cursor_position);
+        self.rebuild_rows();
+
+        // Hidden lines map to the next line still shown, so the view stays near the same spot
+        self.scroll_offset = self.nearest_row_for_line(top_line);
+        self.cursor_position = self.nearest_row_for_line(cursor_line);
+        if !self.selectable(self.cursor_position)
+            && let Some(row) =
+                (self.cursor_position..self.rows.len()).find(|&row| self.selectable(row))
+        {
+            self.cursor_position = row;
         }
-        KeyCode::Char('t') => {
-            app.mode = AppMode::SelectingTarget;
-            let _ = app.file_browser.load_entries();
+    }
+
+    /// Whether the cursor can rest on a row. The dividers of the hunk view are passed over.
+    fn selectable(&self, row: usize) -> bool {
+        !matches!(self.rows.get(row), Some(DiffRow::Skip { .. }))
+    }
+
+    /// Expands the fold under the cursor, returning whether there was one.
+    pub fn expand_fold_at_cursor(&mut self) -> bool {
+        let Some(DiffRow::Fold { start, end }) = self.rows.get(self.cursor_position).copied()
+        else {
+            return false;
+        };
+
+        self.expanded_folds.insert(start);
+        self.rebuild_rows();
+        self.status_message = Some(format!("Expanded {} unchanged lines", end - start + 1));
+        true
+    }
+
+    /// Shows the line under the cursor in full when `max_line_length` cut it.
+    pub fn show_full_line_at_cursor(&mut self) {
+        let Some(max_line_length) = self.max_line_length else {
+            return;
+        };
+        if !self.cursor_driven() {
+            self.status_message = Some("Press i to show the cursor first".to_string());
+            return;
         }
-        KeyCode::Char('v') => {
-            app.enter_selection_mode();
+        if let Some(&DiffRow::Line(idx)) = self.rows.get(self.cursor_position) {
+            let content = &self.diff_lines[idx].content;
+            if ui::is_cut(content, max_line_length, self.tab_width) {
+                self.line_popup = Some(content.clone());
+            }
         }
-        KeyCode::Char('c') => match app.copy_to_clipboard() {
-            Ok(_) => {
-                app.status_message = Some("Diff copied to clipboard!".to_string());
+    }
+
+    pub fn scroll_up(&mut self, max_visible_lines: usize) {
+        let max_offset = self.max_scroll_offset(max_visible_lines);
+        let wrap_around = self.wrap_around;
+        let offset = self.scroll_offset_mut();
+
+        if *offset > 0 {
+            *offset -= 1;
+        } else if wrap_around {
+            *offset = max_offset;
+        }
+    }
+
+    pub fn scroll_down(&mut self, max_visible_lines: usize) {
+        let max_offset = self.max_scroll_offset(max_visible_lines);
+        let wrap_around = self.wrap_around;
+        let offset = self.scroll_offset_mut();
+
+        if *offset < max_offset {
+            *offset += 1;
+        } else if wrap_around {
+            *offset = 0;
+        }
+    }
+
+    pub fn jump_to_top(&mut self) {
+        *self.scroll_offset_mut() = 0;
+        self.cursor_position = 0;
+    }
+
+    pub fn jump_to_bottom(&mut self, max_visible_lines: usize) {
+        *self.scroll_offset_mut() = self.max_scroll_offset(max_visible_lines);
+        self.cursor_position = self.rows.len().saturating_sub(1);
+    }
+
+    /// Moves the cursor and view to line `number` of the target file.
+    ///
+    /// Falls back to diff line positions when the target has no lines, numbers outside the
+    /// file are clamped. Folded lines are unfolded so the line is actually visible, a line
+    /// skipped by the hunk view switches back to showing all lines.
+    pub fn jump_to_line(&mut self, number: usize, max_visible_lines: usize) {
+        let last_target_line = self.diff_lines.iter().rev().find_map(|line| line.new_line);
+
+        let (line, clamped) = match last_target_line {
+            Some(last) => {
+                let clamped = number.clamp(1, last);
+                let line = self
+                    .diff_lines
+                    .iter()
+                    .position(|line| line.new_line == Some(clamped))
+                    .unwrap_or(0);
+                (line, clamped)
             }
-            Err(e) => {
-                app.status_message = Some(format!("Error: {}", e));
+            None if self.diff_lines.is_empty() => return,
+            None => {
+                let clamped = number.clamp(1, self.diff_lines.len());
+                (clamped - 1, clamped)
             }
-        },
-        KeyCode::Char('e') => match app.export_to_file() {
-            Ok(filename) => {
-                app.status_message = Some(format!("Diff exported to {}", filename));
+        };
+
+        if clamped != number {
+            self.status_message = Some(format!(
+                "Line {} is out of range, jumped to line {}",
+                number, clamped
+            ));
+        }
+
+        match self.rows.get(self.row_for_line(line)) {
+            Some(&DiffRow::Fold { start, .. }) => {
+                self.expanded_folds.insert(start);
+                self.rebuild_rows();
             }
-            Err(e) => {
-                app.status_message = Some(format!("Error: {}", e));
+            Some(DiffRow::Skip { .. }) => {
+                self.hunks_only = false;
+                self.rebuild_rows();
+                self.status_message = Some(format!(
+                    "Line {} is outside the hunks, showing all lines",
+                    clamped
+                ));
             }
-        },
-        KeyCode::Up => {
-            app.scroll_up();
+            _ => {}
         }
-        KeyCode::Down => {
-            let content_height = terminal.size()?.height.saturating_sub(8) as usize;
-            app.scroll_down(content_height);
+
+        let row = self.row_for_line(line);
+        self.cursor_position = row;
+        self.scroll_offset = row.min(self.max_scroll_offset(max_visible_lines));
+    }
+
+    /// True when there is nothing to see: no changes between two (possibly empty) text files.
+    pub fn is_identical(&self) -> bool {
+        if let Some(output) = &self.external_output {
+            return output.is_empty();
         }
-        _ => {}
+        self.pending_diff.is_none() && diff::is_identical(&self.diff_lines)
     }
 
-    Ok(false)
-}
+    /// Whether any of the open file pairs differ. A diff that is still being computed counts
+    /// as a difference, it can't be shown to be identical yet.
+    pub fn has_changes(&self) -> bool {
+        !self.is_identical()
+            || self.tabs.iter().any(|tab| {
+                tab.pending_diff.is_some()
+                    || !diff::is_identical(&tab.diff_lines)
+                    || tab.external_output.as_ref().is_some_and(|o| !o.is_empty())
+            })
+            || self
+                .dir_summary
+                .as_ref()
+                .is_some_and(|summary| !summary.changes.is_empty())
+    }
 
-fn handle_selection_input<B: ratatui::backend::Backend>(
-    app: &mut App,
-    key_code: KeyCode,
-    terminal: &Terminal<B>,
-) -> io::Result<bool> {
-    match key_code {
-        KeyCode::Char('q') => return Ok(true),
-        KeyCode::Char('v') => {
-            app.exit_selection_mode();
+    /// The offset at which the last row sits at the bottom of the viewport.
+    fn max_scroll_offset(&self, max_visible_lines: usize) -> usize {
+        let mut height = 0;
+        for (idx, row) in self.rows.iter().enumerate().rev() {
+            height += ui::row_height(self, row, self.view_width);
+            if height > max_visible_lines {
+                // A row taller than the view still gets to be at the top
+                return (idx + 1).min(self.rows.len() - 1);
+            }
         }
-        KeyCode::Char(' ') => {
-            app.toggle_selection_anchor();
+        0
+    }
+
+    /// Display rows taken up by the view rows from `start` to `end`, inclusive.
+    fn rows_height(&self, start: usize, end: usize) -> usize {
+        self.rows[start..=end]
+            .iter()
+            .map(|row| ui::row_height(self, row, self.view_width))
+            .sum()
+    }
+
+    fn generate_patch(&self) -> String {
+        // Copies and exports of an external diff are whatever the tool printed, minus colors
+        if let Some(output) = &self.external_output {
+            return output.iter().map(|line| format!("{}\n", line)).collect();
         }
-        KeyCode::Char('c') => {
-            if app.get_selection_range().is_some() {
-                match app.copy_to_clipboard() {
-                    Ok(_) => {
-                        app.status_message = Some("Selection copied to clipboard!".to_string());
-                    }
-                    Err(e) => {
-                        app.status_message = Some(format!("Error: {}", e));
-                    }
+
+        let line_range = self.get_selection_range();
+        let patch = diff::generate_patch(
+            &self.source.label(),
+            &self.target.label(),
+            &self.diff_lines,
+            line_range,
+            self.patch_format,
+            self.context,
+        );
+
+        // Patch tools skip text before the headers, so the note doesn't get in the way
+        if self.normalize_line_endings && self.line_endings.is_some() {
+            format!(
+                "Line endings were normalized to LF before diffing.\n{}",
+                patch
+            )
+        } else {
+            patch
+        }
+    }
+
+    /// Switches between diffing the raw line endings and normalizing CRLF to LF.
+    pub fn toggle_line_endings(&mut self) {
+        self.normalize_line_endings = !self.normalize_line_endings;
+
+        self.status_message = Some(match self.regenerate_diff() {
+            Ok(()) if self.normalize_line_endings => "Line endings normalized to LF".to_string(),
+            Ok(()) => "Line endings compared as-is".to_string(),
+            Err(e) => format!("Error: {}", e),
+        });
+    }
+
+    /// Switches between comparing whitespace and ignoring it, like `diff -w`.
+    pub fn toggle_ignore_whitespace(&mut self) {
+        self.ignore_whitespace = !self.ignore_whitespace;
+
+        self.status_message = Some(match self.regenerate_diff() {
+            Ok(()) if self.ignore_whitespace => "Ignoring whitespace changes".to_string(),
+            Ok(()) => "Whitespace changes compared".to_string(),
+            Err(e) => format!("Error: {}", e),
+        });
+    }
+
+    /// Re-reads both files and diffs them again, keeping the view where it was.
+    pub fn reload(&mut self) {
+        let (scroll_offset, target_scroll_offset, cursor_position) = (
+            self.scroll_offset,
+            self.target_scroll_offset,
+            self.cursor_position,
+        );
+
+        self.status_message = None;
+        if let Err(e) = self.regenerate_diff() {
+            if !self.reselect_missing_files() {
+                // regenerate_diff reads both files before touching the diff, so the old one stays
+                self.status_message = Some(format!("Error: Failed to reload: {}", e));
+            }
+            return;
+        }
+
+        let last_row = self.rows.len().saturating_sub(1);
+        self.scroll_offset = scroll_offset.min(last_row);
+        self.target_scroll_offset = target_scroll_offset.min(last_row);
+        self.cursor_position = cursor_position.min(last_row);
+
+        // Keep warnings about the new contents, e.g. invalid UTF-8
+        if self.status_message.is_none() {
+            self.status_message = Some(format!(
+                "Reloaded {} and {}",
+                self.source.label(),
+                self.target.label()
+            ));
+        }
+    }
+
+    /// Clears the status message once it has been shown for longer than the timeout.
+    ///
+    /// Messages are set all over the place, so a message counts as new when it differs
+    /// from the one seen on the previous call.
+    pub fn expire_status_message(&mut self, now: Instant) -> bool {
+        let (Some(timeout), Some(message)) = (self.status_timeout, &self.status_message) else {
+            self.status_shown = None;
+            return false;
+        };
+        if self.quit_prompt {
+            return false;
+        }
+
+        match &self.status_shown {
+            Some((shown, since)) if shown == message => {
+                let timeout = if message.starts_with("Error") {
+                    timeout * ERROR_TIMEOUT_FACTOR
+                } else {
+                    timeout
+                };
+                if now.duration_since(*since) >= timeout {
+                    self.status_message = None;
+                    self.status_shown = None;
+                    return true;
                 }
-            } else {
-                app.status_message =
-                    Some("No selection made. Press Space to mark start/end.".to_string());
             }
+            _ => self.status_shown = Some((message.clone(), now)),
         }
-        KeyCode::Char('e') => {
-            if app.get_selection_range().is_some() {
-                match app.export_to_file() {
-                    Ok(filename) => {
-                        app.status_message = Some(format!("Selection exported to {}", filename));
-                    }
-                    Err(e) => {
-                        app.status_message = Some(format!("Error: {}", e));
-                    }
+        false
+    }
+
+    /// Returns true when the app can quit right away, otherwise asks for confirmation first.
+    pub fn request_quit(&mut self) -> bool {
+        let pending = self.selection_start.is_some() || self.patch_series.is_some();
+        if !self.confirm_quit || !pending {
+            return true;
+        }
+
+        self.quit_prompt = true;
+        self.status_message = Some("Quit? (y/n)".to_string());
+        false
+    }
+
+    /// Drops files that were deleted or renamed and opens the browser to pick them again.
+    /// Returns false when both files are still there.
+    fn reselect_missing_files(&mut self) -> bool {
+        let source_missing = self.source.is_missing();
+        let target_missing = self.target.is_missing();
+
+        let (mode, missing) = match (source_missing, target_missing) {
+            (true, true) => (
+                AppMode::SelectingSource,
+                "Source and target files no longer exist",
+            ),
+            (true, false) => (AppMode::SelectingSource, "Source file no longer exists"),
+            (false, true) => (AppMode::SelectingTarget, "Target file no longer exists"),
+            (false, false) => return false,
+        };
+
+        // Start browsing where the missing file used to be
+        let old_path = if source_missing {
+            self.source.label()
+        } else {
+            self.target.label()
+        };
+        if let Some(dir) = Path::new(&old_path).parent().filter(|dir| dir.is_dir()) {
+            self.file_browser.current_dir = dir.to_path_buf();
+        }
+
+        // Without the file there's nothing valid to show, so leave no stale diff behind
+        if source_missing {
+            self.source = DiffSource::default();
+            self.source_stats = None;
+        }
+        if target_missing {
+            self.target = DiffSource::default();
+            self.target_stats = None;
+        }
+        self.diff_lines.clear();
+        self.rows.clear();
+        self.scroll_offset = 0;
+        self.target_scroll_offset = 0;
+        self.cursor_position = 0;
+
+        self.mode = mode;
+        let _ = self.file_browser.load_entries();
+        self.status_message = Some(format!("{} - select it again", missing));
+        true
+    }
+
+    /// A selection of only unchanged lines would make a patch that does nothing.
+    fn check_selection_has_changes(&self) -> Result<(), String> {
+        match self.get_selection_range() {
+            Some((start, end))
+                if self
+                    .diff_lines
+                    .get(start..=end)
+                    .is_some_and(diff::is_identical) =>
+            {
+                Err("Selection contains no changes".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails in read-only mode, for the actions that would write files or use the clipboard.
+    fn check_writable(&self, action: &str) -> Result<(), String> {
+        if self.read_only {
+            Err(format!("{} is disabled in read-only mode", action))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn copy_to_clipboard(&mut self) -> Result<CopyMethod, String> {
+        self.check_selection_has_changes()?;
+        let patch = self.generate_patch();
+        self.set_clipboard_text(&patch)
+    }
+
+    /// Copies the diff as colored HTML along with the plain patch, falling back to only the
+    /// patch where HTML isn't supported. The flag tells whether the HTML made it.
+    pub fn copy_html_to_clipboard(&mut self) -> Result<(CopyMethod, bool), String> {
+        self.check_writable("Copying")?;
+        self.check_selection_has_changes()?;
+        let patch = self.generate_patch();
+        let lines = match self.get_selection_range() {
+            Some((start, end)) => {
+                let end = (end + 1).min(self.diff_lines.len());
+                &self.diff_lines[start.min(end)..end]
+            }
+            None => &self.diff_lines[..],
+        };
+        let html = diff::to_html(&self.source.label(), &self.target.label(), lines);
+
+        if let Some(clipboard) = &mut self.clipboard
+            && let Ok(html) = diff::copy_html_to_clipboard(clipboard, &html, &patch)
+        {
+            return Ok((CopyMethod::System, html));
+        }
+        self.set_clipboard_text(&patch)
+            .map(|method| (method, false))
+    }
+
+    /// Copies the patch wrapped in a Markdown ```` ```diff ```` block, for PRs and chat.
+    pub fn copy_markdown_to_clipboard(&mut self) -> Result<CopyMethod, String> {
+        self.check_selection_has_changes()?;
+        let markdown = diff::to_markdown(&self.generate_patch());
+        self.set_clipboard_text(&markdown)
+    }
+
+    /// Copies the plain text of one side of the selection (or the whole diff).
+    pub fn copy_side_to_clipboard(&mut self, side: Side) -> Result<CopyMethod, String> {
+        let text = diff::extract_side(&self.diff_lines, side, self.get_selection_range());
+        self.set_clipboard_text(&text)
+    }
+
+    /// Copies the absolute path of the source or target file, returning the path.
+    pub fn copy_path_to_clipboard(&mut self, side: Side) -> Result<(CopyMethod, String), String> {
+        let path = self.absolute_path(side)?.display().to_string();
+        let method = self.set_clipboard_text(&path)?;
+        Ok((method, path))
+    }
+
+    /// The absolute path of one side with symlinks resolved, for sides that are files.
+    pub fn absolute_path(&self, side: Side) -> Result<PathBuf, String> {
+        let source = match side {
+            Side::Source => &self.source,
+            Side::Target => &self.target,
+        };
+        let DiffSource::File(path) = source else {
+            return Err(format!("{} isn't a file on disk", source.label()));
+        };
+
+        std::fs::canonicalize(path)
+            .map_err(|e| format!("Failed to resolve '{}': {}", path.display(), e))
+    }
+
+    /// How copying would get the text to the clipboard, `None` when it can't: in read-only
+    /// mode, or without a system clipboard and a terminal to send OSC 52 to.
+    pub fn copy_method(&self) -> Option<CopyMethod> {
+        match self.clipboard {
+            _ if self.read_only => None,
+            Some(_) => Some(CopyMethod::System),
+            None if self.osc52 => Some(CopyMethod::Osc52),
+            None => None,
+        }
+    }
+
+    /// Copies the diff line under the cursor, with or without its diff prefix.
+    pub fn copy_line_to_clipboard(&mut self, with_prefix: bool) -> Result<CopyMethod, String> {
+        if self.mode != AppMode::SelectionMode && !self.cursor_driven() {
+            return Err("Press i to show the cursor first".to_string());
+        }
+
+        let text = match self.rows.get(self.cursor_position) {
+            Some(&DiffRow::Line(idx)) => diff::format_line(&self.diff_lines[idx], with_prefix),
+            Some(DiffRow::Fold { .. }) => {
+                return Err("The cursor is on a fold, press Enter to expand it".to_string());
+            }
+            _ => return Err("There is no line under the cursor".to_string()),
+        };
+        self.set_clipboard_text(&text)
+    }
+
+    /// Copies through the system clipboard, falling back to OSC 52 when there is none or
+    /// it refuses the text (e.g. over SSH) as long as we're talking to a terminal.
+    fn set_clipboard_text(&mut self, text: &str) -> Result<CopyMethod, String> {
+        self.check_writable("Copying")?;
+        let system = self
+            .clipboard
+            .as_mut()
+            .map(|clipboard| diff::copy_to_clipboard(clipboard, text));
+        let mut stdout = io::stdout();
+        let tmux = env::var_os("TMUX").is_some();
+        diff::copy_with_fallback(system, self.osc52.then_some(&mut stdout), text, tmux)
+    }
+
+    /// Exports the diff or selection, appending it to the patch series when one is active.
+    pub fn export_to_file(&mut self) -> Result<String, String> {
+        self.check_writable("Exporting")?;
+        self.check_selection_has_changes()?;
+        let patch = self.generate_patch();
+
+        match &mut self.patch_series {
+            Some(series) => {
+                diff::append_to_file(&series.filename, &patch)?;
+                series.hunks += 1;
+                Ok(series.filename.clone())
+            }
+            None => diff::export_to_file(&patch, self.export_dir.as_deref()),
+        }
+    }
+
+    /// Works out where `e` would write the diff or selection and what, leaving the disk
+    /// untouched. The export directory isn't created either.
+    pub fn preview_export(&mut self) -> Result<(), String> {
+        const EXCERPT_LINES: usize = 5;
+
+        self.check_selection_has_changes()?;
+        let patch = self.generate_patch();
+        let (path, appends) = match &self.patch_series {
+            Some(series) => (series.filename.clone(), true),
+            None => {
+                let filename = diff::export_filename("diff")?;
+                let path = match &self.export_dir {
+                    Some(dir) => dir.join(filename).display().to_string(),
+                    None => filename,
+                };
+                (path, false)
+            }
+        };
+
+        self.export_preview = Some(ExportPreview {
+            path,
+            appends,
+            total_lines: patch.lines().count(),
+            excerpt: diff::excerpt(&patch, EXCERPT_LINES, EXCERPT_LINES, self.glyphs().ellipsis),
+        });
+        Ok(())
+    }
+
+    /// Starts collecting exports in a single patch file, or stops the current series.
+    pub fn toggle_patch_series(&mut self) {
+        self.status_message = Some(match self.patch_series.take() {
+            Some(series) => format!(
+                "Patch series {} closed with {} hunks",
+                series.filename, series.hunks
+            ),
+            None => match self
+                .check_writable("Exporting")
+                .and_then(|_| diff::export_path("series", self.export_dir.as_deref()))
+            {
+                Ok(filename) => {
+                    let message =
+                        format!("Patch series started - exports are added to {}", filename);
+                    self.patch_series = Some(PatchSeries { filename, hunks: 0 });
+                    message
                 }
-            } else {
-                app.status_message =
-                    Some("No selection made. Press Space to mark start/end.".to_string());
+                Err(e) => format!("Error: {}", e),
+            },
+        });
+    }
+
+    /// The files being diffed, to go back to after picking another one.
+    fn file_pair(&self) -> Option<FilePair> {
+        if self.source.is_unset() || self.target.is_unset() {
+            return None;
+        }
+
+        Some(FilePair {
+            source: self.source.clone(),
+            target: self.target.clone(),
+        })
+    }
+
+    fn push_history(&mut self, pair: FilePair) {
+        if self.history.len() == MAX_HISTORY {
+            self.history.remove(0);
+        }
+        self.history.push(pair);
+    }
+
+    /// Goes back to the files that were diffed before the last source or target was picked.
+    pub fn undo_file_selection(&mut self) {
+        let Some(pair) = self.history.pop() else {
+            self.status_message = Some("Nothing to undo".to_string());
+            return;
+        };
+
+        self.source = pair.source;
+        self.target = pair.target;
+        self.status_message = None;
+        if let Err(e) = self.regenerate_diff() {
+            self.status_message = Some(format!("Error loading files: {}", e));
+        } else if self.status_message.is_none() {
+            self.status_message = Some(format!(
+                "Back to {} and {}",
+                self.source.label(),
+                self.target.label()
+            ));
+        }
+    }
+
+    pub fn enter_selection_mode(&mut self) {
+        // Selections are made in the unified view
+        if self.side_by_side {
+            self.toggle_side_by_side();
+        }
+        self.mode = AppMode::SelectionMode;
+        // Keep an existing cursor, otherwise start at the top of the view
+        if !self.cursor_driven() {
+            self.cursor_position = self.scroll_offset;
+        }
+        self.selection_pending = false;
+
+        // Pick up the selection from the last time, x clears it
+        match self.last_selection.take() {
+            Some((start, end)) if end < self.diff_lines.len() => {
+                self.selection_start = Some(start);
+                self.selection_end = Some(end);
+                self.status_message = Some(format!(
+                    "SELECTION MODE - Restored lines {}-{}, x clears, v to exit",
+                    start, end
+                ));
+            }
+            _ => {
+                self.selection_start = None;
+                self.selection_end = None;
+                self.status_message =
+                    Some("SELECTION MODE - Press Space to mark start/end, v to exit".to_string());
             }
         }
-        KeyCode::Up => {
-            app.cursor_up();
-            app.update_selection_end();
+    }
+
+    pub fn exit_selection_mode(&mut self) {
+        self.mode = AppMode::DiffView;
+        self.last_selection = self.get_selection_range();
+        self.selection_start = None;
+        self.selection_end = None;
+        self.selection_pending = false;
+        self.status_message = Some("Selection mode exited".to_string());
+    }
+
+    pub fn toggle_selection_anchor(&mut self) {
+        if !self.selection_pending {
+            // Start a new selection at current cursor position
+            let line = self.first_line_of_row(self.cursor_position);
+            self.selection_start = Some(line);
+            self.selection_end = Some(line);
+            self.selection_pending = true;
+            self.status_message = Some(format!("Selection start: line {}", line));
+        } else {
+            // Finalize the selection at current cursor position
+            self.update_selection_end();
+            self.selection_pending = false;
+            self.report_selection();
         }
-        KeyCode::Down => {
-            let content_height = terminal.size()?.height.saturating_sub(8) as usize;
-            app.cursor_down(content_height);
-            app.update_selection_end();
+    }
+
+    /// Summarizes what copying the selection would grab: its size, the lines it adds and
+    /// removes, and its first and last line.
+    fn report_selection(&mut self) {
+        let Some((start, end)) = self.get_selection_range() else {
+            return;
+        };
+        let Some(lines) = self.diff_lines.get(start..=end) else {
+            return;
+        };
+
+        let counts = diff::count_changes(lines);
+        let mut message = format!(
+            "Selection: lines {}-{} ({} lines selected, +{} -{})",
+            start,
+            end,
+            end - start + 1,
+            counts.insertions,
+            counts.deletions
+        );
+        let ellipsis = self.glyphs().ellipsis;
+        let preview = |line: &DiffLine| {
+            let content = line.content.trim();
+            match content.char_indices().nth(SELECTION_PREVIEW_WIDTH) {
+                Some((idx, _)) => format!("\"{}{}\"", &content[..idx], ellipsis),
+                None => format!("\"{}\"", content),
+            }
+        };
+        message.push_str(&format!(" {}", preview(&lines[0])));
+        if lines.len() > 1 {
+            message.push_str(&format!(
+                " {} {}",
+                ellipsis,
+                preview(&lines[lines.len() - 1])
+            ));
+        }
+        self.status_message = Some(message);
+    }
+
+    /// Grows the selection by the next (or previous) whole change hunk. Without a
+    /// selection the hunk at or after (before) the cursor is selected.
+    pub fn extend_selection_by_hunk(&mut self, forward: bool, max_visible_lines: usize) {
+        let hunks = diff::hunks(&self.diff_lines, self.context);
+        let cursor_line = self.first_line_of_row(self.cursor_position);
+
+        let hunk = match (self.get_selection_range(), forward) {
+            (Some((_, end)), true) => hunks.iter().find(|hunk| hunk.1 > end),
+            (Some((start, _)), false) => hunks.iter().rev().find(|hunk| hunk.0 < start),
+            (None, true) => hunks.iter().find(|hunk| hunk.1 >= cursor_line),
+            (None, false) => hunks.iter().rev().find(|hunk| hunk.0 <= cursor_line),
+        };
+        let Some(&(hunk_start, hunk_end)) = hunk else {
+            self.status_message = Some(format!(
+                "No {} change hunk",
+                if forward { "next" } else { "previous" }
+            ));
+            return;
+        };
+
+        let (start, end) = match self.get_selection_range() {
+            Some((start, end)) => (start.min(hunk_start), end.max(hunk_end)),
+            None => (hunk_start, hunk_end),
+        };
+        self.selection_start = Some(start);
+        self.selection_end = Some(end);
+        self.selection_pending = false;
+
+        // Follow the edge that grew
+        self.cursor_position = self.row_for_line(if forward { end } else { start });
+        self.scroll_to_cursor(max_visible_lines);
+        self.report_selection();
+    }
+
+    /// Selects every diff line at once, e.g. to copy or export the whole patch.
+    pub fn select_all(&mut self) {
+        if self.diff_lines.is_empty() {
+            self.status_message = Some("Nothing to select".to_string());
+            return;
+        }
+
+        self.selection_start = Some(0);
+        self.selection_end = Some(self.diff_lines.len() - 1);
+        self.selection_pending = false;
+        self.status_message = Some(format!(
+            "Selection: all {} lines selected",
+            self.diff_lines.len()
+        ));
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection_start = None;
+        self.selection_end = None;
+        self.selection_pending = false;
+        self.status_message = Some("Selection cleared".to_string());
+    }
+
+    pub fn update_selection_end(&mut self) {
+        if self.selection_pending
+            && let (Some(start), Some(row)) =
+                (self.selection_start, self.rows.get(self.cursor_position))
+        {
+            // A fold under the cursor is selected as a whole
+            let (first, last) = row.line_range();
+            self.selection_end = Some(if last >= start { last } else { first });
+            self.report_selection();
+        }
+    }
+
+    pub fn cursor_up(&mut self) {
+        let Some(row) = (0..self.cursor_position)
+            .rev()
+            .find(|&row| self.selectable(row))
+        else {
+            // Only a divider is left above, scroll it into view
+            self.scroll_offset = 0;
+            return;
+        };
+
+        self.cursor_position = row;
+        // Scroll up if cursor moves above visible area
+        if self.cursor_position < self.scroll_offset {
+            self.scroll_offset = self.cursor_position;
+        }
+    }
+
+    pub fn cursor_down(&mut self, max_visible_lines: usize) {
+        match (self.cursor_position + 1..self.rows.len()).find(|&row| self.selectable(row)) {
+            Some(row) => {
+                self.cursor_position = row;
+                self.scroll_to_cursor(max_visible_lines);
+            }
+            None => {
+                // Only a divider is left below, scroll it into view
+                let max_offset = self.max_scroll_offset(max_visible_lines);
+                self.scroll_offset = self.scroll_offset.max(max_offset);
+            }
+        }
+    }
+
+    /// Scrolls just far enough to show the cursor, including all wrapped rows of its line.
+    fn scroll_to_cursor(&mut self, max_visible_lines: usize) {
+        if self.cursor_position < self.scroll_offset {
+            self.scroll_offset = self.cursor_position;
+        }
+        while self.scroll_offset < self.cursor_position
+            && self.rows_height(self.scroll_offset, self.cursor_position) > max_visible_lines
+        {
+            self.scroll_offset += 1;
+        }
+    }
+
+    pub fn get_selection_range(&self) -> Option<(usize, usize)> {
+        match (self.selection_start, self.selection_end) {
+            (Some(start), Some(end)) => {
+                let min = start.min(end);
+                let max = start.max(end);
+                Some((min, max))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn handle_file_selection(app: &mut App) {
+    let previous = app.file_pair();
+    match app.file_browser.enter_selected() {
+        Ok(Some(selected_file)) => {
+            // File was selected
+            if let Some(file_path) = selected_file.to_str() {
+                if app.mode == AppMode::SelectingSource {
+                    app.source = DiffSource::File(selected_file.clone());
+
+                    // If target is not set, move to selecting target
+                    if app.target.is_unset() {
+                        app.mode = AppMode::SelectingTarget;
+                        app.status_message =
+                            Some(format!("Source: {} - Now select target file", file_path));
+                        let _ = app.file_browser.load_entries();
+                    } else {
+                        // Both files are set, regenerate diff
+                        if let Err(e) = app.regenerate_diff() {
+                            app.status_message = Some(format!("Error loading files: {}", e));
+                        } else {
+                            if let Some(previous) = previous {
+                                app.push_history(previous);
+                            }
+                            // Warnings about the new file, e.g. invalid UTF-8, follow the update
+                            let warning = app.status_message.take();
+                            app.status_message = Some(match warning {
+                                Some(warning) => {
+                                    format!("Source file updated: {} - {}", file_path, warning)
+                                }
+                                None => format!("Source file updated: {}", file_path),
+                            });
+                        }
+                        app.mode = AppMode::DiffView;
+                    }
+                } else {
+                    app.target = DiffSource::File(selected_file.clone());
+
+                    // If source is not set, move to selecting source
+                    if app.source.is_unset() {
+                        app.mode = AppMode::SelectingSource;
+                        app.status_message =
+                            Some(format!("Target: {} - Now select source file", file_path));
+                        let _ = app.file_browser.load_entries();
+                    } else {
+                        // Both files are set, regenerate diff
+                        if let Err(e) = app.regenerate_diff() {
+                            app.status_message = Some(format!("Error loading files: {}", e));
+                        } else {
+                            if let Some(previous) = previous {
+                                app.push_history(previous);
+                            }
+                            // Warnings about the new file, e.g. invalid UTF-8, follow the update
+                            let warning = app.status_message.take();
+                            app.status_message = Some(match warning {
+                                Some(warning) => {
+                                    format!("Target file updated: {} - {}", file_path, warning)
+                                }
+                                None => format!("Target file updated: {}", file_path),
+                            });
+                        }
+                        app.mode = AppMode::DiffView;
+                    }
+                }
+            }
+        }
+        Ok(None) => {
+            // Directory was entered, nothing to do
+        }
+        Err(e) => {
+            app.status_message = Some(format!("Error: {}", e));
+        }
+    }
+}
+
+/// Rows of the diff or file list that fit in the terminal right now.
+fn visible_height<B: ratatui::backend::Backend>(
+    app: &App,
+    terminal: &Terminal<B>,
+) -> io::Result<usize> {
+    let area = Rect::from((Position::ORIGIN, terminal.size()?));
+    Ok(app.visible_height(area))
+}
+
+/// Handles keys while the help popup is open: arrows scroll, anything else closes it.
+fn handle_help_input<B: ratatui::backend::Backend>(
+    app: &mut App,
+    key_code: KeyCode,
+    terminal: &Terminal<B>,
+) -> io::Result<()> {
+    match key_code {
+        KeyCode::Up => {
+            app.help_scroll = app.help_scroll.saturating_sub(1);
+        }
+        KeyCode::Down => {
+            let area = Rect::from((Position::ORIGIN, terminal.size()?));
+            if app.help_scroll < ui::help_max_scroll(area) {
+                app.help_scroll += 1;
+            }
+        }
+        _ => {
+            app.show_help = false;
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles keys in the directory summary: arrows pick a file or folder, Enter opens the
+/// diff of a file or collapses and expands a folder.
+fn handle_dir_summary_input<B: ratatui::backend::Backend>(
+    app: &mut App,
+    key_code: KeyCode,
+    terminal: &Terminal<B>,
+) -> io::Result<bool> {
+    let content_height = visible_height(app, terminal)?;
+    let Some(summary) = &mut app.dir_summary else {
+        return Ok(false);
+    };
+
+    match key_code {
+        KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
+        KeyCode::Up | KeyCode::Char('k') => {
+            summary.move_up();
+            summary.update_scroll(content_height);
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            summary.move_down();
+            summary.update_scroll(content_height);
+        }
+        KeyCode::Enter if summary.toggle_selected_folder() => {
+            summary.update_scroll(content_height);
+        }
+        KeyCode::Enter => {
+            if let Err(e) = app.open_selected_change() {
+                app.mode = AppMode::DirSummary;
+                app.status_message = Some(format!("Error: {}", e));
+            }
+        }
+        _ => {}
+    }
+
+    Ok(false)
+}
+
+fn handle_browser_input<B: ratatui::backend::Backend>(
+    app: &mut App,
+    key_code: KeyCode,
+    terminal: &Terminal<B>,
+) -> io::Result<bool> {
+    let content_height = visible_height(app, terminal)?;
+
+    match key_code {
+        KeyCode::Up => {
+            app.file_browser.move_up();
+        }
+        KeyCode::Down => {
+            app.file_browser.move_down();
+            app.file_browser.update_scroll(content_height);
+        }
+        KeyCode::Enter => {
+            handle_file_selection(app);
+        }
+        KeyCode::Char('g') => {
+            app.path_prompt = Some(String::new());
+        }
+        KeyCode::Char('f') => {
+            if let Err(e) = app.file_browser.toggle_filter() {
+                app.status_message = Some(format!("Error: {}", e));
+            }
+        }
+        KeyCode::Esc | KeyCode::Char('q') => {
+            // Only return to diff view if both files are set
+            if !app.source.is_unset() && !app.target.is_unset() {
+                app.mode = AppMode::DiffView;
+            } else {
+                // Exit the application if files aren't set
+                return Ok(true);
+            }
+        }
+        _ => {}
+    }
+
+    Ok(false)
+}
+
+fn copy_side(app: &mut App, side: Side) {
+    let name = match side {
+        Side::Source => "Source",
+        Side::Target => "Target",
+    };
+
+    app.status_message = Some(match app.copy_side_to_clipboard(side) {
+        Ok(method) => format!("{} text copied to clipboard{}!", name, method.describe()),
+        Err(e) => format!("Error: {}", e),
+    });
+}
+
+fn copy_path(app: &mut App, side: Side) {
+    app.status_message = Some(match app.copy_path_to_clipboard(side) {
+        Ok((method, path)) => format!("Copied {}{}", path, method.describe()),
+        Err(e) => format!("Error: {}", e),
+    });
+}
+
+/// Edits the target file in `$EDITOR` and diffs it again once the editor exits.
+fn open_in_editor<B: ratatui::backend::Backend>(
+    app: &mut App,
+    terminal: &mut Terminal<B>,
+) -> io::Result<()> {
+    if let Err(e) = app.check_writable("Editing") {
+        app.status_message = Some(format!("Error: {}", e));
+        return Ok(());
+    }
+    let DiffSource::File(path) = &app.target else {
+        app.status_message = Some(format!(
+            "Error: {} isn't a file that can be edited",
+            app.target.label()
+        ));
+        return Ok(());
+    };
+    let Ok(command) = env::var("EDITOR") else {
+        app.status_message = Some("Error: $EDITOR isn't set".to_string());
+        return Ok(());
+    };
+
+    let result = editor::edit(&command, path, app.current_target_line());
+    // The editor drew over the whole screen
+    terminal.clear()?;
+    app.reload();
+    if let Err(e) = result {
+        app.status_message = Some(format!("Error: {}", e));
+    }
+    Ok(())
+}
+
+fn copy_line(app: &mut App, with_prefix: bool) {
+    app.status_message = Some(match app.copy_line_to_clipboard(with_prefix) {
+        Ok(method) => format!("Line copied{}", method.describe()),
+        Err(e) => format!("Error: {}", e),
+    });
+}
+
+fn copy_markdown(app: &mut App, what: &str) {
+    app.status_message = Some(match app.copy_markdown_to_clipboard() {
+        Ok(method) => format!(
+            "{} copied to clipboard as Markdown{}!",
+            what,
+            method.describe()
+        ),
+        Err(e) => format!("Error: {}", e),
+    });
+}
+
+fn copy_html(app: &mut App, what: &str) {
+    app.status_message = Some(match app.copy_html_to_clipboard() {
+        Ok((method, true)) => format!("{} copied to clipboard as HTML{}!", what, method.describe()),
+        Ok((method, false)) => format!(
+            "{} copied to clipboard as plain text{}, HTML isn't supported here",
+            what,
+            method.describe()
+        ),
+        Err(e) => format!("Error: {}", e),
+    });
+}
+
+fn export(app: &mut App, what: &str) {
+    app.status_message = Some(match app.export_to_file() {
+        Ok(filename) => match &app.patch_series {
+            Some(series) => format!(
+                "{} added to patch series {} ({} hunks)",
+                what, filename, series.hunks
+            ),
+            None => format!("{} exported to {}", what, filename),
+        },
+        Err(e) => format!("Error: {}", e),
+    });
+}
+
+fn preview_export(app: &mut App) {
+    if let Err(e) = app.preview_export() {
+        app.status_message = Some(format!("Error: {}", e));
+    }
+}
+
+fn handle_line_prompt_input<B: ratatui::backend::Backend>(
+    app: &mut App,
+    key_code: KeyCode,
+    terminal: &Terminal<B>,
+) -> io::Result<()> {
+    let Some(input) = app.line_prompt.as_mut() else {
+        return Ok(());
+    };
+
+    match key_code {
+        KeyCode::Char(c) if c.is_ascii_digit() => input.push(c),
+        KeyCode::Backspace => {
+            input.pop();
+        }
+        KeyCode::Enter => {
+            let input = app.line_prompt.take().unwrap_or_default();
+            if input.is_empty() {
+                return Ok(());
+            }
+            match input.parse() {
+                Ok(number) => app.jump_to_line(number, visible_height(app, terminal)?),
+                Err(_) => app.status_message = Some(format!("Invalid line number: {}", input)),
+            }
+        }
+        KeyCode::Esc => app.line_prompt = None,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_path_prompt_input(app: &mut App, key_code: KeyCode) {
+    let Some(input) = app.path_prompt.as_mut() else {
+        return;
+    };
+
+    match key_code {
+        KeyCode::Char(c) => input.push(c),
+        KeyCode::Backspace => {
+            input.pop();
+        }
+        KeyCode::Tab => *input = app.file_browser.complete_path(input),
+        KeyCode::Enter => {
+            let input = app.path_prompt.take().unwrap_or_default();
+            if input.trim().is_empty() {
+                return;
+            }
+            if let Err(e) = app.file_browser.jump_to(&input) {
+                app.status_message = Some(format!("Error: {}", e));
+            }
+        }
+        KeyCode::Esc => app.path_prompt = None,
+        _ => {}
+    }
+}
+
+fn handle_diffview_input<B: ratatui::backend::Backend>(
+    app: &mut App,
+    key: KeyEvent,
+    terminal: &mut Terminal<B>,
+) -> io::Result<bool> {
+    let pending_g = std::mem::take(&mut app.pending_g);
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key.code {
+        KeyCode::Char('w') if ctrl => {
+            app.toggle_whitespace();
+        }
+        KeyCode::Char('q') => return Ok(app.request_quit()),
+        // These work on the diff lines, an external tool's output is just text
+        KeyCode::Char('v' | '|' | 'z' | 'f' | 'm' | 'N' | 'W' | 'y' | 'Y' | 'H' | 'l' | 'L')
+            if app.external_output.is_some() =>
+        {
+            app.status_message = Some("Not available for an external diff".to_string());
+        }
+        KeyCode::Esc if app.dir_summary.is_some() => {
+            app.mode = AppMode::DirSummary;
+        }
+        KeyCode::Char('s') => {
+            app.mode = AppMode::SelectingSource;
+            let _ = app.file_browser.load_entries();
+        }
+        KeyCode::Char('t') => {
+            app.mode = AppMode::SelectingTarget;
+            let _ = app.file_browser.load_entries();
+        }
+        KeyCode::Char('v') => {
+            app.enter_selection_mode();
+        }
+        KeyCode::Char(':') => {
+            app.line_prompt = Some(String::new());
+        }
+        KeyCode::Char('c') => match app.copy_to_clipboard() {
+            Ok(method) => {
+                app.status_message =
+                    Some(format!("Diff copied to clipboard{}!", method.describe()));
+            }
+            Err(e) => {
+                app.status_message = Some(format!("Error: {}", e));
+            }
+        },
+        KeyCode::Char('e') => export(app, "Diff"),
+        KeyCode::Char('d') => preview_export(app),
+        KeyCode::Char('M') => copy_markdown(app, "Diff"),
+        KeyCode::Char('H') => copy_html(app, "Diff"),
+        KeyCode::Char('p') => {
+            app.toggle_patch_series();
+        }
+        KeyCode::Char('N') => {
+            app.toggle_line_endings();
+        }
+        KeyCode::Char('W') => {
+            app.toggle_ignore_whitespace();
+        }
+        KeyCode::Char('r') => {
+            // Reading big files takes a moment, say so in a frame before reading them
+            app.reload_requested = true;
+        }
+        KeyCode::Char('o') => open_in_editor(app, terminal)?,
+        KeyCode::Char('S') => copy_path(app, Side::Source),
+        KeyCode::Char('T') => copy_path(app, Side::Target),
+        KeyCode::Char('y') => copy_side(app, Side::Target),
+        KeyCode::Char('Y') => copy_side(app, Side::Source),
+        KeyCode::Char('z') => {
+            app.toggle_folding();
+        }
+        KeyCode::Char('f') => {
+            app.toggle_changes_only();
+        }
+        KeyCode::Char('h') => {
+            app.toggle_hunks_only();
+        }
+        KeyCode::Char('m') => {
+            app.toggle_minimap();
+        }
+        KeyCode::Char('F') => {
+            app.toggle_chrome();
+        }
+        KeyCode::Char('u') => {
+            app.undo_file_selection();
+        }
+        KeyCode::Char('i') => {
+            app.toggle_cursor(visible_height(app, terminal)?);
+        }
+        KeyCode::Char('l') => copy_line(app, false),
+        KeyCode::Char('L') => copy_line(app, true),
+        KeyCode::Char('w') => {
+            app.toggle_wrap();
+        }
+        KeyCode::Char('|') => {
+            app.toggle_side_by_side();
+        }
+        KeyCode::Char('=') => {
+            app.toggle_sync_scroll();
+        }
+        KeyCode::Tab if app.side_by_side && !app.sync_scroll => {
+            app.switch_active_pane();
+        }
+        KeyCode::Tab => {
+            app.next_tab();
+        }
+        KeyCode::BackTab => {
+            app.previous_tab();
+        }
+        KeyCode::Enter => {
+            // A fold's first line may be cut too, that takes another Enter
+            let expanded = app.expand_fold_at_cursor();
+            if !expanded {
+                app.show_full_line_at_cursor();
+            }
+        }
+        KeyCode::Char('g') if pending_g => {
+            app.jump_to_top();
+        }
+        KeyCode::Char('g') => {
+            app.pending_g = true;
+        }
+        KeyCode::Char('G') => {
+            app.jump_to_bottom(visible_height(app, terminal)?);
+        }
+        KeyCode::Up | KeyCode::Char('k') => {
+            scroll_held(app, false, visible_height(app, terminal)?);
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            scroll_held(app, true, visible_height(app, terminal)?);
+        }
+        _ => {}
+    }
+
+    Ok(false)
+}
+
+/// Scrolls, or moves the cursor, by as many lines as the arrow key has been held for. Only
+/// the first line of a step wraps around, so a held key stops at the end of the diff.
+fn scroll_held(app: &mut App, down: bool, max_visible_lines: usize) {
+    let step = app.scroll_accel.step(down, Instant::now());
+    let wrap_around = app.wrap_around;
+
+    for i in 0..step {
+        if i == 1 {
+            app.wrap_around = false;
+        }
+        match (app.cursor_driven(), down) {
+            (true, true) => app.cursor_down(max_visible_lines),
+            (true, false) => app.cursor_up(),
+            (false, true) => app.scroll_down(max_visible_lines),
+            (false, false) => app.scroll_up(max_visible_lines),
+        }
+    }
+    app.wrap_around = wrap_around;
+}
+
+fn handle_selection_input<B: ratatui::backend::Backend>(
+    app: &mut App,
+    key: KeyEvent,
+    terminal: &Terminal<B>,
+) -> io::Result<bool> {
+    let pending_g = std::mem::take(&mut app.pending_g);
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key.code {
+        KeyCode::Char('w') if ctrl => {
+            app.toggle_whitespace();
+        }
+        KeyCode::Char('q') => return Ok(app.request_quit()),
+        KeyCode::Char('v') => {
+            app.exit_selection_mode();
+        }
+        KeyCode::Char(' ') => {
+            app.toggle_selection_anchor();
+        }
+        KeyCode::Char('a') => {
+            app.select_all();
+        }
+        KeyCode::Char('x') => {
+            app.clear_selection();
+        }
+        KeyCode::Char('}') => {
+            app.extend_selection_by_hunk(true, visible_height(app, terminal)?);
+        }
+        KeyCode::Char('{') => {
+            app.extend_selection_by_hunk(false, visible_height(app, terminal)?);
+        }
+        KeyCode::Char('c') => {
+            if app.get_selection_range().is_some() {
+                match app.copy_to_clipboard() {
+                    Ok(method) => {
+                        app.status_message = Some(format!(
+                            "Selection copied to clipboard{}!",
+                            method.describe()
+                        ));
+                    }
+                    Err(e) => {
+                        app.status_message = Some(format!("Error: {}", e));
+                    }
+                }
+            } else {
+                app.status_message =
+                    Some("No selection made. Press Space to mark start/end.".to_string());
+            }
+        }
+        KeyCode::Char('H') => {
+            if app.get_selection_range().is_some() {
+                copy_html(app, "Selection");
+            } else {
+                app.status_message =
+                    Some("No selection made. Press Space to mark start/end.".to_string());
+            }
+        }
+        KeyCode::Char('M') => {
+            if app.get_selection_range().is_some() {
+                copy_markdown(app, "Selection");
+            } else {
+                app.status_message =
+                    Some("No selection made. Press Space to mark start/end.".to_string());
+            }
+        }
+        KeyCode::Char('e') => {
+            if app.get_selection_range().is_some() {
+                export(app, "Selection");
+            } else {
+                app.status_message =
+                    Some("No selection made. Press Space to mark start/end.".to_string());
+            }
+        }
+        KeyCode::Char('d') => {
+            if app.get_selection_range().is_some() {
+                preview_export(app);
+            } else {
+                app.status_message =
+                    Some("No selection made. Press Space to mark start/end.".to_string());
+            }
+        }
+        KeyCode::Char('y') => copy_side(app, Side::Target),
+        KeyCode::Char('Y') => copy_side(app, Side::Source),
+        KeyCode::Char('g') if pending_g => {
+            app.jump_to_top();
+            app.update_selection_end();
+        }
+        KeyCode::Char('g') => {
+            app.pending_g = true;
+        }
+        KeyCode::Char('G') => {
+            app.jump_to_bottom(visible_height(app, terminal)?);
+            app.update_selection_end();
+        }
+        KeyCode::Up | KeyCode::Char('k') => {
+            app.cursor_up();
+            app.update_selection_end();
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            app.cursor_down(visible_height(app, terminal)?);
+            app.update_selection_end();
+        }
+        _ => {}
+    }
+
+    Ok(false)
+}
+
+/// Where the TUI gets its key presses and resizes from, so tests can script them.
+trait EventSource {
+    /// Waits up to `timeout` for an event, returning whether one is ready to be read.
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool>;
+    fn read(&mut self) -> io::Result<Event>;
+}
+
+/// The terminal's events, read through crossterm.
+struct CrosstermEvents;
+
+impl EventSource for CrosstermEvents {
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool> {
+        event::poll(timeout)
+    }
+
+    fn read(&mut self) -> io::Result<Event> {
+        event::read()
+    }
+}
+
+/// Runs the TUI until the user quits. Returns whether the open files differed at that
+/// point, for `--exit-code`.
+pub fn run_app<B: ratatui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    mut app: App,
+) -> io::Result<bool> {
+    run_events(terminal, &mut app, &mut CrosstermEvents)
+}
+
+/// The event loop of `run_app`, handling the events of `events` until the user quits.
+fn run_events<B: ratatui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    events: &mut impl EventSource,
+) -> io::Result<bool> {
+    // Drawing builds the styled lines of every row in view, so frames are only drawn when
+    // something could have changed instead of on every tick or mouse move
+    let mut needs_draw = true;
+    loop {
+        // The spinner moves on every tick until the diff is installed
+        needs_draw |= app.pending_diff.is_some();
+        app.poll_pending_diff();
+        needs_draw |= app.expire_status_message(Instant::now());
+
+        if needs_draw {
+            app.view_width = terminal.size()?.width;
+            terminal.draw(|f| {
+                ui::render_ui(f, app);
+            })?;
+            needs_draw = false;
+        }
+
+        if app.reload_requested {
+            app.reload_requested = false;
+            app.reload();
+            needs_draw = true;
+            continue;
+        }
+
+        // Keep the spinner moving while a diff is computed in the background, and notice
+        // when the status message has been shown long enough
+        let expiring = app.status_timeout.is_some() && app.status_message.is_some();
+        if (app.pending_diff.is_some() || expiring) && !events.poll(REDRAW_TICK)? {
+            continue;
+        }
+
+        let key = match events.read()? {
+            Event::Key(key) => key,
+            Event::Resize(..) => {
+                needs_draw = true;
+                continue;
+            }
+            _ => continue,
+        };
+        needs_draw = true;
+        app.status_message = None;
+
+        // A second q confirms as well, so quitting stays a double tap
+        if app.quit_prompt {
+            app.quit_prompt = false;
+            if matches!(key.code, KeyCode::Char('q' | 'y')) {
+                return Ok(app.has_changes());
+            }
+            app.status_message = Some("Quit cancelled".to_string());
+            continue;
+        }
+
+        if app.show_help {
+            handle_help_input(app, key.code, terminal)?;
+            continue;
+        }
+
+        // Any key closes the export preview and the full line
+        if app.export_preview.take().is_some() || app.line_popup.take().is_some() {
+            continue;
+        }
+
+        if app.line_prompt.is_some() {
+            handle_line_prompt_input(app, key.code, terminal)?;
+            continue;
+        }
+
+        if app.path_prompt.is_some() {
+            handle_path_prompt_input(app, key.code);
+            continue;
+        }
+
+        if key.code == KeyCode::Char('?') {
+            app.show_help = true;
+            app.help_scroll = 0;
+            continue;
+        }
+
+        let should_exit = match app.mode {
+            AppMode::DiffView => handle_diffview_input(app, key, terminal)?,
+            AppMode::SelectingSource | AppMode::SelectingTarget => {
+                handle_browser_input(app, key.code, terminal)?
+            }
+            AppMode::SelectionMode => handle_selection_input(app, key, terminal)?,
+            AppMode::DirSummary => handle_dir_summary_input(app, key.code, terminal)?,
+        };
+
+        if should_exit {
+            return Ok(app.has_changes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use similar::ChangeTag;
+    use std::fs;
+    use std::sync::Mutex;
+
+    // Mutex to serialize clipboard access during tests
+    static CLIPBOARD_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Writes a source and a target file into a temporary directory of their own. The
+    /// directory is removed when the returned `TempDir` is dropped, so a failing test
+    /// doesn't leave the files behind.
+    fn create_test_files()
+    -> Result<(assert_fs::TempDir, String, String), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let source_path = dir.path().join("source.txt");
+        let target_path = dir.path().join("target.txt");
+
+        fs::write(&source_path, b"Line 1\nLine 2\nLine 3\nLine to remove\n")?;
+        fs::write(
+            &target_path,
+            b"Line 1\nLine 2 modified\nLine 3\nLine added\n",
+        )?;
+
+        Ok((
+            dir,
+            source_path.display().to_string(),
+            target_path.display().to_string(),
+        ))
+    }
+
+    /// Plays back a script of events. Running out of them is reported as an error, which
+    /// ends the event loop.
+    struct ScriptedEvents(std::collections::VecDeque<Event>);
+
+    impl EventSource for ScriptedEvents {
+        fn poll(&mut self, _timeout: Duration) -> io::Result<bool> {
+            Ok(true)
+        }
+
+        fn read(&mut self) -> io::Result<Event> {
+            self.0
+                .pop_front()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "end of script"))
+        }
+    }
+
+    fn key(code: KeyCode) -> Event {
+        Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn keys(text: &str) -> Vec<Event> {
+        text.chars().map(|c| key(KeyCode::Char(c))).collect()
+    }
+
+    /// Feeds `events` through the event loop on an 80x24 test terminal. Returns what the
+    /// loop returned when the script quit, and `None` when the script ran out first.
+    fn run_script(
+        app: &mut App,
+        events: Vec<Event>,
+    ) -> Result<Option<bool>, Box<dyn std::error::Error>> {
+        use ratatui::backend::TestBackend;
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 24))?;
+        match run_events(&mut terminal, app, &mut ScriptedEvents(events.into())) {
+            Ok(changed) => Ok(Some(changed)),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[test]
+    fn test_generate_patch() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let app = App::new(source.clone(), target.clone())?;
+
+        let patch = app.generate_patch();
+
+        assert!(patch.contains(&format!("--- {}", source)));
+        assert!(patch.contains(&format!("+++ {}", target)));
+        assert!(patch.contains(" Line 1"));
+        assert!(patch.contains(" Line 3"));
+        assert!(patch.contains("-Line 2"));
+        assert!(patch.contains("-Line to remove"));
+        assert!(patch.contains("+Line 2 modified"));
+        assert!(patch.contains("+Line added"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_to_file() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+
+        let filename = app.export_to_file()?;
+
+        assert!(std::path::Path::new(&filename).exists());
+        assert!(filename.starts_with("diff_"));
+        assert!(filename.ends_with(".patch"));
+
+        let contents = fs::read_to_string(&filename)?;
+        assert!(!contents.is_empty());
+        assert!(contents.contains(&format!("--- {}", source)));
+        assert!(contents.contains(&format!("+++ {}", target)));
+
+        let line_count = contents.lines().count();
+        assert!(line_count > 2);
+        fs::remove_file(&filename)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_creates_unique_filenames() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+
+        let filename1 = app.export_to_file()?;
+        assert!(std::path::Path::new(&filename1).exists());
+
+        let filename2 = app.export_to_file()?;
+        assert!(std::path::Path::new(&filename2).exists());
+
+        assert_ne!(filename1, filename2);
+
+        if std::path::Path::new(&filename1).exists() {
+            fs::remove_file(&filename1)?;
+        }
+        if std::path::Path::new(&filename2).exists() {
+            fs::remove_file(&filename2)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch_format_with_no_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source_path, target_path) = create_test_files()?;
+        fs::write(&source_path, "Same content\n")?;
+        fs::write(&target_path, "Same content\n")?;
+
+        let app = App::new(source_path.to_string(), target_path.to_string())?;
+        let patch = app.generate_patch();
+
+        assert!(patch.starts_with("---"));
+        assert!(patch.contains("+++"));
+        // Identical files have no hunks, like `diff -u`
+        assert!(!patch.contains("@@"));
+
+        let lines: Vec<&str> = patch.lines().collect();
+        let has_deletions = lines.iter().skip(2).any(|line| line.starts_with('-'));
+        assert!(!has_deletions);
+
+        let has_additions = lines.iter().skip(2).any(|line| line.starts_with('+'));
+        assert!(!has_additions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_selection_without_changes_is_not_exported() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        fs::write(&source, "same 1\nsame 2\nold\n")?;
+        fs::write(&target, "same 1\nsame 2\nnew\n")?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.enter_selection_mode();
+        app.selection_start = Some(0);
+        app.selection_end = Some(1);
+
+        assert_eq!(
+            app.export_to_file(),
+            Err("Selection contains no changes".to_string())
+        );
+        assert_eq!(
+            app.copy_to_clipboard().unwrap_err(),
+            "Selection contains no changes"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_selection_is_restored_after_leaving_selection_mode()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+
+        app.enter_selection_mode();
+        app.selection_start = Some(3);
+        app.selection_end = Some(1);
+        app.exit_selection_mode();
+        assert_eq!(app.get_selection_range(), None);
+
+        app.enter_selection_mode();
+        assert_eq!(app.get_selection_range(), Some((1, 3)));
+
+        // Clearing it explicitly leaves nothing to restore
+        app.clear_selection();
+        app.exit_selection_mode();
+        app.enter_selection_mode();
+        assert_eq!(app.get_selection_range(), None);
+
+        // Neither does a new diff
+        app.selection_start = Some(0);
+        app.selection_end = Some(0);
+        app.exit_selection_mode();
+        app.regenerate_diff()?;
+        app.enter_selection_mode();
+        assert_eq!(app.get_selection_range(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_picking_a_file_keeps_its_warning() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        fs::write(&target, b"Line 1\nCaf\xe9\n")?;
+        let mut app = App::new(source.clone(), source.clone())?;
+
+        app.mode = AppMode::SelectingTarget;
+        app.file_browser.entries = vec![PathBuf::from(&target)];
+        app.file_browser.selected_index = 0;
+        handle_file_selection(&mut app);
+
+        let status = app.status_message.clone().unwrap_or_default();
+        assert!(status.starts_with("Target file updated: "), "{}", status);
+        assert!(status.contains("not valid UTF-8"), "{}", status);
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_file_selection_restores_previous_pair() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let dir = assert_fs::TempDir::new()?;
+        let wrong = dir.path().join("wrong.txt");
+        fs::write(&wrong, "something else\n")?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        let original_patch = app.generate_patch();
+
+        // Pick another source in the file browser
+        app.mode = AppMode::SelectingSource;
+        app.file_browser.entries = vec![wrong.clone()];
+        app.file_browser.selected_index = 0;
+        handle_file_selection(&mut app);
+        assert_eq!(app.source.label(), wrong.display().to_string());
+        assert_eq!(
+            app.status_message,
+            Some(format!("Source file updated: {}", wrong.display()))
+        );
+
+        app.undo_file_selection();
+        assert_eq!(app.source.label(), source);
+        assert_eq!(app.target.label(), target);
+        assert_eq!(app.generate_patch(), original_patch);
+
+        app.undo_file_selection();
+        assert_eq!(app.status_message.as_deref(), Some("Nothing to undo"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_all_and_clear() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.enter_selection_mode();
+
+        app.select_all();
+        assert_eq!(
+            app.get_selection_range(),
+            Some((0, app.diff_lines.len() - 1))
+        );
+        assert!(app.generate_patch().contains(&app.diff_lines[0].content));
+
+        app.clear_selection();
+        assert_eq!(app.get_selection_range(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extend_selection_by_hunk() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let unchanged: String = (1..=10).map(|i| format!("same {}\n", i)).collect();
+        fs::write(&source, format!("a\n{}b\n", unchanged))?;
+        fs::write(&target, format!("A\n{}B\n", unchanged))?;
+
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.context = Context::symmetric(2);
+        app.enter_selection_mode();
+
+        app.extend_selection_by_hunk(true, 20);
+        assert_eq!(app.get_selection_range(), Some((0, 3)));
+
+        app.extend_selection_by_hunk(true, 20);
+        assert_eq!(app.get_selection_range(), Some((0, 13)));
+        assert_eq!(app.cursor_position, 13);
+
+        app.clear_selection();
+        app.extend_selection_by_hunk(false, 20);
+        assert_eq!(app.get_selection_range(), Some((10, 13)));
+        app.extend_selection_by_hunk(false, 20);
+        assert_eq!(app.get_selection_range(), Some((0, 13)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_selection_follows_cursor_until_finalized() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+
+        app.enter_selection_mode();
+        app.toggle_selection_anchor();
+        assert!(app.selection_pending);
+
+        app.cursor_down(10);
+        app.update_selection_end();
+        assert_eq!(app.get_selection_range(), Some((0, 1)));
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Selection: lines 0-1 (2 lines selected, +0 -1) \"Line 1\" … \"Line 2\"")
+        );
+        app.ascii = true;
+        app.update_selection_end();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Selection: lines 0-1 (2 lines selected, +0 -1) \"Line 1\" ... \"Line 2\"")
+        );
+        app.ascii = false;
+        app.cursor_down(10);
+        app.update_selection_end();
+        assert!(
+            app.status_message
+                .as_deref()
+                .unwrap()
+                .contains("(3 lines selected, +1 -1)")
+        );
+        app.cursor_up();
+        app.update_selection_end();
+
+        // Finalizing stops the selection from following the cursor
+        app.toggle_selection_anchor();
+        assert!(!app.selection_pending);
+        app.cursor_down(10);
+        app.update_selection_end();
+        assert_eq!(app.get_selection_range(), Some((0, 1)));
+
+        // A third press starts a fresh selection at the cursor
+        app.toggle_selection_anchor();
+        assert_eq!(app.get_selection_range(), Some((2, 2)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_scroll_down_reaches_exact_end() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        assert_eq!(app.diff_lines.len(), 6);
+
+        for _ in 0..10 {
+            app.scroll_down(4);
+        }
+
+        // The last line sits on the bottom row of a 4-row viewport
+        assert_eq!(app.scroll_offset, 2);
+        assert_eq!(app.scroll_offset + 4, app.diff_lines.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_scroll_wraps_around_when_enabled() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+
+        app.scroll_up(4);
+        assert_eq!(app.scroll_offset, 0);
+
+        app.wrap_around = true;
+        app.scroll_up(4);
+        assert_eq!(app.scroll_offset, 2);
+
+        app.scroll_down(4);
+        assert_eq!(app.scroll_offset, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_has_changes_covers_every_tab() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), source.clone())?;
+        assert!(!app.has_changes());
+
+        app.open_tab(source.clone(), target.clone())?;
+        app.switch_tab(0);
+        assert!(app.has_changes(), "the second tab differs");
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor_line_starts_in_view_and_drives_scrolling()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let unchanged: String = (1..=20).map(|i| format!("same {}\n", i)).collect();
+        fs::write(&source, format!("old\n{}", unchanged))?;
+        fs::write(&target, format!("new\n{}", unchanged))?;
+
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.scroll_offset = 5;
+        assert!(!app.cursor_driven());
+
+        app.toggle_cursor(6);
+        assert!(app.cursor_driven());
+        assert_eq!(app.cursor_position, 8);
+
+        for _ in 0..3 {
+            app.cursor_down(6);
+        }
+        assert_eq!(app.cursor_position, 11);
+        assert_eq!(app.scroll_offset, 6, "the view follows the cursor");
+
+        app.toggle_side_by_side();
+        assert!(
+            !app.cursor_driven(),
+            "the side-by-side view scrolls instead"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor_stays_visible_down_to_the_last_line() -> Result<(), Box<dyn std::error::Error>> {
+        use ratatui::backend::TestBackend;
+
+        let (_dir, source, target) = create_test_files()?;
+        // Long lines wrap, so the view holds fewer rows than it has lines
+        let unchanged: String = (1..=40)
+            .map(|i| format!("same {} {}\n", i, "x".repeat(i % 3 * 60)))
+            .collect();
+        fs::write(&source, format!("old\n{}last\n", unchanged))?;
+        fs::write(&target, format!("new\n{}last\n", unchanged))?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        let mut terminal = Terminal::new(TestBackend::new(80, 24))?;
+        let area = Rect::from((Position::ORIGIN, terminal.size()?));
+        app.view_width = area.width;
+
+        app.enter_selection_mode();
+        while app.cursor_position + 1 < app.rows.len() {
+            app.cursor_down(app.visible_height(area));
+            assert!(
+                app.rows_height(app.scroll_offset, app.cursor_position) <= app.visible_height(area)
+            );
+        }
+
+        terminal.draw(|f| ui::render_ui(f, &app))?;
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains(">  │last"), "the cursor is on screen");
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_line_needs_a_cursor_on_a_line() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let unchanged: String = (1..=10).map(|i| format!("same {}\n", i)).collect();
+        fs::write(&source, format!("old\n{}", unchanged))?;
+        fs::write(&target, format!("new\n{}", unchanged))?;
+        let mut app = App::new(source.clone(), target.clone())?;
+
+        let err = app.copy_line_to_clipboard(false).unwrap_err();
+        assert!(err.contains("cursor"));
+
+        app.toggle_folding();
+        app.cursor_position = 5;
+        let err = app.copy_line_to_clipboard(true).unwrap_err();
+        assert!(err.contains("fold"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_scroll_accel_speeds_up_while_held() {
+        let mut accel = ScrollAccel::new(4);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        let steps: Vec<usize> = (0..35).map(|i| accel.step(true, at(i * 30))).collect();
+        assert_eq!(steps[..10], [1; 10]);
+        assert_eq!(steps[10..20], [2; 10]);
+        // Capped at the max step from then on
+        assert_eq!(steps[20..], [4; 15]);
+
+        // Changing direction or pausing starts over
+        assert_eq!(accel.step(false, at(35 * 30)), 1);
+        accel.step(false, at(36 * 30));
+        assert_eq!(accel.step(false, at(36 * 30 + 1000)), 1);
+    }
+
+    #[test]
+    fn test_absolute_path_resolves_files_only() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+
+        let path = app.absolute_path(Side::Target)?;
+        assert!(path.is_absolute());
+        assert_eq!(path, fs::canonicalize(&target)?);
+
+        app.source = DiffSource::Stdin;
+        assert!(
+            app.absolute_path(Side::Source)
+                .unwrap_err()
+                .contains("<stdin>")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_current_target_line_follows_the_view() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        fs::write(&source, "a\nold\nb\n")?;
+        fs::write(&target, "a\nb\nnew\n")?;
+        let mut app = App::new(source.clone(), target.clone())?;
+
+        assert_eq!(app.current_target_line(), Some(1));
+        // The deleted line opens the editor at the line that took its place
+        app.scroll_offset = 1;
+        assert_eq!(app.current_target_line(), Some(2));
+
+        app.show_cursor = true;
+        app.cursor_position = 3;
+        assert_eq!(app.current_target_line(), Some(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_scrolling_accounts_for_wrapped_lines() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        fs::write(&source, "1\n2\n3\n4\n5\n")?;
+        fs::write(&target, format!("1\n2\n3\n4\n5\n{}\n", "x".repeat(28)))?;
+
+        let mut app = App::new(source.clone(), target.clone())?;
+        // 10 columns of text, so the added line wraps onto three rows
+        app.view_width = 12;
+        assert_eq!(ui::row_height(&app, &app.rows[5], app.view_width), 3);
+
+        app.jump_to_bottom(4);
+        assert_eq!(app.scroll_offset, 4, "the last line fits entirely");
+
+        app.jump_to_top();
+        app.cursor_position = 3;
+        app.cursor_down(4);
+        assert_eq!(app.scroll_offset, 1);
+        app.cursor_down(4);
+        assert_eq!(app.cursor_position, 5);
+        assert_eq!(
+            app.scroll_offset, 4,
+            "all three rows of the cursor line are in view"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_added_file_from_dir_summary() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::dirdiff::FileChange;
+
+        let dir = assert_fs::TempDir::new()?;
+        fs::create_dir_all(dir.path().join("old"))?;
+        fs::create_dir_all(dir.path().join("new"))?;
+        fs::write(dir.path().join("new").join("added.txt"), "hello\n")?;
+
+        let mut app = App::new_empty(AppMode::DirSummary)?;
+        app.dir_summary = Some(DirSummary::new(
+            dir.path().join("old"),
+            dir.path().join("new"),
+            vec![FileChange {
+                path: PathBuf::from("added.txt"),
+                kind: ChangeKind::Added,
+                counts: None,
+            }],
+        ));
+
+        app.open_selected_change()?;
+        assert_eq!(app.mode, AppMode::DiffView);
+        assert_eq!(app.diff_lines.len(), 1);
+        assert_eq!(app.diff_lines[0].tag, ChangeTag::Insert);
+        assert!(app.has_changes());
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_files_diff_as_all_inserts_or_deletes() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        fs::write(&source, "")?;
+        fs::write(&target, "one\ntwo\n")?;
+
+        let app = App::new(source.clone(), target.clone())?;
+        let tags: Vec<ChangeTag> = app.diff_lines.iter().map(|line| line.tag).collect();
+        assert_eq!(tags, vec![ChangeTag::Insert, ChangeTag::Insert]);
+        assert_eq!(app.source_stats.map(|stats| stats.bytes), Some(0));
+        assert_eq!(app.source_stats.and_then(|stats| stats.lines), Some(0));
+        assert!(app.has_changes());
+
+        let app = App::new(target.clone(), source.clone())?;
+        let tags: Vec<ChangeTag> = app.diff_lines.iter().map(|line| line.tag).collect();
+        assert_eq!(tags, vec![ChangeTag::Delete, ChangeTag::Delete]);
+        assert_eq!(app.target_stats.map(|stats| stats.bytes), Some(0));
+
+        // Unlike an empty file, a missing one can't be diffed
+        fs::remove_file(&source)?;
+        assert!(App::new(source.clone(), target.clone()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_changes_only_keeps_position_across_toggle() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let unchanged: String = (1..=10).map(|i| format!("same {}\n", i)).collect();
+        fs::write(&source, format!("a\n{}b\n", unchanged))?;
+        fs::write(&target, format!("A\n{}B\n", unchanged))?;
+
+        let mut app = App::new(source.clone(), target.clone())?;
+        assert_eq!(app.rows.len(), 14);
+        app.scroll_offset = 6;
+
+        app.toggle_changes_only();
+        assert_eq!(app.rows.len(), 8);
+        // Line 6 is hidden, so the view moves to the next line still shown
+        assert_eq!(app.rows[app.scroll_offset], DiffRow::Line(10));
+
+        app.toggle_changes_only();
+        assert_eq!(app.rows.len(), 14);
+        assert_eq!(app.scroll_offset, 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor_passes_over_hunk_dividers() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let unchanged: String = (1..=10).map(|i| format!("same {}\n", i)).collect();
+        fs::write(&source, format!("{}a\n{}", unchanged, unchanged))?;
+        fs::write(&target, format!("{}A\n{}", unchanged, unchanged))?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.context = Context::symmetric(1);
+
+        app.toggle_hunks_only();
+        assert_eq!(app.rows.len(), 6);
+        assert_eq!(app.rows[0], DiffRow::Skip { start: 0, end: 8 });
+        // The cursor starts on the first line that isn't a divider
+        assert_eq!(app.cursor_position, 1);
+
+        for _ in 0..10 {
+            app.cursor_down(20);
+        }
+        assert_eq!(app.cursor_position, 4);
+        for _ in 0..10 {
+            app.cursor_up();
         }
-        _ => {}
+        assert_eq!(app.cursor_position, 1);
+        assert_eq!(app.scroll_offset, 0);
+
+        app.toggle_hunks_only();
+        assert_eq!(app.rows.len(), 22);
+        Ok(())
     }
 
-    Ok(false)
-}
+    #[test]
+    fn test_jump_to_skipped_line_shows_all_lines() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let unchanged: String = (1..=10).map(|i| format!("same {}\n", i)).collect();
+        fs::write(&source, format!("{}a\n{}", unchanged, unchanged))?;
+        fs::write(&target, format!("{}A\n{}", unchanged, unchanged))?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.context = Context::symmetric(1);
+        app.toggle_hunks_only();
+
+        // Line 3 is in the divider in front of the hunk
+        app.jump_to_line(3, 10);
+
+        assert!(!app.hunks_only);
+        assert_eq!(app.rows.len(), 22);
+        assert_eq!(app.rows[app.cursor_position], DiffRow::Line(2));
+        assert!(app.selectable(app.cursor_position));
+
+        // Lines in a hunk keep the hunk view
+        app.toggle_hunks_only();
+        app.jump_to_line(11, 10);
+        assert!(app.hunks_only);
+        assert_eq!(app.rows[app.cursor_position], DiffRow::Line(11));
+        Ok(())
+    }
 
-pub fn run_app<B: ratatui::backend::Backend>(
-    terminal: &mut Terminal<B>,
-    mut app: App,
-) -> io::Result<()> {
-    loop {
-        terminal.draw(|f| {
-            ui::render_ui(f, &app);
-        })?;
+    #[test]
+    fn test_folding_hides_unchanged_lines_until_expanded() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let (_dir, source, target) = create_test_files()?;
+        let unchanged: String = (1..=10).map(|i| format!("same {}\n", i)).collect();
+        fs::write(&source, format!("old\n{}", unchanged))?;
+        fs::write(&target, format!("new\n{}", unchanged))?;
 
-        if let Event::Key(key) = event::read()? {
-            app.status_message = None;
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.context = Context::symmetric(2);
+        assert_eq!(app.rows.len(), 12);
+        let unfolded_patch = app.generate_patch();
+
+        app.toggle_folding();
+        assert!(app.folding);
+        assert_eq!(app.rows.len(), 5);
+        assert_eq!(app.rows[4], DiffRow::Fold { start: 4, end: 11 });
+
+        app.cursor_position = 4;
+        app.expand_fold_at_cursor();
+        assert_eq!(app.rows.len(), 12);
+
+        // Exports don't depend on what is folded on screen
+        app.toggle_folding();
+        app.toggle_folding();
+        assert_eq!(app.generate_patch(), unfolded_patch);
+        assert!(unfolded_patch.contains("@@ -1,3 +1,3 @@\n-old\n+new\n same 1\n same 2\n"));
+        Ok(())
+    }
 
-            let should_exit = match app.mode {
-                AppMode::DiffView => handle_diffview_input(&mut app, key.code, terminal)?,
-                AppMode::SelectingSource | AppMode::SelectingTarget => {
-                    handle_browser_input(&mut app, key.code, terminal)?
-                }
-                AppMode::SelectionMode => handle_selection_input(&mut app, key.code, terminal)?,
-            };
+    #[test]
+    fn test_selection_over_fold_includes_hidden_lines() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let unchanged: String = (1..=10).map(|i| format!("same {}\n", i)).collect();
+        fs::write(&source, format!("{}old\n", unchanged))?;
+        fs::write(&target, format!("{}new\n", unchanged))?;
 
-            if should_exit {
-                return Ok(());
-            }
-        }
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.context = Context::symmetric(1);
+        app.toggle_folding();
+        assert_eq!(app.rows[0], DiffRow::Fold { start: 0, end: 8 });
+
+        app.enter_selection_mode();
+        app.cursor_position = 0;
+        app.toggle_selection_anchor();
+        app.cursor_down(10);
+        app.update_selection_end();
+
+        assert_eq!(app.get_selection_range(), Some((0, 9)));
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use std::io::Write;
-    use std::sync::Mutex;
+    #[test]
+    fn test_jump_to_top_and_bottom() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
 
-    // Mutex to serialize clipboard access during tests
-    static CLIPBOARD_LOCK: Mutex<()> = Mutex::new(());
+        app.jump_to_bottom(4);
+        assert_eq!(app.scroll_offset, 2);
+        assert_eq!(app.cursor_position, 5);
+
+        app.jump_to_top();
+        assert_eq!(app.scroll_offset, 0);
+        assert_eq!(app.cursor_position, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_utf8_file_is_decoded_lossily() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        fs::write(&target, b"Line 1\nCaf\xe9\n")?;
+
+        let app = App::new(source.clone(), target.clone())?;
+
+        assert!(!app.binary);
+        assert!(app.diff_lines.iter().any(|l| l.content == "Caf\u{FFFD}"));
+        let status = app.status_message.clone().unwrap_or_default();
+        assert!(status.contains("Target file is not valid UTF-8"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_files_are_diffed_as_hex() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        fs::write(&source, b"\x00\x01\x02\x03")?;
+        fs::write(&target, b"\x00\x01\xff\x03")?;
+
+        let app = App::new(source.clone(), target.clone())?;
+
+        assert!(app.binary);
+        let contents: Vec<(ChangeTag, &str)> = app
+            .diff_lines
+            .iter()
+            .map(|line| (line.tag, &line.content[..21]))
+            .collect();
+        assert_eq!(
+            contents,
+            vec![
+                (ChangeTag::Delete, "00000000  00 01 02 03"),
+                (ChangeTag::Insert, "00000000  00 01 ff 03"),
+            ]
+        );
+        assert!(app.generate_patch().contains("+00000000  00 01 ff 03"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_stats_are_recorded() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
 
-    fn create_test_files() -> Result<(String, String), Box<dyn std::error::Error>> {
-        use std::thread;
-        use std::time::{SystemTime, UNIX_EPOCH};
+        let app = App::new(source.clone(), target.clone())?;
 
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
-        let thread_id = format!("{:?}", thread::current().id());
-        let unique_suffix = format!(
-            "{}_{}",
-            timestamp,
-            thread_id.replace("ThreadId(", "").replace(")", "")
+        assert_eq!(
+            app.source_stats,
+            Some(FileStats {
+                bytes: 36,
+                lines: Some(4)
+            })
         );
+        assert_eq!(app.target_stats.and_then(|stats| stats.lines), Some(4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_large_files_are_diffed_in_background() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        let expected: Vec<String> = app.diff_lines.iter().map(|l| l.content.clone()).collect();
+
+        app.async_diff_threshold = 0;
+        app.regenerate_diff()?;
+        assert!(app.pending_diff.is_some());
+        assert!(!app.is_identical());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !app.poll_pending_diff() {
+            assert!(Instant::now() < deadline, "background diff never finished");
+            thread::sleep(Duration::from_millis(10));
+        }
 
-        let source_path = format!("test_source_{}_.txt", unique_suffix);
-        let target_path = format!("test_target_{}_.txt", unique_suffix);
+        assert!(app.pending_diff.is_none());
+        let actual: Vec<String> = app.diff_lines.iter().map(|l| l.content.clone()).collect();
+        assert_eq!(actual, expected);
+        assert_eq!(app.rows.len(), expected.len());
+        Ok(())
+    }
 
-        let mut source_file = fs::File::create(&source_path)?;
-        source_file.write_all(b"Line 1\nLine 2\nLine 3\nLine to remove\n")?;
+    #[test]
+    fn test_jump_to_target_line() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
 
-        let mut target_file = fs::File::create(&target_path)?;
-        target_file.write_all(b"Line 1\nLine 2 modified\nLine 3\nLine added\n")?;
+        app.jump_to_line(3, 2);
+        assert_eq!(app.diff_lines[app.cursor_position].content, "Line 3");
+        assert_eq!(app.scroll_offset, 3);
+        assert!(app.status_message.is_none());
 
-        Ok((source_path, target_path))
+        app.jump_to_line(99, 2);
+        assert_eq!(app.diff_lines[app.cursor_position].content, "Line added");
+        assert_eq!(app.scroll_offset, 4);
+        let status = app.status_message.clone().unwrap_or_default();
+        assert!(status.contains("out of range"));
+        Ok(())
     }
 
-    fn cleanup_test_files(source: &str, target: &str) {
-        let _ = fs::remove_file(source);
-        let _ = fs::remove_file(target);
+    #[test]
+    fn test_toggle_wrap() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        assert!(app.wrap);
+
+        app.toggle_wrap();
+        assert!(!app.wrap);
+        assert_eq!(app.status_message.as_deref(), Some("Line wrapping off"));
+
+        app.toggle_wrap();
+        assert!(app.wrap);
+        Ok(())
     }
 
     #[test]
-    fn test_generate_patch() -> Result<(), Box<dyn std::error::Error>> {
-        let (source, target) = create_test_files()?;
-        let app = App::new(source.clone(), target.clone())?;
+    fn test_side_by_side_panes_scroll_independently() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
 
-        let patch = app.generate_patch();
+        app.toggle_side_by_side();
+        assert_eq!(app.rows.len(), 4);
 
-        assert!(patch.contains(&format!("--- {}", source)));
-        assert!(patch.contains(&format!("+++ {}", target)));
-        assert!(patch.contains(" Line 1"));
-        assert!(patch.contains(" Line 3"));
-        assert!(patch.contains("-Line 2"));
-        assert!(patch.contains("-Line to remove"));
-        assert!(patch.contains("+Line 2 modified"));
-        assert!(patch.contains("+Line added"));
+        // Synced by default, both panes follow the same offset
+        app.scroll_down(2);
+        assert_eq!(app.scroll_offset, 1);
+        assert_eq!(app.target_pane_offset(), 1);
+
+        app.toggle_sync_scroll();
+        app.switch_active_pane();
+        app.scroll_down(2);
+        assert_eq!(app.scroll_offset, 1);
+        assert_eq!(app.target_pane_offset(), 2);
+
+        app.switch_active_pane();
+        app.scroll_up(2);
+        assert_eq!(app.scroll_offset, 0);
+        assert_eq!(app.target_pane_offset(), 2);
 
-        cleanup_test_files(&source, &target);
+        // Syncing again snaps the target pane back to the source pane
+        app.toggle_sync_scroll();
+        assert_eq!(app.target_pane_offset(), 0);
         Ok(())
     }
 
     #[test]
-    fn test_export_to_file() -> Result<(), Box<dyn std::error::Error>> {
-        let (source, target) = create_test_files()?;
-        let app = App::new(source.clone(), target.clone())?;
+    fn test_export_to_file_uses_export_dir() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let dir = assert_fs::TempDir::new()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.export_dir = Some(dir.path().join("out"));
 
         let filename = app.export_to_file()?;
 
+        assert!(filename.starts_with(&fs::canonicalize(dir.path())?.display().to_string()));
         assert!(std::path::Path::new(&filename).exists());
-        assert!(filename.starts_with("diff_"));
-        assert!(filename.ends_with(".patch"));
+        Ok(())
+    }
 
-        let contents = fs::read_to_string(&filename)?;
-        assert!(!contents.is_empty());
-        assert!(contents.contains(&format!("--- {}", source)));
-        assert!(contents.contains(&format!("+++ {}", target)));
+    #[test]
+    fn test_preview_export_writes_nothing() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let dir = assert_fs::TempDir::new()?;
+        let export_dir = dir.path().join("patches");
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.export_dir = Some(export_dir.clone());
 
-        let line_count = contents.lines().count();
-        assert!(line_count > 2);
+        app.preview_export()?;
 
-        cleanup_test_files(&source, &target);
-        fs::remove_file(&filename)?;
+        let preview = app.export_preview.as_ref().unwrap();
+        assert!(preview.path.starts_with(&export_dir.display().to_string()));
+        assert!(!preview.appends);
+        assert_eq!(preview.total_lines, app.generate_patch().lines().count());
+        assert_eq!(preview.excerpt[0], format!("--- {}", source));
+        assert!(!export_dir.exists(), "the preview must not touch the disk");
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch_series_collects_exports() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+
+        app.toggle_patch_series();
+        let first = app.export_to_file()?;
+        let second = app.export_to_file()?;
+        assert_eq!(first, second);
+        assert_eq!(
+            app.patch_series.as_ref().map(|series| series.hunks),
+            Some(2)
+        );
+
+        let contents = fs::read_to_string(&first)?;
+        assert_eq!(contents.matches(&format!("--- {}", source)).count(), 2);
 
+        app.toggle_patch_series();
+        assert!(app.patch_series.is_none());
+        let status = app.status_message.clone().unwrap_or_default();
+        assert!(status.contains("closed with 2 hunks"));
+
+        let _ = fs::remove_file(&first);
         Ok(())
     }
 
     #[test]
-    fn test_export_creates_unique_filenames() -> Result<(), Box<dyn std::error::Error>> {
-        let (source, target) = create_test_files()?;
-        let app = App::new(source.clone(), target.clone())?;
+    fn test_reload_keeps_scroll_position() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.scroll_offset = 3;
+
+        fs::write(
+            &target,
+            "Line 1\nLine 2 modified\nLine 3\nLine added\nMore\n",
+        )?;
+        app.reload();
+
+        assert_eq!(app.scroll_offset, 3);
+        assert_eq!(
+            app.diff_lines.last().map(|line| line.content.as_str()),
+            Some("More")
+        );
+        assert!(
+            app.status_message
+                .as_deref()
+                .unwrap_or_default()
+                .starts_with("Reloaded")
+        );
+        Ok(())
+    }
 
-        let filename1 = app.export_to_file()?;
-        assert!(std::path::Path::new(&filename1).exists());
+    #[test]
+    fn test_reload_after_source_was_removed() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
 
-        std::thread::sleep(std::time::Duration::from_secs(1));
-        let filename2 = app.export_to_file()?;
-        assert!(std::path::Path::new(&filename2).exists());
+        fs::remove_file(&source)?;
+        app.reload();
 
-        assert_ne!(filename1, filename2);
+        assert_eq!(app.mode, AppMode::SelectingSource);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Source file no longer exists - select it again")
+        );
+        assert!(app.source.is_unset());
+        assert_eq!(app.target.label(), target);
+        assert!(app.diff_lines.is_empty());
+        Ok(())
+    }
 
-        if std::path::Path::new(&filename1).exists() {
-            fs::remove_file(&filename1)?;
-        }
-        if std::path::Path::new(&filename2).exists() {
-            fs::remove_file(&filename2)?;
-        }
-        cleanup_test_files(&source, &target);
+    #[test]
+    fn test_confirm_quit_only_with_pending_work() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+
+        app.selection_start = Some(1);
+        assert!(app.request_quit());
+
+        app.confirm_quit = true;
+        app.selection_start = None;
+        assert!(app.request_quit());
 
+        app.selection_start = Some(1);
+        assert!(!app.request_quit());
+        assert!(app.quit_prompt);
+        assert_eq!(app.status_message.as_deref(), Some("Quit? (y/n)"));
         Ok(())
     }
 
     #[test]
-    fn test_patch_format_with_no_changes() -> Result<(), Box<dyn std::error::Error>> {
-        use std::thread;
-        use std::time::{SystemTime, UNIX_EPOCH};
-
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
-        let thread_id = format!("{:?}", thread::current().id());
-        let unique_suffix = format!(
-            "{}_{}",
-            timestamp,
-            thread_id.replace("ThreadId(", "").replace(")", "")
+    fn test_tabs_keep_their_own_diff() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let (_other_dir, other_source, other_target) = create_test_files()?;
+        fs::write(&other_source, "a\n")?;
+        fs::write(&other_target, "b\n")?;
+
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.scroll_offset = 2;
+        app.open_tab(other_source.clone(), other_target.clone())?;
+
+        assert_eq!(app.tab_count(), 2);
+        assert_eq!(app.active_tab, 1);
+        assert_eq!(app.diff_lines.len(), 2);
+        assert_eq!(app.scroll_offset, 0);
+
+        app.next_tab();
+        assert_eq!(app.active_tab, 0);
+        assert_eq!(app.source.label(), source);
+        assert_eq!(app.diff_lines.len(), 6);
+        assert_eq!(app.scroll_offset, 2);
+        assert_eq!(
+            app.tab_files(),
+            vec![
+                (source.clone(), target.clone()),
+                (other_source.clone(), other_target.clone())
+            ]
         );
 
-        let source_path = format!("test_identical_source_{}_.txt", unique_suffix);
-        let target_path = format!("test_identical_target_{}_.txt", unique_suffix);
+        app.previous_tab();
+        assert_eq!(app.source.label(), other_source);
+        Ok(())
+    }
 
-        let mut source_file = fs::File::create(&source_path)?;
-        source_file.write_all(b"Same content\n")?;
+    #[test]
+    fn test_status_message_times_out() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        let start = Instant::now();
+
+        app.status_message = Some("Copied".to_string());
+        app.expire_status_message(start);
+        assert!(app.status_message.is_some(), "no timeout configured");
+
+        app.status_timeout = Some(Duration::from_secs(2));
+        app.expire_status_message(start);
+        app.expire_status_message(start + Duration::from_secs(1));
+        assert_eq!(app.status_message.as_deref(), Some("Copied"));
+        assert!(app.expire_status_message(start + Duration::from_secs(2)));
+        assert!(app.status_message.is_none());
+        assert!(!app.expire_status_message(start + Duration::from_secs(3)));
+
+        // Errors linger longer
+        app.status_message = Some("Error: oops".to_string());
+        app.expire_status_message(start);
+        app.expire_status_message(start + Duration::from_secs(5));
+        assert!(app.status_message.is_some());
+        app.expire_status_message(start + Duration::from_secs(6));
+        assert!(app.status_message.is_none());
+        Ok(())
+    }
 
-        let mut target_file = fs::File::create(&target_path)?;
-        target_file.write_all(b"Same content\n")?;
+    #[test]
+    fn test_visible_height_leaves_room_for_header_and_status_bar()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let mut app = App::new_empty(AppMode::SelectingSource)?;
+        // 3 header rows, 3 status bar rows and the 2 borders around the content
+        assert_eq!(app.visible_height(Rect::new(0, 0, 80, 24)), 16);
+        assert_eq!(app.visible_height(Rect::new(0, 0, 80, 9)), 1);
+        assert_eq!(app.visible_height(Rect::new(0, 0, 80, 5)), 0);
+
+        // Without the chrome the diff gets every row
+        app.mode = AppMode::DiffView;
+        app.toggle_chrome();
+        assert_eq!(app.visible_height(Rect::new(0, 0, 80, 24)), 24);
+        app.line_prompt = Some(String::new());
+        assert_eq!(app.visible_height(Rect::new(0, 0, 80, 24)), 16);
+        Ok(())
+    }
 
-        let app = App::new(source_path.to_string(), target_path.to_string())?;
-        let patch = app.generate_patch();
+    #[test]
+    fn test_line_ranges() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
 
-        assert!(patch.starts_with("---"));
-        assert!(patch.contains("+++"));
-        assert!(patch.contains(" Same content"));
+        app.source_range = Some(LineRange { start: 3, end: 4 });
+        app.target_range = Some(LineRange { start: 3, end: 10 });
+        app.regenerate_diff()?;
+
+        let contents: Vec<&str> = app
+            .diff_lines
+            .iter()
+            .map(|line| line.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["Line 3", "Line to remove", "Line added"]);
+        // Target line numbers still count from the top of the file
+        assert_eq!(app.diff_lines[0].new_line, Some(3));
+        assert_eq!(app.diff_lines[2].new_line, Some(4));
+        Ok(())
+    }
 
-        let lines: Vec<&str> = patch.lines().collect();
-        let has_deletions = lines.iter().skip(2).any(|line| line.starts_with('-'));
-        assert!(!has_deletions);
+    #[test]
+    fn test_max_lines_truncates_long_files() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::with_sources(
+            DiffSource::File(PathBuf::from(&source)),
+            DiffSource::File(PathBuf::from(&target)),
+            AppOptions {
+                max_lines: Some(1),
+                ..AppOptions::default()
+            },
+        )?;
+
+        assert!(app.truncated);
+        assert!(diff::is_identical(&app.diff_lines));
+        assert_eq!(app.diff_lines.len(), 1);
+
+        app.max_lines = Some(100);
+        app.regenerate_diff()?;
+        assert!(!app.truncated);
+
+        // A file without newlines is cut off too, instead of being read in full
+        let size = 200 * diff::HEAD_BYTES_PER_LINE;
+        fs::write(&source, "x".repeat(size))?;
+        app.regenerate_diff()?;
+        assert!(app.truncated);
+        assert!(app.source_stats.is_some_and(|stats| stats.bytes < size));
+        Ok(())
+    }
 
-        let has_additions = lines.iter().skip(2).any(|line| line.starts_with('+'));
-        assert!(!has_additions);
+    #[test]
+    fn test_normalize_line_endings() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        fs::write(&source, "one\r\ntwo\r\n")?;
+        fs::write(&target, "one\ntwo\n")?;
+
+        let mut app = App::new(source.clone(), target.clone())?;
+        assert_eq!(app.line_endings, Some((LineEnding::Crlf, LineEnding::Lf)));
+        assert!(!app.is_identical());
+
+        app.toggle_line_endings();
+        assert!(app.is_identical());
+        assert!(app.line_endings.is_some());
+        assert!(
+            app.generate_patch()
+                .starts_with("Line endings were normalized")
+        );
+
+        app.toggle_line_endings();
+        assert!(!app.is_identical());
+        assert!(app.generate_patch().starts_with("--- "));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_whitespace() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        fs::write(&source, "fn main() {\n  run();\n}\n")?;
+        fs::write(&target, "fn main() {\n    run();\n}\n")?;
+
+        let mut app = App::new(source.clone(), target.clone())?;
+        assert!(!app.is_identical());
+
+        app.toggle_ignore_whitespace();
+        assert!(app.is_identical());
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Ignoring whitespace changes")
+        );
+
+        app.toggle_ignore_whitespace();
+        assert!(!app.is_identical());
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_files_are_identical() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        fs::write(&source, "")?;
+        fs::write(&target, "")?;
+
+        let app = App::new(source.clone(), target.clone())?;
 
-        cleanup_test_files(&source_path, &target_path);
+        assert!(app.diff_lines.is_empty());
+        assert!(app.is_identical());
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_sources_need_no_files() -> Result<(), Box<dyn std::error::Error>> {
+        let memory = |label: &str, text: &str| DiffSource::Memory {
+            label: label.to_string(),
+            content: diff::decode_content(text.as_bytes()),
+        };
+        let mut app = App::with_sources(
+            memory("ours:a.txt", "keep\nold\n"),
+            memory("theirs:a.txt", "keep\nnew\n"),
+            AppOptions::default(),
+        )?;
+
+        assert!(
+            app.generate_patch()
+                .contains("--- ours:a.txt\n+++ theirs:a.txt\n")
+        );
+        assert_eq!(diff::count_changes(&app.diff_lines).insertions, 1);
+
+        app.reload();
+        assert_eq!(app.source.label(), "ours:a.txt");
+        assert!(!app.is_identical());
+        Ok(())
+    }
+
+    #[test]
+    fn test_git_object_is_read_once() -> Result<(), Box<dyn std::error::Error>> {
+        let source = DiffSource::GitObject {
+            rev: "HEAD".to_string(),
+            path: "Cargo.toml".to_string(),
+        };
+        let app = App::with_sources(
+            source,
+            DiffSource::File(PathBuf::from("Cargo.toml")),
+            AppOptions::default(),
+        )?;
+
+        // Kept in memory so a reload doesn't ask git again
+        assert!(matches!(app.source, DiffSource::Memory { .. }));
+        assert_eq!(app.source.label(), "HEAD:Cargo.toml");
+        assert!(matches!(app.target, DiffSource::File(_)));
         Ok(())
     }
 
@@ -568,7 +3784,7 @@ mod tests {
     fn test_copy_to_clipboard() -> Result<(), Box<dyn std::error::Error>> {
         let _lock = CLIPBOARD_LOCK.lock().unwrap();
 
-        let (source, target) = create_test_files()?;
+        let (_dir, source, target) = create_test_files()?;
         let mut app = App::new(source.clone(), target.clone())?;
 
         let result = app.copy_to_clipboard();
@@ -589,16 +3805,55 @@ mod tests {
                 eprintln!("Clipboard not available: {}", e);
             }
         }
+        Ok(())
+    }
+
+    #[test]
+    fn test_options_apply_to_the_first_diff() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        fs::write(&source, "a\nb\nc\n")?;
+        fs::write(&target, "a\nB\nc\n")?;
+
+        let app = App::with_sources(
+            DiffSource::File(PathBuf::from(&source)),
+            DiffSource::File(PathBuf::from(&target)),
+            AppOptions {
+                algorithm: Algorithm::Patience,
+                source_range: Some(LineRange { start: 2, end: 2 }),
+                target_range: Some(LineRange { start: 2, end: 2 }),
+                ..AppOptions::default()
+            },
+        )?;
+
+        assert_eq!(app.algorithm, Algorithm::Patience);
+        let lines: Vec<&str> = app.diff_lines.iter().map(|l| l.content.as_str()).collect();
+        assert_eq!(lines, vec!["b", "B"]);
+        Ok(())
+    }
 
-        cleanup_test_files(&source, &target);
+    #[test]
+    fn test_read_only_leaves_clipboard_and_state_alone() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let app = App::with_options(
+            AppMode::SelectingSource,
+            AppOptions {
+                state_path: Some(dir.path().join("state.toml")),
+                read_only: true,
+                ..AppOptions::default()
+            },
+        )?;
+
+        assert!(app.clipboard.is_none());
+        assert_eq!(app.file_browser.state_path, None);
         Ok(())
     }
 
     #[test]
+    #[allow(clippy::collapsible_if, clippy::redundant_pattern_matching)]
     fn test_clipboard_contains_correct_patch() -> Result<(), Box<dyn std::error::Error>> {
         let _lock = CLIPBOARD_LOCK.lock().unwrap();
 
-        let (source, target) = create_test_files()?;
+        let (_dir, source, target) = create_test_files()?;
         let mut app = App::new(source.clone(), target.clone())?;
 
         let expected_patch = app.generate_patch();
@@ -629,20 +3884,16 @@ mod tests {
                 }
             }
         }
-
-        cleanup_test_files(&source, &target);
         Ok(())
     }
 
     #[test]
+    #[allow(clippy::collapsible_if, clippy::redundant_pattern_matching)]
     fn test_multiple_clipboard_copies() -> Result<(), Box<dyn std::error::Error>> {
         let _lock = CLIPBOARD_LOCK.lock().unwrap();
 
-        use std::thread;
-        use std::time::{SystemTime, UNIX_EPOCH};
-
         // First copy
-        let (source1, target1) = create_test_files()?;
+        let (_dir, source1, target1) = create_test_files()?;
         let mut app1 = App::new(source1.clone(), target1.clone())?;
         let patch1 = app1.generate_patch();
 
@@ -654,25 +3905,10 @@ mod tests {
             }
         }
 
-        cleanup_test_files(&source1, &target1);
-
         // Second copy with different content
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
-        let thread_id = format!("{:?}", thread::current().id());
-        let unique_suffix = format!(
-            "{}_{}",
-            timestamp,
-            thread_id.replace("ThreadId(", "").replace(")", "")
-        );
-
-        let source2_path = format!("test_source2_{}_.txt", unique_suffix);
-        let target2_path = format!("test_target2_{}_.txt", unique_suffix);
-
-        let mut source_file = fs::File::create(&source2_path)?;
-        source_file.write_all(b"Different line 1\nDifferent line 2\n")?;
-
-        let mut target_file = fs::File::create(&target2_path)?;
-        target_file.write_all(b"Different line 1\nModified line 2\n")?;
+        let (_dir2, source2_path, target2_path) = create_test_files()?;
+        fs::write(&source2_path, "Different line 1\nDifferent line 2\n")?;
+        fs::write(&target2_path, "Different line 1\nModified line 2\n")?;
 
         let mut app2 = App::new(source2_path.to_string(), target2_path.to_string())?;
         let patch2 = app2.generate_patch();
@@ -685,8 +3921,130 @@ mod tests {
                 }
             }
         }
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_selects_and_copies_a_range() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        // Whether copying works depends on the machine, so this only checks that `c` tries
+        // to copy the selection. Without a clipboard that fails the same way everywhere
+        app.clipboard = None;
+        app.osc52 = false;
+
+        // Enter selection mode, mark the first two rows and copy them
+        assert_eq!(run_script(&mut app, keys("v j c"))?, None);
+
+        assert_eq!(app.mode, AppMode::SelectionMode);
+        assert_eq!(app.get_selection_range(), Some((0, 1)));
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Error: Clipboard not available in this environment")
+        );
+        Ok(())
+    }
 
-        cleanup_test_files(&source2_path, &target2_path);
+    #[test]
+    fn test_script_closes_help_and_quits() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+
+        assert_eq!(run_script(&mut app, keys("?"))?, None);
+        assert!(app.show_help);
+
+        // The q closes the help, only the second one quits
+        assert_eq!(run_script(&mut app, keys("q"))?, None);
+        assert!(!app.show_help);
+        assert_eq!(run_script(&mut app, keys("q"))?, Some(true));
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_leaves_the_browser_with_esc() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+
+        let mut events = keys("s");
+        events.push(Event::Resize(80, 24));
+        assert_eq!(run_script(&mut app, events)?, None);
+        assert_eq!(app.mode, AppMode::SelectingSource);
+
+        assert_eq!(run_script(&mut app, vec![key(KeyCode::Esc)])?, None);
+        assert_eq!(app.mode, AppMode::DiffView);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_refuses_export_and_copy() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let export_dir = std::env::temp_dir().join(format!("{}_exports", source));
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.export_dir = Some(export_dir.clone());
+        app.read_only = true;
+
+        run_script(&mut app, keys("e"))?;
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Error: Exporting is disabled in read-only mode")
+        );
+        run_script(&mut app, keys("c"))?;
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Error: Copying is disabled in read-only mode")
+        );
+        run_script(&mut app, keys("p"))?;
+        assert!(app.patch_series.is_none());
+        assert!(!export_dir.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_enter_shows_a_cut_line_in_full() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.max_line_length = Some(5);
+        assert_eq!(ui::row_height(&app, &app.rows[0], 4), 1);
+
+        run_script(&mut app, vec![key(KeyCode::Enter)])?;
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Press i to show the cursor first")
+        );
+
+        // The cursor shows up halfway down the view, which is the last line here
+        let mut events = keys("i");
+        events.push(key(KeyCode::Enter));
+        run_script(&mut app, events)?;
+        assert_eq!(app.line_popup.as_deref(), Some("Line added"));
+
+        // Any key closes it again, without doing anything else
+        let cursor = app.cursor_position;
+        run_script(&mut app, keys("k"))?;
+        assert_eq!(app.line_popup, None);
+        assert_eq!(app.cursor_position, cursor);
+        Ok(())
+    }
+
+    #[test]
+    fn test_enter_on_a_fold_only_expands_it() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, target) = create_test_files()?;
+        let unchanged: String = (1..=10).map(|i| format!("same line {}\n", i)).collect();
+        fs::write(&source, format!("old\n{}", unchanged))?;
+        fs::write(&target, format!("new\n{}", unchanged))?;
+        let mut app = App::new(source.clone(), target.clone())?;
+        app.context = Context::symmetric(2);
+        app.max_line_length = Some(5);
+        app.toggle_folding();
+        app.cursor_position = 4;
+
+        run_script(&mut app, vec![key(KeyCode::Enter)])?;
+        assert_eq!(app.rows.len(), 12);
+        assert_eq!(app.line_popup, None);
+
+        // The line that took the fold's place opens with the next Enter
+        run_script(&mut app, vec![key(KeyCode::Enter)])?;
+        assert_eq!(app.line_popup.as_deref(), Some("same line 3"));
         Ok(())
     }
 }
@@ -1,23 +1,51 @@
+use crate::config::State;
 use std::env;
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub struct FileBrowser {
     pub current_dir: PathBuf,
     pub entries: Vec<PathBuf>,
+    /// Modification time of each file in `entries`, `None` for directories and `..`
+    pub modified: Vec<Option<SystemTime>>,
     pub selected_index: usize,
     pub scroll_offset: usize,
+    /// Where the last visited directory is remembered, `None` disables remembering
+    pub state_path: Option<PathBuf>,
+    /// Only list files with these extensions (lowercase, without the dot), all when empty
+    pub extensions: Vec<String>,
+    /// Temporarily list every file even though extensions are set
+    pub show_all: bool,
+    /// Enter symlinked directories and pick symlinked files, off for `--no-follow-symlinks`
+    pub follow_symlinks: bool,
 }
 
 impl FileBrowser {
-    pub fn new() -> Result<Self, io::Error> {
-        let current_dir = env::current_dir()?;
+    /// Opens the browser in the directory remembered at `state_path`, or the current
+    /// directory. Without a state path nothing is remembered.
+    pub fn new(state_path: Option<PathBuf>) -> Result<Self, io::Error> {
+        // Start in the directory we left off last time, if it still exists
+        let last_dir = state_path
+            .as_deref()
+            .and_then(|path| State::load_from(path).last_dir)
+            .filter(|dir| dir.is_dir());
+        let current_dir = match last_dir {
+            Some(dir) => dir,
+            None => env::current_dir()?,
+        };
+
         let mut browser = FileBrowser {
-            current_dir: current_dir.clone(),
+            current_dir,
             entries: Vec::new(),
+            modified: Vec::new(),
             selected_index: 0,
             scroll_offset: 0,
+            extensions: Vec::new(),
+            show_all: false,
+            follow_symlinks: true,
+            state_path,
         };
         browser.load_entries()?;
         Ok(browser)
@@ -25,6 +53,7 @@ impl FileBrowser {
 
     pub fn load_entries(&mut self) -> Result<(), io::Error> {
         self.entries.clear();
+        self.modified.clear();
         self.selected_index = 0;
         self.scroll_offset = 0;
 
@@ -39,6 +68,11 @@ impl FileBrowser {
             .map(|entry| entry.path())
             .collect();
 
+        // Directories stay visible so the filter doesn't get in the way of navigating
+        if !self.extensions.is_empty() && !self.show_all {
+            entries.retain(|entry| entry.is_dir() || self.matches_extension(entry));
+        }
+
         // Sort: directories first, then files, alphabetically
         entries.sort_by(|a, b| {
             let a_is_dir = a.is_dir();
@@ -52,9 +86,68 @@ impl FileBrowser {
         });
 
         self.entries.extend(entries);
+
+        // Read once here instead of on every frame
+        self.modified = self
+            .entries
+            .iter()
+            .map(|entry| {
+                fs::metadata(entry)
+                    .ok()
+                    .filter(|metadata| metadata.is_file())
+                    .and_then(|metadata| metadata.modified().ok())
+            })
+            .collect();
         Ok(())
     }
 
+    fn matches_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                self.extensions
+                    .iter()
+                    .any(|wanted| wanted.eq_ignore_ascii_case(ext))
+            })
+    }
+
+    /// Turns the extension filter on or off. Without extensions given up front, the
+    /// extension of the selected file becomes the filter.
+    pub fn toggle_filter(&mut self) -> Result<(), io::Error> {
+        if self.extensions.is_empty() {
+            let extension = self
+                .entries
+                .get(self.selected_index)
+                .and_then(|entry| entry.extension())
+                .and_then(|ext| ext.to_str());
+            match extension {
+                Some(ext) => {
+                    self.extensions = vec![ext.to_lowercase()];
+                    self.show_all = false;
+                }
+                None => return Ok(()),
+            }
+        } else {
+            self.show_all = !self.show_all;
+        }
+
+        self.load_entries()
+    }
+
+    /// The active filter as shown in the browser title, e.g. `*.rs, *.toml`.
+    pub fn filter_label(&self) -> Option<String> {
+        if self.extensions.is_empty() || self.show_all {
+            return None;
+        }
+
+        let patterns: Vec<String> = self
+            .extensions
+            .iter()
+            .map(|ext| format!("*.{}", ext))
+            .collect();
+        Some(patterns.join(", "))
+    }
+
     pub fn move_up(&mut self) {
         if self.selected_index > 0 {
             self.selected_index -= 1;
@@ -90,6 +183,7 @@ impl FileBrowser {
             if let Some(parent) = self.current_dir.parent() {
                 self.current_dir = parent.to_path_buf();
                 self.load_entries()?;
+                self.remember_current_dir();
             }
             return Ok(None);
         }
@@ -100,9 +194,28 @@ impl FileBrowser {
             self.current_dir.join(selected)
         };
 
+        // Broken symlinks are listed so they don't go unnoticed, but there's nothing to open
+        if let Some(target) = symlink_target(&full_path) {
+            let name = full_path.file_name().unwrap_or_default().to_string_lossy();
+            if !full_path.exists() {
+                return Err(io::Error::other(format!(
+                    "'{}' is a broken symlink to '{}'",
+                    name,
+                    target.display()
+                )));
+            }
+            if !self.follow_symlinks {
+                return Err(io::Error::other(format!(
+                    "'{}' is a symlink, which --no-follow-symlinks refuses to follow",
+                    name
+                )));
+            }
+        }
+
         if full_path.is_dir() {
             self.current_dir = full_path;
             self.load_entries()?;
+            self.remember_current_dir();
             Ok(None)
         } else if full_path.is_file() {
             Ok(Some(full_path))
@@ -111,6 +224,84 @@ impl FileBrowser {
         }
     }
 
+    /// Moves to a typed directory, relative paths start from the current directory and a
+    /// leading `~` stands for the home directory. Stays put when it isn't a directory.
+    pub fn jump_to(&mut self, input: &str) -> Result<(), String> {
+        let path = self.resolve(input.trim());
+        if !path.is_dir() {
+            return Err(format!("'{}' is not a directory", input.trim()));
+        }
+        let path = fs::canonicalize(&path).unwrap_or(path);
+
+        let previous = std::mem::replace(&mut self.current_dir, path);
+        if let Err(e) = self.load_entries() {
+            self.current_dir = previous;
+            let _ = self.load_entries();
+            return Err(format!("Failed to read '{}': {}", input.trim(), e));
+        }
+        self.remember_current_dir();
+        Ok(())
+    }
+
+    /// Completes the last part of a typed path as far as the directories in it agree,
+    /// adding a trailing `/` once a single directory matches.
+    pub fn complete_path(&self, input: &str) -> String {
+        let (parent, prefix) = match input.rfind('/') {
+            Some(idx) => (&input[..=idx], &input[idx + 1..]),
+            None => ("", input),
+        };
+
+        let Ok(entries) = fs::read_dir(self.resolve(parent)) else {
+            return input.to_string();
+        };
+        let matches: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+
+        match matches.as_slice() {
+            [] => input.to_string(),
+            [only] => format!("{}{}/", parent, only),
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.as_str(), |common, name| {
+                    let len = common
+                        .char_indices()
+                        .zip(name.chars())
+                        .take_while(|((_, a), b)| a == b)
+                        .last()
+                        .map_or(0, |((idx, a), _)| idx + a.len_utf8());
+                    &common[..len]
+                });
+                format!("{}{}", parent, common)
+            }
+        }
+    }
+
+    fn resolve(&self, input: &str) -> PathBuf {
+        let expanded = match input.strip_prefix('~') {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => dirs::home_dir()
+                .map(|home| home.join(rest.trim_start_matches('/')))
+                .unwrap_or_else(|| PathBuf::from(input)),
+            _ => PathBuf::from(input),
+        };
+
+        // Joining an absolute path replaces the current directory
+        self.current_dir.join(expanded)
+    }
+
+    /// Persists the current directory so the next run starts here.
+    fn remember_current_dir(&self) {
+        if let Some(path) = &self.state_path {
+            let state = State {
+                last_dir: Some(self.current_dir.clone()),
+            };
+            // Failing to remember the directory shouldn't interrupt browsing
+            let _ = state.save_to(path);
+        }
+    }
+
     pub fn get_display_name(&self, path: &PathBuf) -> String {
         if path.to_str() == Some("..") {
             return "..".to_string();
@@ -122,21 +313,93 @@ impl FileBrowser {
             .unwrap_or("?")
             .to_string();
 
-        if path.is_absolute() && path.is_dir() {
-            format!("{}/", name)
-        } else if !path.is_absolute() {
-            name
-        } else if self.current_dir.join(path).is_dir() {
+        let full_path = self.current_dir.join(path);
+        let name = if full_path.is_dir() {
             format!("{}/", name)
         } else {
             name
+        };
+
+        // Symlinks show where they point, like `ls -l`
+        match symlink_target(&full_path) {
+            Some(target) if full_path.exists() => format!("{} -> {}", name, target.display()),
+            Some(target) => format!("{} -> {} (broken)", name, target.display()),
+            None => name,
         }
     }
 }
 
+/// Where a symlink points, `None` when the path isn't a symlink.
+fn symlink_target(path: &Path) -> Option<PathBuf> {
+    fs::symlink_metadata(path)
+        .ok()
+        .filter(|metadata| metadata.is_symlink())
+        .and_then(|_| fs::read_link(path).ok())
+}
+
+/// Parses a comma-separated list of extensions like `rs,.toml` into lowercase
+/// extensions without the leading dot.
+pub fn parse_extensions(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+        .filter(|ext| !ext.is_empty())
+        .collect()
+}
+
+/// Formats a modification time relative to `now`, e.g. `5 min ago`, falling back to the
+/// date for anything older than a month.
+pub fn format_modified(modified: SystemTime, now: SystemTime) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+
+    // Times in the future (clock skew) count as just now
+    let age = now.duration_since(modified).unwrap_or_default().as_secs();
+    let plural = |count: u64, unit: &str| {
+        if count == 1 {
+            format!("1 {} ago", unit)
+        } else {
+            format!("{} {}s ago", count, unit)
+        }
+    };
+
+    match age {
+        0..MINUTE => "just now".to_string(),
+        MINUTE..HOUR => format!("{} min ago", age / MINUTE),
+        HOUR..DAY => plural(age / HOUR, "hour"),
+        _ if age < 30 * DAY => plural(age / DAY, "day"),
+        _ => {
+            let days = modified
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                / DAY;
+            let (year, month, day) = civil_from_days(days as i64);
+            format!("{:04}-{:02}-{:02}", year, month, day)
+        }
+    }
+}
+
+/// Converts days since the Unix epoch to a (year, month, day) date in the Gregorian
+/// calendar, after Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     fn create_test_browser() -> FileBrowser {
         FileBrowser {
@@ -148,8 +411,13 @@ mod tests {
                 PathBuf::from("file1.txt"),
                 PathBuf::from("file2.txt"),
             ],
+            modified: vec![None; 5],
             selected_index: 0,
             scroll_offset: 0,
+            extensions: Vec::new(),
+            show_all: false,
+            follow_symlinks: true,
+            state_path: None,
         }
     }
 
@@ -248,6 +516,46 @@ mod tests {
         assert_eq!(browser.get_display_name(&path), "..");
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_are_marked_and_broken_ones_not_selectable()
+    -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::symlink;
+
+        let dir = assert_fs::TempDir::new()?;
+        fs::write(dir.path().join("real.txt"), "text\n")?;
+        symlink("real.txt", dir.path().join("link.txt"))?;
+        symlink("gone.txt", dir.path().join("broken.txt"))?;
+        let mut browser = create_test_browser();
+        browser.current_dir = dir.path().to_path_buf();
+        browser.load_entries()?;
+
+        let names: Vec<String> = browser
+            .entries
+            .iter()
+            .map(|entry| browser.get_display_name(entry))
+            .collect();
+        assert!(names.contains(&"link.txt -> real.txt".to_string()));
+        assert!(names.contains(&"broken.txt -> gone.txt (broken)".to_string()));
+        assert!(names.contains(&"real.txt".to_string()));
+
+        let select = |browser: &mut FileBrowser, name: &str| {
+            browser.selected_index = browser
+                .entries
+                .iter()
+                .position(|entry| entry.file_name().is_some_and(|n| n == name))
+                .unwrap();
+            browser.enter_selected()
+        };
+        assert!(select(&mut browser, "broken.txt").is_err());
+        assert!(select(&mut browser, "link.txt")?.is_some());
+
+        browser.follow_symlinks = false;
+        assert!(select(&mut browser, "link.txt").is_err());
+        assert!(select(&mut browser, "real.txt")?.is_some());
+        Ok(())
+    }
+
     #[test]
     fn test_get_display_name_for_relative_path() {
         let browser = create_test_browser();
@@ -255,4 +563,181 @@ mod tests {
 
         assert_eq!(browser.get_display_name(&path), "file.txt");
     }
+
+    #[test]
+    fn test_jump_to_relative_and_invalid_paths() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let nested = dir.path().join("a").join("b");
+        fs::create_dir_all(&nested)?;
+        fs::write(dir.path().join("file.txt"), "")?;
+
+        let mut browser = FileBrowser {
+            current_dir: fs::canonicalize(dir.path())?,
+            entries: Vec::new(),
+            modified: Vec::new(),
+            selected_index: 0,
+            scroll_offset: 0,
+            extensions: Vec::new(),
+            show_all: false,
+            follow_symlinks: true,
+            state_path: None,
+        };
+
+        browser.jump_to("a/b")?;
+        assert_eq!(browser.current_dir, fs::canonicalize(&nested)?);
+
+        let error = browser.jump_to("../../file.txt").unwrap_err();
+        assert!(error.contains("is not a directory"), "{}", error);
+        assert_eq!(browser.current_dir, fs::canonicalize(&nested)?);
+
+        browser.jump_to(dir.path().to_str().unwrap())?;
+        assert_eq!(browser.current_dir, fs::canonicalize(dir.path())?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_complete_path() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        fs::create_dir_all(dir.path().join("projects").join("lazydiff"))?;
+        fs::create_dir_all(dir.path().join("projects").join("lazygit"))?;
+        fs::write(dir.path().join("profile.txt"), "")?;
+
+        let browser = FileBrowser {
+            current_dir: dir.path().to_path_buf(),
+            entries: Vec::new(),
+            modified: Vec::new(),
+            selected_index: 0,
+            scroll_offset: 0,
+            extensions: Vec::new(),
+            show_all: false,
+            follow_symlinks: true,
+            state_path: None,
+        };
+
+        // Files don't count, so the only directory completes fully
+        assert_eq!(browser.complete_path("pro"), "projects/");
+        assert_eq!(browser.complete_path("projects/l"), "projects/lazy");
+        assert_eq!(browser.complete_path("projects/x"), "projects/x");
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_modified() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let ago = |secs| now - Duration::from_secs(secs);
+
+        assert_eq!(format_modified(ago(5), now), "just now");
+        assert_eq!(
+            format_modified(now + Duration::from_secs(5), now),
+            "just now"
+        );
+        assert_eq!(format_modified(ago(5 * 60), now), "5 min ago");
+        assert_eq!(format_modified(ago(3600), now), "1 hour ago");
+        assert_eq!(format_modified(ago(3 * 86_400), now), "3 days ago");
+        // 2023-11-14 22:13:20 UTC, minus 60 days
+        assert_eq!(format_modified(ago(60 * 86_400), now), "2023-09-15");
+    }
+
+    #[test]
+    fn test_load_entries_reads_modification_times() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        fs::create_dir(dir.path().join("sub"))?;
+        fs::write(dir.path().join("file.txt"), "")?;
+
+        let mut browser = FileBrowser {
+            current_dir: dir.path().to_path_buf(),
+            entries: Vec::new(),
+            modified: Vec::new(),
+            selected_index: 0,
+            scroll_offset: 0,
+            extensions: Vec::new(),
+            show_all: false,
+            follow_symlinks: true,
+            state_path: None,
+        };
+        browser.load_entries()?;
+
+        // "..", then the directory, then the file
+        assert_eq!(browser.modified.len(), 3);
+        assert!(browser.modified[0].is_none());
+        assert!(browser.modified[1].is_none());
+        assert!(browser.modified[2].is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_extensions() {
+        assert_eq!(parse_extensions("rs, .TOML,,md"), vec!["rs", "toml", "md"]);
+        assert!(parse_extensions("").is_empty());
+    }
+
+    #[test]
+    fn test_extension_filter_keeps_directories() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        fs::create_dir(dir.path().join("src"))?;
+        fs::write(dir.path().join("main.rs"), "")?;
+        fs::write(dir.path().join("Cargo.TOML"), "")?;
+        fs::write(dir.path().join("README.md"), "")?;
+
+        let mut browser = FileBrowser {
+            current_dir: dir.path().to_path_buf(),
+            entries: Vec::new(),
+            modified: Vec::new(),
+            selected_index: 0,
+            scroll_offset: 0,
+            state_path: None,
+            extensions: parse_extensions("rs,toml"),
+            show_all: false,
+            follow_symlinks: true,
+        };
+        browser.load_entries()?;
+
+        let names: Vec<String> = browser
+            .entries
+            .iter()
+            .map(|entry| browser.get_display_name(entry))
+            .collect();
+        assert_eq!(names, vec!["..", "src/", "Cargo.TOML", "main.rs"]);
+        assert_eq!(browser.filter_label().as_deref(), Some("*.rs, *.toml"));
+
+        browser.toggle_filter()?;
+        assert_eq!(browser.entries.len(), 5);
+        assert_eq!(browser.filter_label(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_entering_directory_remembers_it() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let nested = dir.path().join("nested");
+        fs::create_dir(&nested)?;
+        let state_path = dir.path().join("state.toml");
+
+        let mut browser = FileBrowser {
+            current_dir: dir.path().to_path_buf(),
+            entries: Vec::new(),
+            modified: Vec::new(),
+            selected_index: 0,
+            scroll_offset: 0,
+            extensions: Vec::new(),
+            show_all: false,
+            follow_symlinks: true,
+            state_path: Some(state_path.clone()),
+        };
+        browser.load_entries()?;
+        browser.selected_index = browser
+            .entries
+            .iter()
+            .position(|entry| entry.ends_with("nested"))
+            .unwrap();
+
+        browser.enter_selected()?;
+
+        assert_eq!(browser.current_dir, nested);
+        assert_eq!(State::load_from(&state_path).last_dir, Some(nested.clone()));
+
+        let reopened = FileBrowser::new(Some(state_path))?;
+        assert_eq!(reopened.current_dir, nested);
+        Ok(())
+    }
 }
@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+/// Settings read from `~/.config/lazydiff/config.toml`.
+///
+/// Every key is optional, anything left out falls back to the built-in defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory that exported patches are written to
+    pub export_dir: Option<PathBuf>,
+    /// Seconds before status messages disappear on their own
+    pub status_timeout: Option<f64>,
+    /// Unchanged lines kept around changes when folding
+    pub context: Option<usize>,
+    /// Largest number of lines a held arrow key scrolls at once
+    pub max_scroll_step: Option<NonZeroUsize>,
+    /// Columns diff lines are cut to, so each takes up a single row
+    pub max_line_length: Option<NonZeroUsize>,
+    pub theme: ThemeConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub name: Option<String>,
+    pub insert: Option<String>,
+    pub delete: Option<String>,
+    pub equal: Option<String>,
+    pub modified: Option<String>,
+    pub selection: Option<String>,
+    pub dim: Option<bool>,
+}
+
+impl Config {
+    pub fn load() -> Result<Self, String> {
+        match config_dir() {
+            Some(dir) => Self::load_from(&dir.join("config.toml")),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config '{}': {}", path.display(), e))?;
+
+        Self::parse(&contents).map_err(|e| format!("Invalid config '{}': {}", path.display(), e))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut config: Config = toml::from_str(contents).map_err(|e| e.message().to_string())?;
+        config.export_dir = config.export_dir.map(expand_home);
+        Ok(config)
+    }
+}
+
+/// Replaces a leading `~` with the home directory, like a shell would.
+fn expand_home(path: PathBuf) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path,
+    }
+}
+
+/// Small bits of state remembered between runs, stored next to the config file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    pub last_dir: Option<PathBuf>,
+}
+
+impl State {
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("state.toml"))
+    }
+
+    /// Reads the state, treating a missing or unreadable file as empty state.
+    pub fn load_from(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create '{}': {}", dir.display(), e))?;
+        }
+
+        let contents = toml::to_string(self).map_err(|e| format!("Failed to save state: {}", e))?;
+        fs::write(path, contents)
+            .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+    }
+}
+
+/// The directory holding lazydiff's config, honoring `XDG_CONFIG_HOME` when set.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => dirs::home_dir()?.join(".config"),
+    };
+
+    Some(base.join("lazydiff"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_empty_config_uses_defaults() {
+        let config = Config::parse("").unwrap();
+
+        assert!(config.theme.name.is_none());
+        assert!(config.theme.insert.is_none());
+        assert!(config.theme.dim.is_none());
+        assert!(config.export_dir.is_none());
+        assert!(config.status_timeout.is_none());
+        assert!(config.context.is_none());
+    }
+
+    #[test]
+    fn test_parse_context() {
+        let config = Config::parse("context = 5\n").unwrap();
+
+        assert_eq!(config.context, Some(5));
+        assert!(Config::parse("context = -1\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_max_scroll_step() {
+        let config = Config::parse("max_scroll_step = 16\n").unwrap();
+
+        assert_eq!(config.max_scroll_step, NonZeroUsize::new(16));
+        assert!(Config::parse("max_scroll_step = 0\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_max_line_length() {
+        let config = Config::parse("max_line_length = 120\n").unwrap();
+
+        assert_eq!(config.max_line_length, NonZeroUsize::new(120));
+        assert!(Config::parse("max_line_length = 0\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_export_dir() {
+        let config =
+            Config::parse("export_dir = \"~/patches\"\n[theme]\nname = \"mono\"\n").unwrap();
+
+        assert_eq!(
+            config.export_dir,
+            dirs::home_dir().map(|home| home.join("patches"))
+        );
+        assert_eq!(config.theme.name.as_deref(), Some("mono"));
+
+        // Only a leading `~` on its own is the home directory
+        for path in ["/tmp/patches", "~user/patches", "patches/~"] {
+            let config = Config::parse(&format!("export_dir = \"{}\"\n", path)).unwrap();
+            assert_eq!(config.export_dir, Some(PathBuf::from(path)));
+        }
+    }
+
+    #[test]
+    fn test_parse_theme_section() {
+        let config = Config::parse(
+            r##"
+            [theme]
+            name = "high-contrast"
+            delete = "#ff5555"
+            dim = false
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(config.theme.name.as_deref(), Some("high-contrast"));
+        assert_eq!(config.theme.delete.as_deref(), Some("#ff5555"));
+        assert_eq!(config.theme.insert, None);
+        assert_eq!(config.theme.dim, Some(false));
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_keys() {
+        assert!(Config::parse("[theme]\ninsrt = \"green\"\n").is_err());
+    }
+
+    #[test]
+    fn test_load_from_missing_file_uses_defaults() {
+        let config = Config::load_from(Path::new("does/not/exist.toml")).unwrap();
+
+        assert!(config.theme.name.is_none());
+    }
+
+    #[test]
+    fn test_state_roundtrip() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("nested").join("state.toml");
+
+        let state = State {
+            last_dir: Some(PathBuf::from("/some/where")),
+        };
+        state.save_to(&path).unwrap();
+
+        let loaded = State::load_from(&path);
+        assert_eq!(loaded.last_dir, Some(PathBuf::from("/some/where")));
+    }
+
+    #[test]
+    fn test_state_load_from_missing_file_is_empty() {
+        let state = State::load_from(Path::new("does/not/exist.toml"));
+
+        assert!(state.last_dir.is_none());
+    }
+}
@@ -1,157 +1,2371 @@
 use arboard::Clipboard;
-use similar::{ChangeTag, TextDiff};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use similar::algorithms::{self as algorithms, Capture, Compact, DiffHook, Replace};
+use similar::{Algorithm, ChangeTag, TextDiff};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::fmt;
 use std::fs;
+use std::io::{self, BufRead, Read, Write};
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use unicode_width::UnicodeWidthChar;
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct DiffLine {
     pub tag: ChangeTag,
     pub content: String,
+    /// 1-based line number in the target file, `None` for deleted lines
+    pub new_line: Option<usize>,
+    /// The last line of a file that doesn't end with a newline
+    pub missing_newline: bool,
+    pub kind: LineKind,
 }
 
-pub fn generate_diff(source_content: &str, target_content: &str) -> Vec<DiffLine> {
-    let diff = TextDiff::from_lines(source_content, target_content);
-    let mut diff_lines = Vec::new();
+/// Whether a changed line is an edit of a line on the other side or stands on its own.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LineKind {
+    /// Unchanged, or a line that was only added or only removed
+    #[default]
+    Standalone,
+    /// A deleted line followed by the inserted line that replaces it, or that inserted line
+    Modified,
+}
+
+/// The decoded content of a file along with what we found out while reading it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileContent {
+    pub text: String,
+    pub lossy: bool,
+    pub binary: bool,
+    pub stats: FileStats,
+    /// The bytes as read, kept only when `text` isn't them: for binary files and invalid
+    /// UTF-8
+    pub raw: Option<Vec<u8>>,
+}
+
+impl FileContent {
+    /// The content as it was read, before hex dumping or replacing invalid UTF-8.
+    pub fn bytes(&self) -> &[u8] {
+        self.raw.as_deref().unwrap_or(self.text.as_bytes())
+    }
+}
+
+/// Size of a file as read from disk, the line count is unknown for binary files.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FileStats {
+    pub bytes: usize,
+    pub lines: Option<usize>,
+}
+
+impl FileStats {
+    fn count(bytes: &[u8], binary: bool) -> Self {
+        let lines = (!binary).then(|| {
+            let newlines = bytes.iter().filter(|&&b| b == b'\n').count();
+            // A last line without a trailing newline still counts
+            match bytes.last() {
+                Some(&last) if last != b'\n' => newlines + 1,
+                _ => newlines,
+            }
+        });
+
+        FileStats {
+            bytes: bytes.len(),
+            lines,
+        }
+    }
+}
+
+/// Formats a byte count the way humans read it, e.g. `512 B` or `1.5 KB`.
+pub fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Number of bytes shown per row of a hex dump.
+const HEX_ROW_BYTES: usize = 16;
+
+/// Formats bytes like `hexdump -C`, one row per 16 bytes with the offset in front and
+/// the printable ASCII characters at the end.
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::with_capacity(bytes.len() / HEX_ROW_BYTES * 79 + 79);
+
+    for (row, chunk) in bytes.chunks(HEX_ROW_BYTES).enumerate() {
+        dump.push_str(&format!("{:08x} ", row * HEX_ROW_BYTES));
+        for column in 0..HEX_ROW_BYTES {
+            // An extra space halfway makes the columns easier to count
+            if column == HEX_ROW_BYTES / 2 {
+                dump.push(' ');
+            }
+            match chunk.get(column) {
+                Some(byte) => dump.push_str(&format!(" {:02x}", byte)),
+                None => dump.push_str("   "),
+            }
+        }
+
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        dump.push_str(&format!("  |{}|\n", ascii));
+    }
+
+    dump
+}
+
+/// Number of leading bytes inspected when deciding whether a file is binary.
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/// Bytes `read_file_head` reads per line it may read at most, plenty for any line that
+/// isn't a whole file on its own.
+pub const HEAD_BYTES_PER_LINE: usize = 4096;
+
+pub fn read_file(path: &str) -> Result<FileContent, io::Error> {
+    let bytes = fs::read(path)?;
+    Ok(decode_content(&bytes))
+}
+
+/// Reads no more than the first `max_lines` lines of a file, and no more than
+/// `HEAD_BYTES_PER_LINE` bytes per line, so a huge file isn't read in full when only its
+/// start is diffed, not even one without newlines like minified code. The stats are those
+/// of what was read. The flag tells whether the byte limit cut the file short.
+pub fn read_file_head(path: &str, max_lines: usize) -> Result<(FileContent, bool), io::Error> {
+    let max_bytes = max_lines.saturating_mul(HEAD_BYTES_PER_LINE);
+    let mut reader = io::BufReader::new(fs::File::open(path)?).take(max_bytes as u64);
+
+    let mut bytes = Vec::new();
+    for _ in 0..max_lines {
+        if reader.read_until(b'\n', &mut bytes)? == 0 {
+            break;
+        }
+    }
+
+    let cut = bytes.len() == max_bytes && !reader.into_inner().fill_buf()?.is_empty();
+    // Don't leave half a character at the cut, it would make the file look like invalid UTF-8
+    if cut
+        && let Err(e) = std::str::from_utf8(&bytes)
+        && e.error_len().is_none()
+    {
+        bytes.truncate(e.valid_up_to());
+    }
+    Ok((decode_content(&bytes), cut))
+}
+
+pub fn decode_content(bytes: &[u8]) -> FileContent {
+    // Treat anything with a null byte near the start as binary, like git does. Binary
+    // files are diffed as a hex dump
+    if bytes.iter().take(BINARY_SNIFF_LEN).any(|&b| b == 0) {
+        return FileContent {
+            text: hex_dump(bytes),
+            lossy: false,
+            binary: true,
+            stats: FileStats::count(bytes, true),
+            raw: Some(bytes.to_vec()),
+        };
+    }
+
+    let stats = FileStats::count(bytes, false);
+
+    // Invalid sequences are replaced with U+FFFD instead of refusing the file
+    match String::from_utf8(bytes.to_vec()) {
+        Ok(text) => FileContent {
+            text,
+            lossy: false,
+            binary: false,
+            stats,
+            raw: None,
+        },
+        Err(_) => FileContent {
+            text: String::from_utf8_lossy(bytes).into_owned(),
+            lossy: true,
+            binary: false,
+            stats,
+            raw: Some(bytes.to_vec()),
+        },
+    }
+}
+
+/// Splits a file containing git conflict markers into its "ours" and "theirs" versions.
+///
+/// Lines outside of conflicts end up in both versions, the base section of diff3-style
+/// conflicts is dropped. Returns `Ok(None)` when the content has no conflicts.
+pub fn split_conflict_sides(content: &str) -> Result<Option<(String, String)>, String> {
+    enum Section {
+        Both,
+        Ours,
+        Base,
+        Theirs,
+    }
+
+    let mut ours = String::new();
+    let mut theirs = String::new();
+    let mut section = Section::Both;
+    let mut found_conflict = false;
+
+    for line in content.split_inclusive('\n') {
+        section = match section {
+            Section::Both if is_conflict_marker(line, "<<<<<<<") => {
+                found_conflict = true;
+                Section::Ours
+            }
+            Section::Ours if is_conflict_marker(line, "|||||||") => Section::Base,
+            Section::Ours | Section::Base if is_conflict_marker(line, "=======") => Section::Theirs,
+            Section::Theirs if is_conflict_marker(line, ">>>>>>>") => Section::Both,
+            // Outside of a conflict a line like `=======` is just text, e.g. the underline
+            // of a Markdown heading
+            Section::Both => {
+                ours.push_str(line);
+                theirs.push_str(line);
+                Section::Both
+            }
+            Section::Ours => {
+                ours.push_str(line);
+                Section::Ours
+            }
+            Section::Base => Section::Base,
+            Section::Theirs => {
+                theirs.push_str(line);
+                Section::Theirs
+            }
+        };
+    }
+
+    if !matches!(section, Section::Both) {
+        return Err("Conflict is not terminated by a '>>>>>>>' marker".to_string());
+    }
+
+    Ok(found_conflict.then_some((ours, theirs)))
+}
+
+/// Whether a line is the conflict `marker` on its own or followed by a space and a label,
+/// so longer runs like `==========` don't count.
+fn is_conflict_marker(line: &str, marker: &str) -> bool {
+    line.strip_prefix(marker)
+        .is_some_and(|rest| matches!(rest, "" | "\n" | "\r\n") || rest.starts_with(' '))
+}
+
+/// Names accepted by `--algorithm`.
+pub const ALGORITHM_NAMES: &[&str] = &["myers", "patience", "lcs"];
+
+pub fn parse_algorithm(name: &str) -> Result<Algorithm, String> {
+    match name {
+        "myers" => Ok(Algorithm::Myers),
+        "patience" => Ok(Algorithm::Patience),
+        "lcs" => Ok(Algorithm::Lcs),
+        _ => Err(format!(
+            "unknown algorithm '{}', expected one of: {}",
+            name,
+            ALGORITHM_NAMES.join(", ")
+        )),
+    }
+}
+
+/// Names accepted by `--granularity`.
+pub const GRANULARITY_NAMES: &[&str] = &["line", "char"];
+
+/// How finely changed lines are highlighted. Patches are always made of whole lines.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Granularity {
+    #[default]
+    Line,
+    /// Highlight the characters that differ between a deleted line and the inserted line
+    /// that replaces it
+    Char,
+}
+
+pub fn parse_granularity(name: &str) -> Result<Granularity, String> {
+    match name {
+        "line" => Ok(Granularity::Line),
+        "char" => Ok(Granularity::Char),
+        _ => Err(format!(
+            "unknown granularity '{}', expected one of: {}",
+            name,
+            GRANULARITY_NAMES.join(", ")
+        )),
+    }
+}
+
+/// Names accepted by `--format`.
+pub const FORMAT_NAMES: &[&str] = &["patch", "json"];
+
+/// What `--print` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Patch,
+    /// The lines of the diff with their line numbers, for other programs to read
+    Json,
+}
+
+pub fn parse_format(name: &str) -> Result<OutputFormat, String> {
+    match name {
+        "patch" => Ok(OutputFormat::Patch),
+        "json" => Ok(OutputFormat::Json),
+        _ => Err(format!(
+            "unknown format '{}', expected one of: {}",
+            name,
+            FORMAT_NAMES.join(", ")
+        )),
+    }
+}
+
+/// The inserted line that replaces a deleted line, or the other way around.
+///
+/// Lines are paired the way the side-by-side view lines them up: the n-th line of a run of
+/// deletes with the n-th line of the inserts right after it.
+pub fn partner_line(diff_lines: &[DiffLine], idx: usize) -> Option<usize> {
+    let run_of = |idx: usize, tag: ChangeTag| {
+        let start = diff_lines[..idx]
+            .iter()
+            .rposition(|line| line.tag != tag)
+            .map_or(0, |pos| pos + 1);
+        let end = diff_lines[idx..]
+            .iter()
+            .position(|line| line.tag != tag)
+            .map_or(diff_lines.len(), |pos| idx + pos);
+        (start, end)
+    };
+
+    match diff_lines.get(idx)?.tag {
+        ChangeTag::Delete => {
+            let (start, end) = run_of(idx, ChangeTag::Delete);
+            let partner = end + (idx - start);
+            (diff_lines.get(end)?.tag == ChangeTag::Insert
+                && run_of(end, ChangeTag::Insert).1 > partner)
+                .then_some(partner)
+        }
+        ChangeTag::Insert => {
+            let (start, _) = run_of(idx, ChangeTag::Insert);
+            let deletes_end = start.checked_sub(1)?;
+            if diff_lines[deletes_end].tag != ChangeTag::Delete {
+                return None;
+            }
+            let (deletes_start, _) = run_of(deletes_end, ChangeTag::Delete);
+            let partner = deletes_start + (idx - start);
+            (partner <= deletes_end).then_some(partner)
+        }
+        ChangeTag::Equal => None,
+    }
+}
+
+/// Splits a changed line into runs of characters that are the same in `other` or not, by
+/// diffing the two lines character by character.
+///
+/// `tag` says which side `line` is on. The runs are grouped so that a changed word shows
+/// up as one highlight rather than a letter at a time.
+pub fn char_runs(line: &str, other: &str, tag: ChangeTag) -> Vec<(String, bool)> {
+    let (old, new) = match tag {
+        ChangeTag::Insert => (other, line),
+        _ => (line, other),
+    };
+    let diff = TextDiff::configure()
+        .timeout(Duration::from_millis(20))
+        .diff_chars(old, new);
+
+    let mut runs: Vec<(String, bool)> = Vec::new();
+    for change in diff.iter_all_changes() {
+        let changed = match change.tag() {
+            ChangeTag::Equal => false,
+            change_tag if change_tag == tag => true,
+            // Characters of the other line aren't part of this one
+            _ => continue,
+        };
+        match runs.last_mut() {
+            Some((run, run_changed)) if *run_changed == changed => run.push_str(change.value()),
+            _ => runs.push((change.value().to_string(), changed)),
+        }
+    }
+    runs
+}
+
+/// An inclusive, 1-based range of lines to diff instead of the whole file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl fmt::Display for LineRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "lines {}-{}", self.start, self.end)
+    }
+}
+
+/// Parses a line range written as `A:B`.
+pub fn parse_line_range(range: &str) -> Result<LineRange, String> {
+    let (start, end) = range
+        .split_once(':')
+        .ok_or_else(|| format!("invalid range '{}', expected START:END", range))?;
+    let parse = |number: &str| {
+        number
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|&number| number > 0)
+            .ok_or_else(|| format!("invalid line number '{}' in range '{}'", number, range))
+    };
+
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start > end {
+        return Err(format!(
+            "range '{}' is inverted, the start must not come after the end",
+            range
+        ));
+    }
+
+    Ok(LineRange { start, end })
+}
+
+/// The lines of a text that fall within the range. Ranges past the end of the text are
+/// clamped, so they yield fewer (or no) lines.
+pub fn slice_lines(text: &str, range: LineRange) -> String {
+    text.split_inclusive('\n')
+        .skip(range.start - 1)
+        .take(range.end - range.start + 1)
+        .collect()
+}
+
+pub fn generate_diff(
+    source_content: &str,
+    target_content: &str,
+    algorithm: Algorithm,
+) -> Vec<DiffLine> {
+    generate_diff_cancellable(
+        source_content,
+        target_content,
+        algorithm,
+        false,
+        &AtomicBool::new(false),
+    )
+    .expect("diff without a cancel flag always completes")
+}
+
+/// Like `generate_diff`, but lines that only differ in whitespace count as unchanged, like
+/// `diff -w`. Those lines show the source side, which is what a patch needs as context.
+pub fn generate_diff_ignoring_whitespace(
+    source_content: &str,
+    target_content: &str,
+    algorithm: Algorithm,
+) -> Vec<DiffLine> {
+    generate_diff_cancellable(
+        source_content,
+        target_content,
+        algorithm,
+        true,
+        &AtomicBool::new(false),
+    )
+    .expect("diff without a cancel flag always completes")
+}
+
+/// Like `generate_diff`, but gives up and returns `None` once `cancel` is set.
+///
+/// The flag is checked every time the diff algorithm reports a piece of the diff, which
+/// stops it part way through.
+pub fn generate_diff_cancellable(
+    source_content: &str,
+    target_content: &str,
+    algorithm: Algorithm,
+    ignore_whitespace: bool,
+    cancel: &AtomicBool,
+) -> Option<Vec<DiffLine>> {
+    // Lines both files start or end with can't be part of a change. Only the lines in
+    // between go through the diff algorithm, so editing a few lines of a big file doesn't
+    // cost a full diff
+    let source_lines: Vec<&str> = source_content.split_inclusive('\n').collect();
+    let target_lines: Vec<&str> = target_content.split_inclusive('\n').collect();
+    let source_keys: Vec<Cow<str>> = source_lines
+        .iter()
+        .map(|line| line_key(line, ignore_whitespace))
+        .collect();
+    let target_keys: Vec<Cow<str>> = target_lines
+        .iter()
+        .map(|line| line_key(line, ignore_whitespace))
+        .collect();
+    let prefix = source_keys
+        .iter()
+        .zip(&target_keys)
+        .take_while(|(source, target)| source == target)
+        .count();
+    let suffix = source_keys[prefix..]
+        .iter()
+        .rev()
+        .zip(target_keys[prefix..].iter().rev())
+        .take_while(|(source, target)| source == target)
+        .count();
+
+    let equal_line = |line: &str, new_index: usize| DiffLine {
+        tag: ChangeTag::Equal,
+        content: line_content(line).to_string(),
+        new_line: Some(new_index + 1),
+        missing_newline: !line.ends_with('\n'),
+        kind: LineKind::Standalone,
+    };
+    let mut diff_lines: Vec<DiffLine> = source_lines[..prefix]
+        .iter()
+        .enumerate()
+        .map(|(idx, line)| equal_line(line, idx))
+        .collect();
+
+    // The same hooks `TextDiff` diffs lines with, so the result doesn't change
+    let source_middle = &source_lines[prefix..source_lines.len() - suffix];
+    let target_middle = &target_lines[prefix..target_lines.len() - suffix];
+    let source_middle_keys = &source_keys[prefix..source_keys.len() - suffix];
+    let target_middle_keys = &target_keys[prefix..target_keys.len() - suffix];
+    let mut hook = Cancellable {
+        inner: Compact::new(
+            Replace::new(Capture::new()),
+            source_middle_keys,
+            target_middle_keys,
+        ),
+        cancel,
+    };
+    algorithms::diff_deadline(
+        algorithm,
+        &mut hook,
+        source_middle_keys,
+        0..source_middle_keys.len(),
+        target_middle_keys,
+        0..target_middle_keys.len(),
+        None,
+    )
+    .ok()?;
+
+    for op in hook.inner.into_inner().into_inner().into_ops() {
+        // Every change of a line diff is one line, a blank one is just its newline
+        for change in op.iter_changes(source_middle, target_middle) {
+            diff_lines.push(DiffLine {
+                tag: change.tag(),
+                content: line_content(change.value()).to_string(),
+                new_line: change.new_index().map(|idx| prefix + idx + 1),
+                missing_newline: !change.value().ends_with('\n'),
+                kind: LineKind::Standalone,
+            });
+        }
+    }
+
+    let suffix_start = target_lines.len() - suffix;
+    diff_lines.extend(
+        source_lines[source_lines.len() - suffix..]
+            .iter()
+            .enumerate()
+            .map(|(idx, line)| equal_line(line, suffix_start + idx)),
+    );
+
+    mark_modified(&mut diff_lines);
+    Some(diff_lines)
+}
+
+/// What a line is compared by: the line itself, or when ignoring whitespace only its other
+/// characters and whether it ends in a newline.
+fn line_key(line: &str, ignore_whitespace: bool) -> Cow<'_, str> {
+    if !ignore_whitespace {
+        return Cow::Borrowed(line);
+    }
+
+    let mut key: String = line_content(line)
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    if line.ends_with('\n') {
+        key.push('\n');
+    }
+    Cow::Owned(key)
+}
+
+/// Hands what the diff algorithm finds on to `inner`, and stops the algorithm with an
+/// error once `cancel` is set.
+struct Cancellable<'a, D> {
+    inner: D,
+    cancel: &'a AtomicBool,
+}
+
+struct Cancelled;
+
+impl<D> Cancellable<'_, D> {
+    fn check(&self) -> Result<(), Cancelled> {
+        match self.cancel.load(Ordering::Relaxed) {
+            true => Err(Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+impl<D: DiffHook<Error = Infallible>> DiffHook for Cancellable<'_, D> {
+    type Error = Cancelled;
+
+    fn equal(&mut self, old_index: usize, new_index: usize, len: usize) -> Result<(), Cancelled> {
+        self.check()?;
+        let Ok(()) = self.inner.equal(old_index, new_index, len);
+        Ok(())
+    }
+
+    fn delete(
+        &mut self,
+        old_index: usize,
+        old_len: usize,
+        new_index: usize,
+    ) -> Result<(), Cancelled> {
+        self.check()?;
+        let Ok(()) = self.inner.delete(old_index, old_len, new_index);
+        Ok(())
+    }
+
+    fn insert(
+        &mut self,
+        old_index: usize,
+        new_index: usize,
+        new_len: usize,
+    ) -> Result<(), Cancelled> {
+        self.check()?;
+        let Ok(()) = self.inner.insert(old_index, new_index, new_len);
+        Ok(())
+    }
+
+    fn replace(
+        &mut self,
+        old_index: usize,
+        old_len: usize,
+        new_index: usize,
+        new_len: usize,
+    ) -> Result<(), Cancelled> {
+        self.check()?;
+        let Ok(()) = self.inner.replace(old_index, old_len, new_index, new_len);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Cancelled> {
+        self.check()?;
+        let Ok(()) = self.inner.finish();
+        Ok(())
+    }
+}
+
+/// Marks the lines that `partner_line` would pair up as modified: the n-th line of a run
+/// of deletes and the n-th line of the inserts right after it. Lines past the shorter
+/// run were only added or removed.
+pub fn mark_modified(diff_lines: &mut [DiffLine]) {
+    let run_end = |lines: &[DiffLine], start: usize, tag: ChangeTag| {
+        lines[start..]
+            .iter()
+            .position(|line| line.tag != tag)
+            .map_or(lines.len(), |pos| start + pos)
+    };
+
+    let mut idx = 0;
+    while idx < diff_lines.len() {
+        if diff_lines[idx].tag != ChangeTag::Delete {
+            idx += 1;
+            continue;
+        }
+
+        let deletes_end = run_end(diff_lines, idx, ChangeTag::Delete);
+        let inserts_end = run_end(diff_lines, deletes_end, ChangeTag::Insert);
+        let pairs = (deletes_end - idx).min(inserts_end - deletes_end);
+        for offset in 0..pairs {
+            diff_lines[idx + offset].kind = LineKind::Modified;
+            diff_lines[deletes_end + offset].kind = LineKind::Modified;
+        }
+        idx = inserts_end;
+    }
+}
+
+/// A line without its line break, blank lines stay as an empty string.
+fn line_content(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// The kind of line endings used in a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Mixed,
+}
+
+impl LineEnding {
+    /// Detects the line endings of a text, `None` when it has no line breaks at all.
+    pub fn detect(text: &str) -> Option<Self> {
+        let newlines = text.matches('\n').count();
+        if newlines == 0 {
+            return None;
+        }
+
+        let crlf = text.matches("\r\n").count();
+        Some(if crlf == 0 {
+            LineEnding::Lf
+        } else if crlf == newlines {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Mixed
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+            LineEnding::Mixed => "mixed",
+        }
+    }
+}
+
+/// Turns CRLF line endings into LF so they don't show up as changes.
+pub fn normalize_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\n")
+}
+
+/// True when the diff has no changes, which includes two empty inputs.
+pub fn is_identical(diff_lines: &[DiffLine]) -> bool {
+    diff_lines.iter().all(|line| line.tag == ChangeTag::Equal)
+}
+
+/// A row in the diff view: either a single diff line or a folded run of unchanged lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiffRow {
+    Line(usize),
+    Fold {
+        start: usize,
+        end: usize,
+    },
+    /// Unchanged lines left out between two hunks, shown as a divider with their count
+    Skip {
+        start: usize,
+        end: usize,
+    },
+    /// A source and a target line shown next to each other in the side-by-side view
+    Pair {
+        source: Option<usize>,
+        target: Option<usize>,
+    },
+}
+
+impl DiffRow {
+    /// The inclusive range of diff line indices covered by this row.
+    pub fn line_range(&self) -> (usize, usize) {
+        match *self {
+            DiffRow::Line(idx) => (idx, idx),
+            DiffRow::Fold { start, end } | DiffRow::Skip { start, end } => (start, end),
+            DiffRow::Pair { source, target } => {
+                let first = source.or(target).unwrap_or(0);
+                let last = target.or(source).unwrap_or(0);
+                (first.min(last), first.max(last))
+            }
+        }
+    }
+
+    /// Whether this row shows the given diff line.
+    pub fn contains(&self, line: usize) -> bool {
+        match *self {
+            DiffRow::Pair { source, target } => source == Some(line) || target == Some(line),
+            _ => {
+                let (start, end) = self.line_range();
+                line >= start && line <= end
+            }
+        }
+    }
+}
+
+/// Pairs up source and target lines for the side-by-side view.
+///
+/// Unchanged lines sit next to themselves, a run of deleted lines is lined up with the
+/// inserted lines that replace it and whichever side is longer gets blank partners.
+pub fn side_by_side_rows(diff_lines: &[DiffLine]) -> Vec<DiffRow> {
+    fn flush(rows: &mut Vec<DiffRow>, deletes: &mut Vec<usize>, inserts: &mut Vec<usize>) {
+        for i in 0..deletes.len().max(inserts.len()) {
+            rows.push(DiffRow::Pair {
+                source: deletes.get(i).copied(),
+                target: inserts.get(i).copied(),
+            });
+        }
+        deletes.clear();
+        inserts.clear();
+    }
+
+    let mut rows = Vec::new();
+    let mut deletes = Vec::new();
+    let mut inserts = Vec::new();
+
+    for (idx, line) in diff_lines.iter().enumerate() {
+        match line.tag {
+            ChangeTag::Delete => {
+                // Deletes after inserts start a new change, keep the original order
+                if !inserts.is_empty() {
+                    flush(&mut rows, &mut deletes, &mut inserts);
+                }
+                deletes.push(idx);
+            }
+            ChangeTag::Insert => inserts.push(idx),
+            ChangeTag::Equal => {
+                flush(&mut rows, &mut deletes, &mut inserts);
+                rows.push(DiffRow::Pair {
+                    source: Some(idx),
+                    target: Some(idx),
+                });
+            }
+        }
+    }
+    flush(&mut rows, &mut deletes, &mut inserts);
+
+    rows
+}
+
+/// Maps every diff line to its own row, which is the view without any folding.
+pub fn unfolded_rows(diff_lines: &[DiffLine]) -> Vec<DiffRow> {
+    (0..diff_lines.len()).map(DiffRow::Line).collect()
+}
+
+/// Unchanged lines kept visible around each change when folding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Context {
+    /// Lines kept in front of a change
+    pub before: usize,
+    /// Lines kept after a change
+    pub after: usize,
+}
+
+impl Context {
+    pub fn symmetric(lines: usize) -> Self {
+        Context {
+            before: lines,
+            after: lines,
+        }
+    }
+}
+
+/// Builds the view rows, folding runs of unchanged lines that lie further than the
+/// context away from a change.
+///
+/// Folds are identified by the index of their first hidden line, folds whose start
+/// is in `expanded` are shown in full.
+pub fn fold_rows(
+    diff_lines: &[DiffLine],
+    context: Context,
+    expanded: &HashSet<usize>,
+) -> Vec<DiffRow> {
+    let mut rows = Vec::new();
+    let mut idx = 0;
+
+    while idx < diff_lines.len() {
+        if diff_lines[idx].tag != ChangeTag::Equal {
+            rows.push(DiffRow::Line(idx));
+            idx += 1;
+            continue;
+        }
+
+        let run_start = idx;
+        while idx < diff_lines.len() && diff_lines[idx].tag == ChangeTag::Equal {
+            idx += 1;
+        }
+        let run_end = idx;
+
+        // Keep context after the previous change and before the next one
+        let keep_head = if run_start == 0 { 0 } else { context.after };
+        let keep_tail = if run_end == diff_lines.len() {
+            0
+        } else {
+            context.before
+        };
+        let fold_start = run_start + keep_head;
+        let fold_end = run_end.saturating_sub(keep_tail);
+
+        // Folding a single line would not save any space
+        if fold_end > fold_start + 1 && !expanded.contains(&fold_start) {
+            rows.extend((run_start..fold_start).map(DiffRow::Line));
+            rows.push(DiffRow::Fold {
+                start: fold_start,
+                end: fold_end - 1,
+            });
+            rows.extend((fold_end..run_end).map(DiffRow::Line));
+        } else {
+            rows.extend((run_start..run_end).map(DiffRow::Line));
+        }
+    }
+
+    rows
+}
+
+/// The change hunks of a diff as inclusive line ranges: each run of changed lines with
+/// the context around it. Runs whose context overlaps or touches share a hunk.
+pub fn hunks(diff_lines: &[DiffLine], context: Context) -> Vec<(usize, usize)> {
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    let mut idx = 0;
+
+    while idx < diff_lines.len() {
+        if diff_lines[idx].tag == ChangeTag::Equal {
+            idx += 1;
+            continue;
+        }
+
+        let run_start = idx;
+        while idx < diff_lines.len() && diff_lines[idx].tag != ChangeTag::Equal {
+            idx += 1;
+        }
+        let start = run_start.saturating_sub(context.before);
+        let end = (idx - 1 + context.after).min(diff_lines.len() - 1);
+
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    hunks
+}
+
+/// Builds the rows of the hunk view: the changes with their context like in a patch, and
+/// a divider for the unchanged lines skipped between them. Unlike folds the dividers
+/// can't be expanded.
+pub fn hunk_rows(diff_lines: &[DiffLine], context: Context) -> Vec<DiffRow> {
+    fold_rows(diff_lines, context, &HashSet::new())
+        .into_iter()
+        .map(|row| match row {
+            DiffRow::Fold { start, end } => DiffRow::Skip { start, end },
+            row => row,
+        })
+        .collect()
+}
+
+/// Rows for the "changes only" view: every changed line plus `context` unchanged lines
+/// around it. Unlike [`fold_rows`] the other unchanged lines are left out entirely.
+pub fn changes_only_rows(diff_lines: &[DiffLine], context: usize) -> Vec<DiffRow> {
+    let changed: Vec<usize> = diff_lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.tag != ChangeTag::Equal)
+        .map(|(idx, _)| idx)
+        .collect();
+
+    (0..diff_lines.len())
+        .filter(|&idx| {
+            // The first change at or after this line, and the one before it
+            let next = changed.partition_point(|&c| c < idx);
+            let near_next = changed.get(next).is_some_and(|&c| c - idx <= context);
+            let near_prev = next > 0 && idx - changed[next - 1] <= context;
+            near_next || near_prev
+        })
+        .map(DiffRow::Line)
+        .collect()
+}
+
+/// Style of the patch headers.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PatchFormat {
+    /// Bare `--- source` / `+++ target` headers
+    #[default]
+    Plain,
+    /// `diff --git` headers with `a/` and `b/` prefixes, for `git apply -p1`
+    Git,
+}
+
+/// A unified diff of the two files. The whole diff becomes a hunk for each change with
+/// `context` unchanged lines around it, like `diff -u`. A selection (`line_range`) becomes
+/// a single hunk of exactly the selected lines.
+pub fn generate_patch(
+    source_file: &str,
+    target_file: &str,
+    diff_lines: &[DiffLine],
+    line_range: Option<(usize, usize)>,
+    format: PatchFormat,
+    context: Context,
+) -> String {
+    let mut patch = String::new();
+
+    // Add patch header
+    match format {
+        PatchFormat::Plain => {
+            patch.push_str(&format!("--- {}\n", source_file));
+            patch.push_str(&format!("+++ {}\n", target_file));
+        }
+        PatchFormat::Git => {
+            let source = git_path("a", source_file);
+            let target = git_path("b", target_file);
+            patch.push_str(&format!("diff --git {} {}\n", source, target));
+            // We don't know the blob hashes, git apply doesn't need them
+            patch.push_str("index 0000000..0000000\n");
+            patch.push_str(&format!("--- {}\n", source));
+            patch.push_str(&format!("+++ {}\n", target));
+        }
+    }
+
+    match line_range {
+        // A selection becomes a single hunk against the source, so it applies on its own
+        Some((start, end)) => {
+            let end = end.min(diff_lines.len().saturating_sub(1));
+            if start <= end {
+                let before = source_lines(&diff_lines[..start]);
+                patch.push_str(&hunk_header(&diff_lines[start..=end], before, before));
+                push_hunk_lines(&mut patch, &diff_lines[start..=end]);
+            }
+        }
+        None => {
+            for (start, end) in hunks(diff_lines, context) {
+                patch.push_str(&hunk_header(
+                    &diff_lines[start..=end],
+                    source_lines(&diff_lines[..start]),
+                    target_lines(&diff_lines[..start]),
+                ));
+                push_hunk_lines(&mut patch, &diff_lines[start..=end]);
+            }
+        }
+    }
+
+    patch
+}
+
+fn push_hunk_lines(patch: &mut String, hunk: &[DiffLine]) {
+    for diff_line in hunk {
+        patch.push_str(&format_line(diff_line, true));
+        patch.push('\n');
+        if diff_line.missing_newline {
+            patch.push_str("\\ No newline at end of file\n");
+        }
+    }
+}
+
+fn source_lines(diff_lines: &[DiffLine]) -> usize {
+    diff_lines
+        .iter()
+        .filter(|line| line.tag != ChangeTag::Insert)
+        .count()
+}
+
+fn target_lines(diff_lines: &[DiffLine]) -> usize {
+    diff_lines
+        .iter()
+        .filter(|line| line.tag != ChangeTag::Delete)
+        .count()
+}
+
+/// The `@@` header of `hunk`, which follows `old_before` lines of the source and
+/// `new_before` lines of the target.
+fn hunk_header(hunk: &[DiffLine], old_before: usize, new_before: usize) -> String {
+    let old_count = source_lines(hunk);
+    let new_count = target_lines(hunk);
+
+    // An empty side names the line in front of it, like `diff -u` does
+    let old_start = if old_count == 0 {
+        old_before
+    } else {
+        old_before + 1
+    };
+    let new_start = if new_count == 0 {
+        new_before
+    } else {
+        new_before + 1
+    };
+    format!(
+        "@@ -{},{} +{},{} @@\n",
+        old_start, old_count, new_start, new_count
+    )
+}
+
+/// A single diff line as text, optionally with its `+`/`-`/` ` prefix, without a newline.
+pub fn format_line(diff_line: &DiffLine, with_prefix: bool) -> String {
+    let prefix = match diff_line.tag {
+        _ if !with_prefix => "",
+        ChangeTag::Delete => "-",
+        ChangeTag::Insert => "+",
+        ChangeTag::Equal => " ",
+    };
+    format!("{}{}", prefix, diff_line.content)
+}
+
+/// A single-file unified diff read back from a patch file.
+#[derive(Debug)]
+pub struct ParsedPatch {
+    pub source_file: String,
+    pub target_file: String,
+    pub diff_lines: Vec<DiffLine>,
+}
+
+/// Parses a unified diff touching a single file.
+///
+/// Hunk headers are optional so patches exported by lazydiff itself can be read back. With
+/// hunk headers the line counts are checked and target line numbers follow the headers.
+pub fn parse_patch(text: &str) -> Result<ParsedPatch, String> {
+    let mut lines = text.lines().enumerate();
+
+    // Skip anything before the file headers, like `diff --git` and `index` lines
+    let source_file = lines
+        .by_ref()
+        .find_map(|(_, line)| line.strip_prefix("--- "))
+        .map(header_path)
+        .ok_or("No '---' file header found, is this a unified diff?")?;
+    let target_file = lines
+        .next()
+        .and_then(|(_, line)| line.strip_prefix("+++ "))
+        .map(header_path)
+        .ok_or("Expected a '+++' file header after the '---' header")?;
+
+    let mut diff_lines: Vec<DiffLine> = Vec::new();
+    let mut new_line = 1;
+    // Source and target lines left in the current hunk, when the patch has hunk headers
+    let mut remaining: Option<(usize, usize)> = None;
+
+    for (idx, line) in lines {
+        let line_number = idx + 1;
+
+        if line.starts_with("@@") {
+            if remaining.is_some_and(|left| left != (0, 0)) {
+                return Err(format!(
+                    "Hunk before line {} is shorter than its header says",
+                    line_number
+                ));
+            }
+            let (old_count, new_start, new_count) = parse_hunk_header(line).ok_or_else(|| {
+                format!("Malformed hunk header on line {}: {}", line_number, line)
+            })?;
+            new_line = new_start;
+            remaining = Some((old_count, new_count));
+            continue;
+        }
+
+        if line.starts_with("diff ") || (remaining == Some((0, 0)) && line.starts_with("--- ")) {
+            return Err("Patches touching more than one file are not supported".to_string());
+        }
+
+        if line.starts_with('\\') {
+            if let Some(last) = diff_lines.last_mut() {
+                last.missing_newline = true;
+            }
+            continue;
+        }
+
+        // Whatever follows a complete hunk, like a mail signature, isn't part of the diff
+        if remaining == Some((0, 0)) {
+            continue;
+        }
+
+        // Some tools strip the leading space of empty context lines
+        let (tag, content) = match line.chars().next() {
+            Some(' ') => (ChangeTag::Equal, &line[1..]),
+            Some('-') => (ChangeTag::Delete, &line[1..]),
+            Some('+') => (ChangeTag::Insert, &line[1..]),
+            None => (ChangeTag::Equal, ""),
+            Some(_) => {
+                return Err(format!(
+                    "Unexpected line {} in patch: {}",
+                    line_number, line
+                ));
+            }
+        };
+
+        if let Some((old_left, new_left)) = remaining.as_mut() {
+            let uses_old = tag != ChangeTag::Insert;
+            let uses_new = tag != ChangeTag::Delete;
+            if (uses_old && *old_left == 0) || (uses_new && *new_left == 0) {
+                return Err(format!(
+                    "Hunk ending at line {} is longer than its header says",
+                    line_number
+                ));
+            }
+            *old_left -= usize::from(uses_old);
+            *new_left -= usize::from(uses_new);
+        }
+
+        let line_in_target = (tag != ChangeTag::Delete).then(|| {
+            new_line += 1;
+            new_line - 1
+        });
+        diff_lines.push(DiffLine {
+            tag,
+            content: content.to_string(),
+            new_line: line_in_target,
+            missing_newline: false,
+            kind: LineKind::Standalone,
+        });
+    }
+
+    if remaining.is_some_and(|left| left != (0, 0)) {
+        return Err("Patch ends in the middle of a hunk".to_string());
+    }
+    mark_modified(&mut diff_lines);
+
+    Ok(ParsedPatch {
+        source_file,
+        target_file,
+        diff_lines,
+    })
+}
+
+/// Drops the timestamp some tools append to `---`/`+++` headers after a tab.
+fn header_path(header: &str) -> String {
+    header.split('\t').next().unwrap_or(header).to_string()
+}
+
+/// Reads `@@ -a,b +c,d @@`, returning the source line count and target start and count.
+fn parse_hunk_header(line: &str) -> Option<(usize, usize, usize)> {
+    let ranges = line.strip_prefix("@@ ")?.split(" @@").next()?;
+    let (old, new) = ranges.split_once(' ')?;
+
+    // A missing count means a single line
+    let parse_range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (_, old_count) = parse_range(old.strip_prefix('-')?)?;
+    let (new_start, new_count) = parse_range(new.strip_prefix('+')?)?;
+
+    Some((old_count, new_start, new_count))
+}
+
+/// Prefixes a path for git-style headers, dropping a leading `./` or `/`.
+fn git_path(prefix: &str, path: &str) -> String {
+    let path = path
+        .strip_prefix("./")
+        .unwrap_or(path)
+        .trim_start_matches('/');
+    format!("{}/{}", prefix, path)
+}
+
+/// One side of a diff.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Source,
+    Target,
+}
+
+/// Reconstructs the plain text of one side of the diff, optionally limited to a range.
+///
+/// The source side consists of the deleted and unchanged lines, the target side of the
+/// inserted and unchanged lines. Diff prefixes are not included.
+pub fn extract_side(
+    diff_lines: &[DiffLine],
+    side: Side,
+    line_range: Option<(usize, usize)>,
+) -> String {
+    let (start, end) = line_range.unwrap_or((0, usize::MAX));
+    let skipped_tag = match side {
+        Side::Source => ChangeTag::Insert,
+        Side::Target => ChangeTag::Delete,
+    };
+
+    diff_lines
+        .iter()
+        .enumerate()
+        .filter(|(i, line)| *i >= start && *i <= end && line.tag != skipped_tag)
+        .map(|(_, line)| {
+            if line.missing_newline {
+                line.content.clone()
+            } else {
+                format!("{}\n", line.content)
+            }
+        })
+        .collect()
+}
+
+pub fn copy_to_clipboard(clipboard: &mut Clipboard, patch: &str) -> Result<(), String> {
+    clipboard
+        .set_text(patch)
+        .map_err(|e| format!("Failed to copy to clipboard: {}", e))
+}
+
+/// Puts HTML on the clipboard with the plain text as alternative. Returns false when the
+/// platform only took the plain text.
+pub fn copy_html_to_clipboard(
+    clipboard: &mut Clipboard,
+    html: &str,
+    plain: &str,
+) -> Result<bool, String> {
+    if clipboard.set_html(html, Some(plain)).is_ok() {
+        return Ok(true);
+    }
+
+    copy_to_clipboard(clipboard, plain).map(|_| false)
+}
+
+/// Reads the text currently on the system clipboard, failing when there is none.
+pub fn read_clipboard() -> Result<String, String> {
+    let mut clipboard =
+        Clipboard::new().map_err(|_| "Clipboard not available in this environment".to_string())?;
+
+    match clipboard.get_text() {
+        Ok(text) if !text.is_empty() => Ok(text),
+        Ok(_) | Err(arboard::Error::ContentNotAvailable) => {
+            Err("Clipboard is empty or doesn't contain text".to_string())
+        }
+        Err(e) => Err(format!("Failed to read from clipboard: {}", e)),
+    }
+}
+
+/// How text ended up on the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CopyMethod {
+    System,
+    Osc52,
+}
+
+impl CopyMethod {
+    /// Suffix for status messages so users know which path was taken.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            CopyMethod::System => "",
+            CopyMethod::Osc52 => " via OSC 52",
+        }
+    }
+}
+
+/// Settles how a copy went given what the system clipboard made of it, `None` when there
+/// is no clipboard. When it's missing or failed, which arboard does over SSH, the text goes
+/// to `terminal` via OSC 52 instead, if there's a terminal to write to.
+pub fn copy_with_fallback<W: Write>(
+    system: Option<Result<(), String>>,
+    terminal: Option<&mut W>,
+    text: &str,
+    tmux: bool,
+) -> Result<CopyMethod, String> {
+    match (system, terminal) {
+        (Some(Ok(())), _) => Ok(CopyMethod::System),
+        (_, Some(writer)) => write_osc52(writer, text, tmux)
+            .map(|_| CopyMethod::Osc52)
+            .map_err(|e| format!("Failed to copy via OSC 52: {}", e)),
+        (Some(Err(e)), None) => Err(e),
+        (None, None) => Err("Clipboard not available in this environment".to_string()),
+    }
+}
+
+/// Asks the terminal to put `text` on the clipboard using the OSC 52 escape sequence.
+///
+/// This works over SSH as long as the terminal emulator supports it. Inside tmux the
+/// sequence must be wrapped in a passthrough, otherwise tmux swallows it.
+pub fn write_osc52<W: Write>(writer: &mut W, text: &str, tmux: bool) -> io::Result<()> {
+    let encoded = STANDARD.encode(text);
+
+    if tmux {
+        write!(writer, "\x1bPtmux;\x1b\x1b]52;c;{}\x07\x1b\\", encoded)?;
+    } else {
+        write!(writer, "\x1b]52;c;{}\x07", encoded)?;
+    }
+
+    writer.flush()
+}
+
+/// Writes the patch to a new timestamped file in `dir` (the current directory when `None`)
+/// and returns its name. A counter is added to the name when the file already exists.
+pub fn export_to_file(patch: &str, dir: Option<&Path>) -> Result<String, String> {
+    let path = export_path("diff", dir)?;
+    let stem = path.strip_suffix(".patch").unwrap_or(&path);
+
+    // Timestamps can collide on fast machines, never overwrite an earlier export
+    let mut attempt = 0;
+    loop {
+        let filename = match attempt {
+            0 => path.clone(),
+            n => format!("{}_{}.patch", stem, n),
+        };
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&filename)
+        {
+            Ok(mut file) => {
+                file.write_all(patch.as_bytes())
+                    .map_err(|e| format!("Failed to write to file: {}", e))?;
+                return Ok(filename);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(format!("Failed to write to file: {}", e)),
+        }
+    }
+}
+
+/// Renders the diff as a `<pre>` block with inline colors, for pasting into emails.
+///
+/// Inline styles are used because mail clients tend to drop `<style>` elements.
+pub fn to_html(source_file: &str, target_file: &str, diff_lines: &[DiffLine]) -> String {
+    let mut html = String::from(
+        "<pre style=\"font-family: monospace; background-color: #ffffff; color: #24292f\">\n",
+    );
+    html.push_str(&format!(
+        "<span style=\"font-weight: bold\">--- {}\n+++ {}</span>\n",
+        escape_html(source_file),
+        escape_html(target_file)
+    ));
+
+    for diff_line in diff_lines {
+        let (prefix, style) = match diff_line.tag {
+            ChangeTag::Delete => ("-", " style=\"color: #82071e; background-color: #ffebe9\""),
+            ChangeTag::Insert => ("+", " style=\"color: #116329; background-color: #dafbe1\""),
+            ChangeTag::Equal => (" ", ""),
+        };
+        html.push_str(&format!(
+            "<span{}>{}{}</span>\n",
+            style,
+            prefix,
+            escape_html(&diff_line.content)
+        ));
+    }
+
+    html.push_str("</pre>\n");
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Number of added and removed lines in a diff.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChangeCounts {
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+pub fn count_changes(diff_lines: &[DiffLine]) -> ChangeCounts {
+    let mut counts = ChangeCounts::default();
+    for line in diff_lines {
+        match line.tag {
+            ChangeTag::Insert => counts.insertions += 1,
+            ChangeTag::Delete => counts.deletions += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+    counts
+}
+
+/// How a file pair changed, for the `--stat` summary.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatChange {
+    Lines(ChangeCounts),
+    /// Sizes in bytes before and after
+    Binary(usize, usize),
+}
+
+/// Formats the diff as two columns like `diff -y`, the source on the left and the target
+/// on the right, fitting in `width` columns. The gutter between them shows `|` for a
+/// changed line, `<` for a deleted and `>` for an inserted line.
+pub fn format_side_by_side(
+    source_file: &str,
+    target_file: &str,
+    diff_lines: &[DiffLine],
+    width: usize,
+    tab_width: usize,
+) -> String {
+    // Three columns go to the gutter, the rest is split evenly
+    let column_width = width.saturating_sub(3) / 2;
+    let row = |left: &str, marker: char, right: &str| {
+        let line = format!(
+            "{} {} {}",
+            side_by_side_column(left, column_width, tab_width),
+            marker,
+            side_by_side_column(right, column_width, tab_width)
+        );
+        format!("{}\n", line.trim_end())
+    };
+
+    let mut output = row(source_file, ' ', target_file);
+    output.push_str(&format!("{}\n", "-".repeat(column_width * 2 + 3)));
+    for diff_row in side_by_side_rows(diff_lines) {
+        let DiffRow::Pair { source, target } = diff_row else {
+            continue;
+        };
+        let content = |idx: Option<usize>| idx.map_or("", |idx| diff_lines[idx].content.as_str());
+        let marker = match (source, target) {
+            (Some(source), Some(target)) if source == target => ' ',
+            (Some(_), Some(_)) => '|',
+            (Some(_), None) => '<',
+            _ => '>',
+        };
+        output.push_str(&row(content(source), marker, content(target)));
+    }
+    output
+}
+
+/// Fits a line into a column `width` terminal columns wide, cutting it off or padding it
+/// with spaces. Tabs are expanded first and wide characters count twice, so neither can
+/// push the other column out of line.
+pub fn side_by_side_column(content: &str, width: usize, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    let mut column = String::new();
+    let mut length = 0;
+
+    for c in content.chars() {
+        let expanded = if c == '\t' {
+            tab_width - length % tab_width
+        } else {
+            c.width().unwrap_or(0)
+        };
+        if length + expanded > width {
+            break;
+        }
+        if c == '\t' {
+            column.push_str(&" ".repeat(expanded));
+        } else {
+            column.push(c);
+        }
+        length += expanded;
+    }
+
+    column.push_str(&" ".repeat(width - length));
+    column
+}
+
+/// Formats a summary like `git diff --stat`: a `name | 7 +++----` row per file, with the
+/// bars scaled down to fit `width`, and a totals line.
+pub fn format_stat(entries: &[(String, StatChange)], width: usize) -> String {
+    let name_width = entries
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let totals = |change: &StatChange| match change {
+        StatChange::Lines(counts) => counts.insertions + counts.deletions,
+        StatChange::Binary(..) => 0,
+    };
+    let max_total = entries
+        .iter()
+        .map(|(_, change)| totals(change))
+        .max()
+        .unwrap_or(0);
+    let count_width = max_total.to_string().len();
+
+    // " name | count " comes before the bar
+    let bar_width = width.saturating_sub(name_width + count_width + 5).max(1);
+    let scale = |lines: usize| {
+        if max_total <= bar_width || lines == 0 {
+            lines
+        } else {
+            (lines * bar_width / max_total).max(1)
+        }
+    };
+
+    let mut output = String::new();
+    let mut total = ChangeCounts::default();
+    for (name, change) in entries {
+        let padding = " ".repeat(name_width - name.chars().count());
+        match change {
+            StatChange::Lines(counts) => {
+                total.insertions += counts.insertions;
+                total.deletions += counts.deletions;
+                output.push_str(&format!(
+                    " {}{} | {:>count_width$} {}{}\n",
+                    name,
+                    padding,
+                    counts.insertions + counts.deletions,
+                    "+".repeat(scale(counts.insertions)),
+                    "-".repeat(scale(counts.deletions)),
+                ));
+            }
+            StatChange::Binary(before, after) => {
+                output.push_str(&format!(
+                    " {}{} | Bin {} -> {} bytes\n",
+                    name, padding, before, after
+                ));
+            }
+        }
+    }
+
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    output.push_str(&format!(
+        " {} file{} changed",
+        entries.len(),
+        plural(entries.len())
+    ));
+    if total.insertions > 0 {
+        output.push_str(&format!(
+            ", {} insertion{}(+)",
+            total.insertions,
+            plural(total.insertions)
+        ));
+    }
+    if total.deletions > 0 {
+        output.push_str(&format!(
+            ", {} deletion{}(-)",
+            total.deletions,
+            plural(total.deletions)
+        ));
+    }
+    output.push('\n');
+
+    output
+}
+
+/// Wraps a patch in a fenced ```` ```diff ```` block so Markdown renderers color it.
+///
+/// The fence is made longer than any run of backticks in the patch, so those can't
+/// close the block early.
+pub fn to_markdown(patch: &str) -> String {
+    let longest_run = patch.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat((longest_run + 1).max(3));
+
+    let newline = if patch.ends_with('\n') || patch.is_empty() {
+        ""
+    } else {
+        "\n"
+    };
+    format!("{}diff\n{}{}{}\n", fence, patch, newline, fence)
+}
+
+/// Appends a patch to a file, creating it first if needed. Used to build up a patch series.
+pub fn append_to_file(filename: &str, patch: &str) -> Result<(), String> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(filename)
+        .map_err(|e| format!("Failed to open '{}': {}", filename, e))?;
+
+    file.write_all(patch.as_bytes())
+        .map_err(|e| format!("Failed to write to file: {}", e))
+}
+
+/// A `.patch` file name for an export, made unique by a high-precision timestamp.
+///
+/// With an export directory the directory is created when missing and the full path is
+/// returned, so the status message tells where the file went.
+pub fn export_path(prefix: &str, dir: Option<&Path>) -> Result<String, String> {
+    let filename = export_filename(prefix)?;
+
+    let Some(dir) = dir else {
+        return Ok(filename);
+    };
+
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create '{}': {}", dir.display(), e))?;
+    let dir = fs::canonicalize(dir)
+        .map_err(|e| format!("Failed to resolve '{}': {}", dir.display(), e))?;
+
+    Ok(dir.join(filename).display().to_string())
+}
+
+/// A timestamped patch name like `diff_1700000000000000000.patch`.
+pub fn export_filename(prefix: &str) -> Result<String, String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| format!("Failed to get timestamp: {}", e))?
+        .as_nanos();
+    Ok(format!("{}_{}.patch", prefix, timestamp))
+}
+
+/// The first `head` and last `tail` lines of `text`, with a marker for the lines in between
+/// wrapped in `ellipsis`.
+pub fn excerpt(text: &str, head: usize, tail: usize, ellipsis: &str) -> Vec<String> {
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.len() <= head + tail {
+        return lines.iter().map(|line| line.to_string()).collect();
+    }
+
+    let skipped = lines.len() - head - tail;
+    let mut excerpt: Vec<String> = lines[..head].iter().map(|line| line.to_string()).collect();
+    excerpt.push(format!("{} {} more lines {}", ellipsis, skipped, ellipsis));
+    excerpt.extend(
+        lines[lines.len() - tail..]
+            .iter()
+            .map(|line| line.to_string()),
+    );
+    excerpt
+}
+
+pub fn validate_file(path: &str, file_type: &str) -> Result<(), String> {
+    let file_path = Path::new(path);
+
+    if !file_path.exists() {
+        return Err(format!("{} file '{}' does not exist", file_type, path));
+    }
+
+    if !file_path.is_file() {
+        return Err(format!("{} path '{}' is not a file", file_type, path));
+    }
+
+    Ok(())
+}
+
+/// Fails when the path itself is a symlink, for `--no-follow-symlinks`.
+pub fn reject_symlink(path: &str) -> Result<(), String> {
+    let is_symlink = fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_symlink());
+    if is_symlink {
+        return Err(format!(
+            "'{}' is a symlink, which --no-follow-symlinks refuses to diff through",
+            path
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::Mutex;
+
+    // Mutex to serialize clipboard access during tests
+    static CLIPBOARD_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Writes a source and a target file into a temporary directory of their own. The
+    /// directory is removed when the returned `TempDir` is dropped, so a failing test
+    /// doesn't leave the files behind.
+    fn create_test_files()
+    -> Result<(assert_fs::TempDir, String, String), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let source_path = dir.path().join("source.txt");
+        let target_path = dir.path().join("target.txt");
+
+        fs::write(&source_path, b"Line 1\nLine 2\nLine 3\nLine to remove\n")?;
+        fs::write(
+            &target_path,
+            b"Line 1\nLine 2 modified\nLine 3\nLine added\n",
+        )?;
+
+        Ok((
+            dir,
+            source_path.display().to_string(),
+            target_path.display().to_string(),
+        ))
+    }
+
+    #[test]
+    fn test_decode_content_valid_utf8() {
+        let content = decode_content("héllo\n".as_bytes());
+
+        assert_eq!(content.text, "héllo\n");
+        assert!(!content.lossy);
+        assert!(!content.binary);
+    }
+
+    #[test]
+    fn test_decode_content_latin1_is_lossy() {
+        // "caf\xe9" is "café" encoded as Latin-1
+        let content = decode_content(b"caf\xe9\n");
+
+        assert_eq!(content.text, "caf\u{FFFD}\n");
+        assert!(content.lossy);
+        assert!(!content.binary);
+    }
+
+    #[test]
+    fn test_decode_content_detects_binary() {
+        let content = decode_content(b"\x7fELF\x00\x01\x02");
+
+        assert!(content.binary);
+        assert_eq!(
+            content.text,
+            "00000000  7f 45 4c 46 00 01 02                              |.ELF...|\n"
+        );
+    }
+
+    #[test]
+    fn test_hex_dump_rows() {
+        let bytes: Vec<u8> = (0x41..0x53).collect();
+
+        assert_eq!(
+            hex_dump(&bytes),
+            "00000000  41 42 43 44 45 46 47 48  49 4a 4b 4c 4d 4e 4f 50  |ABCDEFGHIJKLMNOP|\n\
+             00000010  51 52                                             |QR|\n"
+        );
+        assert_eq!(hex_dump(b""), "");
+    }
+
+    fn lines_from_tags(tags: &[ChangeTag]) -> Vec<DiffLine> {
+        tags.iter()
+            .enumerate()
+            .map(|(i, tag)| DiffLine {
+                tag: *tag,
+                content: format!("line {}", i),
+                new_line: None,
+                missing_newline: false,
+                kind: LineKind::Standalone,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fold_rows_folds_unchanged_run_between_changes() {
+        use ChangeTag::{Delete, Equal, Insert};
+        let diff_lines = lines_from_tags(&[
+            Delete, Equal, Equal, Equal, Equal, Equal, Equal, Equal, Insert,
+        ]);
+
+        let rows = fold_rows(&diff_lines, Context::symmetric(2), &HashSet::new());
+
+        assert_eq!(
+            rows,
+            vec![
+                DiffRow::Line(0),
+                DiffRow::Line(1),
+                DiffRow::Line(2),
+                DiffRow::Fold { start: 3, end: 5 },
+                DiffRow::Line(6),
+                DiffRow::Line(7),
+                DiffRow::Line(8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hunks_include_context_and_merge_when_it_overlaps() {
+        use ChangeTag::{Delete, Equal, Insert};
+        let diff_lines = lines_from_tags(&[
+            Equal, Equal, Delete, Insert, Equal, Equal, Insert, Equal, Equal, Equal, Equal, Equal,
+            Delete,
+        ]);
+
+        assert_eq!(
+            hunks(&diff_lines, Context::symmetric(1)),
+            vec![(1, 7), (11, 12)]
+        );
+        assert_eq!(
+            hunks(&diff_lines, Context::symmetric(2)),
+            vec![(0, 8), (10, 12)]
+        );
+    }
+
+    #[test]
+    fn test_changes_only_rows_keep_context_around_changes() {
+        use ChangeTag::{Delete, Equal, Insert};
+        let diff_lines = lines_from_tags(&[
+            Equal, Equal, Equal, Delete, Equal, Equal, Equal, Equal, Equal, Insert, Equal,
+        ]);
+
+        let rows = changes_only_rows(&diff_lines, 1);
+
+        assert_eq!(
+            rows,
+            [2, 3, 4, 8, 9, 10].map(DiffRow::Line).to_vec(),
+            "unchanged lines further than the context from a change are left out"
+        );
+        assert!(changes_only_rows(&lines_from_tags(&[Equal, Equal]), 2).is_empty());
+    }
+
+    #[test]
+    fn test_hunk_rows_put_dividers_between_hunks() {
+        use ChangeTag::{Equal, Insert};
+        let diff_lines = lines_from_tags(&[
+            Equal, Equal, Equal, Insert, Equal, Equal, Equal, Equal, Insert, Equal,
+        ]);
+
+        let rows = hunk_rows(&diff_lines, Context::symmetric(1));
+
+        assert_eq!(
+            rows,
+            vec![
+                DiffRow::Skip { start: 0, end: 1 },
+                DiffRow::Line(2),
+                DiffRow::Line(3),
+                DiffRow::Line(4),
+                DiffRow::Skip { start: 5, end: 6 },
+                DiffRow::Line(7),
+                DiffRow::Line(8),
+                DiffRow::Line(9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fold_rows_folds_leading_and_trailing_runs() {
+        use ChangeTag::{Equal, Insert};
+        let diff_lines = lines_from_tags(&[Equal, Equal, Equal, Insert, Equal, Equal, Equal]);
+
+        let rows = fold_rows(&diff_lines, Context::symmetric(1), &HashSet::new());
+
+        assert_eq!(
+            rows,
+            vec![
+                DiffRow::Fold { start: 0, end: 1 },
+                DiffRow::Line(2),
+                DiffRow::Line(3),
+                DiffRow::Line(4),
+                DiffRow::Fold { start: 5, end: 6 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fold_rows_with_asymmetric_context() {
+        use ChangeTag::{Delete, Equal, Insert};
+        let diff_lines = lines_from_tags(&[
+            Delete, Equal, Equal, Equal, Equal, Equal, Equal, Equal, Insert,
+        ]);
+        let context = Context {
+            before: 3,
+            after: 1,
+        };
+
+        let rows = fold_rows(&diff_lines, context, &HashSet::new());
+
+        // One line kept after the delete, three in front of the insert
+        assert_eq!(
+            rows,
+            vec![
+                DiffRow::Line(0),
+                DiffRow::Line(1),
+                DiffRow::Fold { start: 2, end: 4 },
+                DiffRow::Line(5),
+                DiffRow::Line(6),
+                DiffRow::Line(7),
+                DiffRow::Line(8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fold_rows_keeps_short_runs_and_expanded_folds() {
+        use ChangeTag::{Delete, Equal};
+        let diff_lines = lines_from_tags(&[Delete, Equal, Equal, Equal, Delete]);
+
+        // Only one line would be hidden, so nothing gets folded
+        let rows = fold_rows(&diff_lines, Context::symmetric(1), &HashSet::new());
+        assert_eq!(rows, unfolded_rows(&diff_lines));
+
+        let expanded = HashSet::from([1]);
+        let rows = fold_rows(&diff_lines, Context::symmetric(0), &expanded);
+        assert_eq!(rows, unfolded_rows(&diff_lines));
+    }
+
+    #[test]
+    fn test_split_conflict_sides() {
+        let content = "start\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> branch\nend\n";
+
+        let (ours, theirs) = split_conflict_sides(content).unwrap().unwrap();
+
+        assert_eq!(ours, "start\nours\nend\n");
+        assert_eq!(theirs, "start\ntheirs\nend\n");
+    }
+
+    #[test]
+    fn test_split_conflict_sides_drops_diff3_base() {
+        let content = "<<<<<<< HEAD\nours\n||||||| base\noriginal\n=======\ntheirs\n>>>>>>> b\n";
+
+        let (ours, theirs) = split_conflict_sides(content).unwrap().unwrap();
+
+        assert_eq!(ours, "ours\n");
+        assert_eq!(theirs, "theirs\n");
+    }
+
+    #[test]
+    fn test_split_conflict_sides_without_markers() {
+        assert!(split_conflict_sides("plain\ncontent\n").unwrap().is_none());
+        // Setext headings look like markers but aren't
+        assert!(
+            split_conflict_sides("Title\n=======\n\nMore\n>>>>>>>\n")
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_split_conflict_sides_needs_exact_markers() {
+        let content = "<<<<<<< HEAD\nours\n==========\n=======\r\ntheirs\n>>>>>>>\n";
+
+        let (ours, theirs) = split_conflict_sides(content).unwrap().unwrap();
+
+        assert_eq!(ours, "ours\n==========\n");
+        assert_eq!(theirs, "theirs\n");
+    }
+
+    #[test]
+    fn test_split_conflict_sides_rejects_unterminated_conflict() {
+        assert!(split_conflict_sides("<<<<<<< HEAD\nours\n=======\n").is_err());
+    }
+
+    #[test]
+    fn test_format_line() {
+        use ChangeTag::{Delete, Equal};
+        let diff_lines = lines_from_tags(&[Delete, Equal]);
+
+        assert_eq!(format_line(&diff_lines[0], true), "-line 0");
+        assert_eq!(format_line(&diff_lines[0], false), "line 0");
+        assert_eq!(format_line(&diff_lines[1], true), " line 1");
+    }
+
+    #[test]
+    fn test_extract_side() {
+        let diff_lines = generate_diff("a\nold\nc\n", "a\nnew\nc\nadded\n", Algorithm::Myers);
+
+        assert_eq!(extract_side(&diff_lines, Side::Source, None), "a\nold\nc\n");
+        assert_eq!(
+            extract_side(&diff_lines, Side::Target, None),
+            "a\nnew\nc\nadded\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_side_with_range() {
+        let diff_lines = generate_diff("a\nold\nc\n", "a\nnew\nc\n", Algorithm::Myers);
+
+        // Lines 1-2 are the deletion of "old" and the insertion of "new"
+        assert_eq!(
+            extract_side(&diff_lines, Side::Source, Some((1, 2))),
+            "old\n"
+        );
+        assert_eq!(
+            extract_side(&diff_lines, Side::Target, Some((1, 2))),
+            "new\n"
+        );
+    }
+
+    #[test]
+    fn test_decode_counts_bytes_and_lines() {
+        let content = decode_content(b"one\ntwo\nthree");
+        assert_eq!(
+            content.stats,
+            FileStats {
+                bytes: 13,
+                lines: Some(3)
+            }
+        );
+
+        assert_eq!(decode_content(b"one\n").stats.lines, Some(1));
+        assert_eq!(decode_content(b"").stats.lines, Some(0));
+        assert_eq!(decode_content(b"\x00\x01").stats.lines, None);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MB");
+    }
+
+    #[test]
+    fn test_parse_algorithm() {
+        assert_eq!(parse_algorithm("myers"), Ok(Algorithm::Myers));
+        assert_eq!(parse_algorithm("patience"), Ok(Algorithm::Patience));
+        assert_eq!(parse_algorithm("lcs"), Ok(Algorithm::Lcs));
+        assert!(parse_algorithm("histogram").is_err());
+    }
+
+    #[test]
+    fn test_every_algorithm_reproduces_both_sides() {
+        let source = "fn a() {}\n\nfn b() {}\n\nfn c() {}\n";
+        let target = "fn c() {}\n\nfn a() {}\n\nfn b() {}\n";
+
+        for name in ALGORITHM_NAMES {
+            let diff_lines = generate_diff(source, target, parse_algorithm(name).unwrap());
+
+            assert_eq!(extract_side(&diff_lines, Side::Source, None), source);
+            assert_eq!(extract_side(&diff_lines, Side::Target, None), target);
+        }
+    }
+
+    #[test]
+    fn test_cancel_stops_the_diff_algorithm() {
+        let old: Vec<String> = (0..1000).map(|i| format!("{}\n", i)).collect();
+        let new: Vec<String> = (0..1000).map(|i| format!("{}\n", i * 7 % 1000)).collect();
+        let cancel = AtomicBool::new(true);
+        let mut hook = Cancellable {
+            inner: Capture::new(),
+            cancel: &cancel,
+        };
+
+        let result = algorithms::diff_deadline(
+            Algorithm::Myers,
+            &mut hook,
+            &old,
+            0..1000,
+            &new,
+            0..1000,
+            None,
+        );
+
+        assert!(result.is_err());
+        assert!(hook.inner.into_ops().is_empty());
+    }
+
+    #[test]
+    fn test_generate_diff_cancellable_stops_when_cancelled() {
+        let cancel = AtomicBool::new(true);
+
+        assert!(
+            generate_diff_cancellable("a\n", "b\n", Algorithm::Myers, false, &cancel).is_none()
+        );
+
+        cancel.store(false, Ordering::Relaxed);
+        let diff_lines =
+            generate_diff_cancellable("a\n", "b\n", Algorithm::Myers, false, &cancel).unwrap();
+        assert_eq!(diff_lines.len(), 2);
+    }
+
+    #[test]
+    fn test_side_by_side_rows_pairs_changes() {
+        use ChangeTag::{Delete, Equal, Insert};
+        let diff_lines = lines_from_tags(&[Equal, Delete, Delete, Insert, Equal, Insert]);
+
+        let rows = side_by_side_rows(&diff_lines);
+
+        let pair = |source, target| DiffRow::Pair { source, target };
+        assert_eq!(
+            rows,
+            vec![
+                pair(Some(0), Some(0)),
+                pair(Some(1), Some(3)),
+                pair(Some(2), None),
+                pair(Some(4), Some(4)),
+                pair(None, Some(5)),
+            ]
+        );
+        assert!(rows[1].contains(3));
+        assert!(!rows[1].contains(2));
+    }
+
+    #[test]
+    fn test_generate_diff_tracks_line_numbers() {
+        let diff_lines = generate_diff("a\nold\nc\n", "a\nnew\nc\n", Algorithm::Myers);
+
+        let numbers: Vec<Option<usize>> = diff_lines.iter().map(|line| line.new_line).collect();
+        assert_eq!(numbers, vec![Some(1), None, Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn test_generate_diff_keeps_common_prefix_and_suffix_intact() {
+        let diff_lines = generate_diff(
+            "a\nb\nold\nc\r\nlast",
+            "a\nb\nnew\nextra\nc\r\nlast",
+            Algorithm::Myers,
+        );
+
+        let summary: Vec<(ChangeTag, &str, Option<usize>, bool)> = diff_lines
+            .iter()
+            .map(|line| {
+                let content = line.content.as_str();
+                (line.tag, content, line.new_line, line.missing_newline)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (ChangeTag::Equal, "a", Some(1), false),
+                (ChangeTag::Equal, "b", Some(2), false),
+                (ChangeTag::Delete, "old", None, false),
+                (ChangeTag::Insert, "new", Some(3), false),
+                (ChangeTag::Insert, "extra", Some(4), false),
+                (ChangeTag::Equal, "c", Some(5), false),
+                (ChangeTag::Equal, "last", Some(6), true),
+            ]
+        );
+    }
+
+    /// Tag, content and missing newline marker of every line, for compact assertions.
+    fn summarize(diff_lines: &[DiffLine]) -> Vec<(ChangeTag, &str, bool)> {
+        diff_lines
+            .iter()
+            .map(|line| (line.tag, line.content.as_str(), line.missing_newline))
+            .collect()
+    }
+
+    #[test]
+    fn test_generate_diff_keeps_blank_changed_lines() {
+        let diff_lines = generate_diff("a\nb\n", "a\n\nb\n", Algorithm::Myers);
+
+        assert_eq!(
+            summarize(&diff_lines),
+            vec![
+                (ChangeTag::Equal, "a", false),
+                (ChangeTag::Insert, "", false),
+                (ChangeTag::Equal, "b", false),
+            ]
+        );
+        assert_eq!(diff_lines[1].new_line, Some(2));
+
+        let diff_lines = generate_diff("a\n\n\nb\n", "a\nb\n", Algorithm::Myers);
+        let deleted = diff_lines
+            .iter()
+            .filter(|line| line.tag == ChangeTag::Delete);
+        assert_eq!(deleted.count(), 2);
+    }
 
-    for change in diff.iter_all_changes() {
-        let tag = change.tag();
-        let content = change.value().to_string();
+    #[test]
+    fn test_generate_diff_change_without_trailing_newline() {
+        let diff_lines = generate_diff("a\nold", "a\nnew", Algorithm::Myers);
 
-        // Split multi-line changes into separate lines
-        for line in content.lines() {
-            diff_lines.push(DiffLine {
-                tag,
-                content: line.to_string(),
-            });
-        }
+        assert_eq!(
+            summarize(&diff_lines),
+            vec![
+                (ChangeTag::Equal, "a", false),
+                (ChangeTag::Delete, "old", true),
+                (ChangeTag::Insert, "new", true),
+            ]
+        );
     }
 
-    diff_lines
-}
+    #[test]
+    fn test_generate_diff_change_with_empty_final_line() {
+        let diff_lines = generate_diff("a\nb\n", "a\nb\n\n", Algorithm::Myers);
 
-pub fn generate_patch(
-    source_file: &str,
-    target_file: &str,
-    diff_lines: &[DiffLine],
-    line_range: Option<(usize, usize)>,
-) -> String {
-    let mut patch = String::new();
+        assert_eq!(
+            summarize(&diff_lines),
+            vec![
+                (ChangeTag::Equal, "a", false),
+                (ChangeTag::Equal, "b", false),
+                (ChangeTag::Insert, "", false),
+            ]
+        );
+        assert_eq!(diff_lines[2].new_line, Some(3));
+    }
 
-    // Add patch header
-    patch.push_str(&format!("--- {}\n", source_file));
-    patch.push_str(&format!("+++ {}\n", target_file));
+    #[test]
+    fn test_empty_files_are_identical() {
+        let diff_lines = generate_diff("", "", Algorithm::Myers);
 
-    // Determine which lines to include
-    let lines_to_include: Vec<&DiffLine> = match line_range {
-        Some((start, end)) => {
-            // Filter diff_lines to only include the selected range
-            diff_lines
-                .iter()
-                .enumerate()
-                .filter(|(i, _)| *i >= start && *i <= end)
-                .map(|(_, line)| line)
-                .collect()
-        }
-        None => diff_lines.iter().collect(),
-    };
-
-    // Add diff lines in unified format
-    for diff_line in lines_to_include {
-        let prefix = match diff_line.tag {
-            ChangeTag::Delete => "-",
-            ChangeTag::Insert => "+",
-            ChangeTag::Equal => " ",
-        };
-        patch.push_str(&format!("{}{}\n", prefix, diff_line.content));
+        assert!(diff_lines.is_empty());
+        assert!(is_identical(&diff_lines));
     }
 
-    patch
-}
+    #[test]
+    fn test_identical_files_are_identical() {
+        let diff_lines = generate_diff("one\ntwo\n", "one\ntwo\n", Algorithm::Myers);
 
-pub fn copy_to_clipboard(clipboard: &mut Clipboard, patch: &str) -> Result<(), String> {
-    clipboard
-        .set_text(patch)
-        .map_err(|e| format!("Failed to copy to clipboard: {}", e))
-}
+        assert_eq!(diff_lines.len(), 2);
+        assert!(is_identical(&diff_lines));
+        assert!(!is_identical(&generate_diff(
+            "one\n",
+            "two\n",
+            Algorithm::Myers
+        )));
+    }
 
-pub fn export_to_file(patch: &str) -> Result<String, String> {
-    // Generate filename with high-precision timestamp to avoid collisions
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| format!("Failed to get timestamp: {}", e))?
-        .as_nanos();
+    #[test]
+    fn test_write_osc52() {
+        let mut output = Vec::new();
 
-    let filename = format!("diff_{}.patch", timestamp);
+        write_osc52(&mut output, "hello", false).unwrap();
 
-    // Write patch to file (fs::write handles flushing automatically)
-    fs::write(&filename, patch.as_bytes())
-        .map_err(|e| format!("Failed to write to file: {}", e))?;
+        assert_eq!(output, b"\x1b]52;c;aGVsbG8=\x07");
+    }
 
-    Ok(filename)
-}
+    #[test]
+    fn test_write_osc52_wraps_for_tmux() {
+        let mut output = Vec::new();
 
-pub fn validate_file(path: &str, file_type: &str) -> Result<(), String> {
-    let file_path = Path::new(path);
+        write_osc52(&mut output, "hello", true).unwrap();
 
-    if !file_path.exists() {
-        return Err(format!("{} file '{}' does not exist", file_type, path));
+        assert_eq!(output, b"\x1bPtmux;\x1b\x1b]52;c;aGVsbG8=\x07\x1b\\");
     }
 
-    if !file_path.is_file() {
-        return Err(format!("{} path '{}' is not a file", file_type, path));
-    }
+    #[test]
+    fn test_ignoring_whitespace_keeps_the_source_lines() {
+        let source = "a  b\nkeep\n\tend";
+        let target = "a b\nchanged\nend";
 
-    Ok(())
-}
+        let lines = generate_diff_ignoring_whitespace(source, target, Algorithm::Myers);
+        let summary: Vec<(ChangeTag, &str, Option<usize>)> = lines
+            .iter()
+            .map(|line| (line.tag, line.content.as_str(), line.new_line))
+            .collect();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use std::io::Write;
-    use std::sync::Mutex;
+        assert_eq!(
+            summary,
+            vec![
+                (ChangeTag::Equal, "a  b", Some(1)),
+                (ChangeTag::Delete, "keep", None),
+                (ChangeTag::Insert, "changed", Some(2)),
+                (ChangeTag::Equal, "\tend", Some(3)),
+            ]
+        );
+        // A missing newline at the end is still a change
+        assert_eq!(
+            generate_diff_ignoring_whitespace("a\n", "a", Algorithm::Myers).len(),
+            2
+        );
+    }
 
-    // Mutex to serialize clipboard access during tests
-    static CLIPBOARD_LOCK: Mutex<()> = Mutex::new(());
+    #[test]
+    fn test_copy_falls_back_to_osc52_when_the_clipboard_fails() {
+        let mut output = Vec::new();
+        let failed = Some(Err("Failed to copy to clipboard: no display".to_string()));
 
-    fn create_test_files() -> Result<(String, String), Box<dyn std::error::Error>> {
-        use std::thread;
-        use std::time::{SystemTime, UNIX_EPOCH};
+        let method = copy_with_fallback(failed.clone(), Some(&mut output), "hello", false);
 
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
-        let thread_id = format!("{:?}", thread::current().id());
-        let unique_suffix = format!(
-            "{}_{}",
-            timestamp,
-            thread_id.replace("ThreadId(", "").replace(")", "")
+        assert_eq!(method, Ok(CopyMethod::Osc52));
+        assert_eq!(output, b"\x1b]52;c;aGVsbG8=\x07");
+        assert_eq!(
+            copy_with_fallback::<Vec<u8>>(failed, None, "hello", false),
+            Err("Failed to copy to clipboard: no display".to_string())
         );
+    }
+
+    #[test]
+    fn test_copy_prefers_the_system_clipboard() {
+        let mut output = Vec::new();
+
+        let method = copy_with_fallback(Some(Ok(())), Some(&mut output), "hello", false);
 
-        let source_path = format!("test_source_{}_.txt", unique_suffix);
-        let target_path = format!("test_target_{}_.txt", unique_suffix);
+        assert_eq!(method, Ok(CopyMethod::System));
+        assert!(output.is_empty());
+        assert!(copy_with_fallback::<Vec<u8>>(None, None, "hello", false).is_err());
+    }
 
-        let mut source_file = fs::File::create(&source_path)?;
-        source_file.write_all(b"Line 1\nLine 2\nLine 3\nLine to remove\n")?;
+    #[test]
+    fn test_read_file_head_stops_at_the_limit() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, _target) = create_test_files()?;
+        fs::write(&source, "1\n2\n3\n4\n")?;
 
-        let mut target_file = fs::File::create(&target_path)?;
-        target_file.write_all(b"Line 1\nLine 2 modified\nLine 3\nLine added\n")?;
+        let (content, cut) = read_file_head(&source, 2)?;
 
-        Ok((source_path, target_path))
+        assert_eq!(content.text, "1\n2\n");
+        assert!(!cut);
+        // The stats describe what was read
+        assert_eq!(content.stats.bytes, 4);
+        assert_eq!(content.stats.lines, Some(2));
+        assert_eq!(read_file_head(&source, 10)?.0.text, "1\n2\n3\n4\n");
+        Ok(())
     }
 
-    fn cleanup_test_files(source: &str, target: &str) {
-        let _ = fs::remove_file(source);
-        let _ = fs::remove_file(target);
+    #[test]
+    fn test_read_file_head_stops_within_a_huge_line() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, source, _target) = create_test_files()?;
+        fs::write(&source, "\u{20ac}".repeat(HEAD_BYTES_PER_LINE))?;
+
+        let (content, cut) = read_file_head(&source, 1)?;
+
+        assert!(cut);
+        // The cut falls before the character it would split
+        assert_eq!(content.text.len(), HEAD_BYTES_PER_LINE - 1);
+        assert!(!content.lossy);
+        // A file that fits exactly isn't cut
+        fs::write(&source, "x".repeat(HEAD_BYTES_PER_LINE))?;
+        assert!(!read_file_head(&source, 1)?.1);
+        Ok(())
     }
 
     #[test]
     fn test_generate_patch() -> Result<(), Box<dyn std::error::Error>> {
-        let (source, target) = create_test_files()?;
+        let (_dir, source, target) = create_test_files()?;
         let source_content = fs::read_to_string(&source)?;
         let target_content = fs::read_to_string(&target)?;
 
-        let diff_lines = generate_diff(&source_content, &target_content);
-        let patch = generate_patch(&source, &target, &diff_lines, None);
+        let diff_lines = generate_diff(&source_content, &target_content, Algorithm::Myers);
+        let patch = generate_patch(
+            &source,
+            &target,
+            &diff_lines,
+            None,
+            PatchFormat::Plain,
+            Context::symmetric(3),
+        );
 
         // Verify patch header
         assert!(patch.contains(&format!("--- {}", source)));
@@ -168,22 +2382,620 @@ mod tests {
         // Verify patch contains added lines with + prefix
         assert!(patch.contains("+Line 2 modified"));
         assert!(patch.contains("+Line added"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_selection_patch_applies_on_its_own() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let target = "A\nb\nc\nd\ne\nf\nG\nh\ni\n";
+        let diff_lines = generate_diff(source, target, Algorithm::Myers);
+
+        // Only the change of g, with a line of context on either side
+        let start = diff_lines.iter().position(|l| l.content == "f").unwrap();
+        let end = diff_lines.iter().position(|l| l.content == "h").unwrap();
+        let patch = generate_patch(
+            "file.txt",
+            "file.txt",
+            &diff_lines,
+            Some((start, end)),
+            PatchFormat::Git,
+            Context::symmetric(3),
+        );
+        assert!(patch.contains("@@ -6,3 +6,3 @@\n f\n-g\n+G\n h\n"));
+
+        let dir = assert_fs::TempDir::new()?;
+        fs::write(dir.path().join("file.txt"), source)?;
+        fs::write(dir.path().join("selection.patch"), &patch)?;
+        let status = std::process::Command::new("git")
+            .args(["apply", "selection.patch"])
+            .current_dir(dir.path())
+            .status()?;
+
+        assert!(status.success());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("file.txt"))?,
+            "a\nb\nc\nd\ne\nf\nG\nh\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_whole_file_patch_applies() -> Result<(), Box<dyn std::error::Error>> {
+        let source: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        let target = source
+            .replace("line 2\n", "line 2 changed\n")
+            .replace("line 17\n", "")
+            + "line 21\n";
+        let diff_lines = generate_diff(&source, &target, Algorithm::Myers);
+
+        let patch = generate_patch(
+            "file.txt",
+            "file.txt",
+            &diff_lines,
+            None,
+            PatchFormat::Git,
+            Context::symmetric(3),
+        );
+        // One hunk for the change at the top, one for the two at the end
+        assert!(patch.contains("@@ -1,5 +1,5 @@\n"));
+        assert!(patch.contains("@@ -14,7 +14,7 @@\n"));
+
+        let dir = assert_fs::TempDir::new()?;
+        fs::write(dir.path().join("file.txt"), &source)?;
+        fs::write(dir.path().join("whole.patch"), &patch)?;
+        let status = std::process::Command::new("git")
+            .args(["apply", "--check", "whole.patch"])
+            .current_dir(dir.path())
+            .status()?;
+
+        assert!(status.success());
+        Ok(())
+    }
+
+    #[test]
+    fn test_whole_file_patch_uses_the_context() {
+        let diff_lines = generate_diff("a\nb\nc\nd\ne\n", "a\nb\nC\nd\ne\n", Algorithm::Myers);
+
+        let patch = generate_patch(
+            "old",
+            "new",
+            &diff_lines,
+            None,
+            PatchFormat::Plain,
+            Context {
+                before: 0,
+                after: 1,
+            },
+        );
+
+        assert_eq!(patch, "--- old\n+++ new\n@@ -3,2 +3,2 @@\n-c\n+C\n d\n");
+    }
+
+    #[test]
+    fn test_hunk_header_for_pure_insertion() {
+        let diff_lines = generate_diff("a\nb\n", "a\nb\nc\n", Algorithm::Myers);
+
+        assert_eq!(hunk_header(&diff_lines[2..], 2, 2), "@@ -2,0 +3,1 @@\n");
+        assert_eq!(hunk_header(&diff_lines[1..], 1, 1), "@@ -2,1 +2,2 @@\n");
+    }
+
+    #[test]
+    fn test_generate_patch_git_format() {
+        let diff_lines = generate_diff("old\n", "new\n", Algorithm::Myers);
+
+        let patch = generate_patch(
+            "./src/old.rs",
+            "/tmp/new.rs",
+            &diff_lines,
+            None,
+            PatchFormat::Git,
+            Context::symmetric(3),
+        );
+
+        assert!(patch.starts_with(
+            "diff --git a/src/old.rs b/tmp/new.rs\n\
+             index 0000000..0000000\n\
+             --- a/src/old.rs\n\
+             +++ b/tmp/new.rs\n"
+        ));
+        assert!(patch.ends_with("@@ -1,1 +1,1 @@\n-old\n+new\n"));
+    }
+
+    #[test]
+    fn test_generate_patch_keeps_blank_lines() {
+        let added = generate_diff("a\nb\n", "a\n\nb\n", Algorithm::Myers);
+        let removed = generate_diff("a\n\nb\n", "a\nb\n", Algorithm::Myers);
+
+        let patch = generate_patch(
+            "old",
+            "new",
+            &added,
+            None,
+            PatchFormat::Plain,
+            Context::symmetric(3),
+        );
+        assert_eq!(patch, "--- old\n+++ new\n@@ -1,2 +1,3 @@\n a\n+\n b\n");
+        let patch = generate_patch(
+            "old",
+            "new",
+            &removed,
+            None,
+            PatchFormat::Plain,
+            Context::symmetric(3),
+        );
+        assert_eq!(patch, "--- old\n+++ new\n@@ -1,3 +1,2 @@\n a\n-\n b\n");
+    }
+
+    #[test]
+    fn test_generate_patch_marks_missing_newline() {
+        let diff_lines = generate_diff("a\nold", "a\nnew\n", Algorithm::Myers);
+
+        let patch = generate_patch(
+            "old",
+            "new",
+            &diff_lines,
+            None,
+            PatchFormat::Plain,
+            Context::symmetric(3),
+        );
+
+        assert_eq!(
+            patch,
+            "--- old\n+++ new\n@@ -1,2 +1,2 @@\n a\n-old\n\\ No newline at end of file\n+new\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_patch_with_trailing_newlines_has_no_marker() {
+        let diff_lines = generate_diff("a\nold\n", "a\nnew\n", Algorithm::Myers);
+
+        let patch = generate_patch(
+            "old",
+            "new",
+            &diff_lines,
+            None,
+            PatchFormat::Plain,
+            Context::symmetric(3),
+        );
+
+        assert!(!patch.contains("No newline"));
+    }
+
+    #[test]
+    fn test_extract_side_keeps_missing_newline() {
+        let diff_lines = generate_diff("same\nend", "same\nend", Algorithm::Myers);
 
-        cleanup_test_files(&source, &target);
+        assert_eq!(extract_side(&diff_lines, Side::Target, None), "same\nend");
+    }
+
+    #[test]
+    fn test_parse_patch_with_hunks() {
+        let patch = "diff --git a/x.rs b/x.rs\n\
+                     index 1234567..89abcde 100644\n\
+                     --- a/x.rs\t2024-01-01 00:00:00\n\
+                     +++ b/x.rs\n\
+                     @@ -10,3 +10,3 @@ fn main() {\n \
+                     keep\n\
+                     -old\n\
+                     +new\n\
+                     \\ No newline at end of file\n \
+                     tail\n";
+
+        let parsed = parse_patch(patch).unwrap();
+
+        assert_eq!(parsed.source_file, "a/x.rs");
+        assert_eq!(parsed.target_file, "b/x.rs");
+        let lines: Vec<(ChangeTag, &str, Option<usize>)> = parsed
+            .diff_lines
+            .iter()
+            .map(|line| (line.tag, line.content.as_str(), line.new_line))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (ChangeTag::Equal, "keep", Some(10)),
+                (ChangeTag::Delete, "old", None),
+                (ChangeTag::Insert, "new", Some(11)),
+                (ChangeTag::Equal, "tail", Some(12)),
+            ]
+        );
+        assert!(parsed.diff_lines[2].missing_newline);
+    }
+
+    #[test]
+    fn test_parse_patch_reads_back_exported_patches() {
+        let diff_lines = generate_diff("a\nold\n", "a\nnew\n", Algorithm::Myers);
+        let patch = generate_patch(
+            "old.txt",
+            "new.txt",
+            &diff_lines,
+            None,
+            PatchFormat::Plain,
+            Context::symmetric(3),
+        );
+
+        let parsed = parse_patch(&patch).unwrap();
+
+        assert_eq!(parsed.source_file, "old.txt");
+        assert_eq!(
+            extract_side(&parsed.diff_lines, Side::Source, None),
+            "a\nold\n"
+        );
+        assert_eq!(
+            extract_side(&parsed.diff_lines, Side::Target, None),
+            "a\nnew\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_patch_rejects_malformed_input() {
+        assert!(
+            parse_patch("just some text\n")
+                .unwrap_err()
+                .contains("'---'")
+        );
+        assert!(
+            parse_patch("--- a\nnot a header\n")
+                .unwrap_err()
+                .contains("'+++'")
+        );
+        assert!(
+            parse_patch("--- a\n+++ b\n@@ -1,2 +1,2 @@\n x\n")
+                .unwrap_err()
+                .contains("middle of a hunk")
+        );
+        assert!(
+            parse_patch("--- a\n+++ b\n@@ -1 +1 @@\n-x\n-y\n")
+                .unwrap_err()
+                .contains("longer than")
+        );
+        assert!(
+            parse_patch("--- a\n+++ b\n@@ nonsense @@\n")
+                .unwrap_err()
+                .contains("Malformed hunk header")
+        );
+        assert!(
+            parse_patch("--- a\n+++ b\n*x\n")
+                .unwrap_err()
+                .contains("Unexpected line")
+        );
+    }
+
+    #[test]
+    fn test_rapid_exports_never_overwrite_each_other() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+
+        let names: HashSet<String> = (0..20)
+            .map(|i| export_to_file(&format!("patch {}\n", i), Some(dir.path())))
+            .collect::<Result<_, _>>()?;
+
+        assert_eq!(names.len(), 20);
+        assert_eq!(fs::read_dir(dir.path())?.count(), 20);
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_to_file_creates_export_dir() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let export_dir = dir.path().join("patches").join("nested");
+
+        let filename = export_to_file("--- a\n+++ b\n", Some(&export_dir))?;
+
+        let path = Path::new(&filename);
+        assert!(path.is_absolute());
+        assert_eq!(
+            path.parent(),
+            Some(fs::canonicalize(&export_dir)?.as_path())
+        );
+        assert_eq!(fs::read_to_string(path)?, "--- a\n+++ b\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_excerpt_keeps_head_and_tail() {
+        let text: String = (1..=10).map(|n| format!("{}\n", n)).collect();
+
+        assert_eq!(
+            excerpt(&text, 2, 3, "…"),
+            vec!["1", "2", "… 5 more lines …", "8", "9", "10"]
+        );
+        assert_eq!(excerpt(&text, 4, 5, "...")[4], "... 1 more lines ...");
+        assert_eq!(excerpt("a\nb\n", 2, 3, "…"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_partner_line_pairs_deletes_with_following_inserts() {
+        let diff_lines = generate_diff("a\nb\nc\nd\n", "a\nB\nC\nX\nd\n", Algorithm::Myers);
+        let tags: Vec<ChangeTag> = diff_lines.iter().map(|line| line.tag).collect();
+        assert_eq!(
+            tags,
+            vec![
+                ChangeTag::Equal,
+                ChangeTag::Delete,
+                ChangeTag::Delete,
+                ChangeTag::Insert,
+                ChangeTag::Insert,
+                ChangeTag::Insert,
+                ChangeTag::Equal,
+            ]
+        );
+
+        let partners: Vec<Option<usize>> = (0..diff_lines.len())
+            .map(|idx| partner_line(&diff_lines, idx))
+            .collect();
+        assert_eq!(
+            partners,
+            vec![None, Some(3), Some(4), Some(1), Some(2), None, None]
+        );
+    }
+
+    #[test]
+    fn test_generate_diff_marks_replaced_lines_as_modified() {
+        let diff_lines = generate_diff("a\nb\nc\nd\n", "a\nB\nC\nX\nd\n", Algorithm::Myers);
+        let kinds: Vec<LineKind> = diff_lines.iter().map(|line| line.kind).collect();
+
+        // The same pairs as partner_line, the third insert is new content
+        use LineKind::{Modified, Standalone};
+        assert_eq!(
+            kinds,
+            vec![
+                Standalone, Modified, Modified, Modified, Modified, Standalone, Standalone
+            ]
+        );
+
+        let removed = generate_diff("a\nb\nc\n", "a\nc\n", Algorithm::Myers);
+        assert!(removed.iter().all(|line| line.kind == Standalone));
+    }
+
+    #[test]
+    fn test_char_runs_groups_changed_characters() {
+        let runs = |line, other, tag| -> Vec<(String, bool)> { char_runs(line, other, tag) };
+
+        assert_eq!(
+            runs("let colour = 1;", "let color = 2;", ChangeTag::Delete),
+            vec![
+                ("let colo".to_string(), false),
+                ("u".to_string(), true),
+                ("r = ".to_string(), false),
+                ("1".to_string(), true),
+                (";".to_string(), false),
+            ]
+        );
+        assert_eq!(
+            runs("let color = 2;", "let colour = 1;", ChangeTag::Insert),
+            vec![
+                ("let color = ".to_string(), false),
+                ("2".to_string(), true),
+                (";".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_granularity() {
+        assert_eq!(parse_granularity("char"), Ok(Granularity::Char));
+        assert_eq!(parse_granularity("line"), Ok(Granularity::Line));
+        assert!(parse_granularity("word").is_err());
+    }
+
+    #[test]
+    fn test_parse_line_range() {
+        assert_eq!(
+            parse_line_range("100:200"),
+            Ok(LineRange {
+                start: 100,
+                end: 200
+            })
+        );
+        assert_eq!(parse_line_range("5:5").map(|range| range.end), Ok(5));
+
+        let error = parse_line_range("200:100").unwrap_err();
+        assert!(error.contains("inverted"), "{}", error);
+        assert!(parse_line_range("0:10").is_err());
+        assert!(parse_line_range("10").is_err());
+        assert!(parse_line_range("a:b").is_err());
+    }
+
+    #[test]
+    fn test_slice_lines_clamps_to_the_text() {
+        let text = "one\ntwo\nthree\nfour";
+
+        assert_eq!(
+            slice_lines(text, LineRange { start: 2, end: 3 }),
+            "two\nthree\n"
+        );
+        assert_eq!(
+            slice_lines(text, LineRange { start: 3, end: 99 }),
+            "three\nfour"
+        );
+        assert_eq!(slice_lines(text, LineRange { start: 50, end: 99 }), "");
+    }
+
+    #[test]
+    fn test_detect_line_endings() {
+        assert_eq!(LineEnding::detect("a\nb\n"), Some(LineEnding::Lf));
+        assert_eq!(LineEnding::detect("a\r\nb\r\n"), Some(LineEnding::Crlf));
+        assert_eq!(LineEnding::detect("a\r\nb\n"), Some(LineEnding::Mixed));
+        assert_eq!(LineEnding::detect("no line break"), None);
+    }
+
+    #[test]
+    fn test_normalized_line_endings_diff_as_equal() {
+        let crlf = "one\r\ntwo\r\n";
+        let lf = "one\ntwo\n";
+
+        assert!(!is_identical(&generate_diff(crlf, lf, Algorithm::Myers)));
+
+        let normalized = normalize_line_endings(crlf);
+        assert_eq!(normalized, lf);
+        assert!(is_identical(&generate_diff(
+            &normalized,
+            lf,
+            Algorithm::Myers
+        )));
+    }
+
+    #[test]
+    fn test_to_html_colors_lines_and_escapes_content() {
+        use ChangeTag::{Delete, Equal, Insert};
+        let mut diff_lines = lines_from_tags(&[Equal, Delete, Insert]);
+        diff_lines[2].content = "<b>&\"x\"</b>".to_string();
+
+        let html = to_html("a.html", "b.html", &diff_lines);
+
+        assert!(html.starts_with("<pre style="));
+        assert!(html.contains("--- a.html\n+++ b.html</span>\n"));
+        assert!(html.contains("<span> line 0</span>\n"));
+        assert!(html.contains("background-color: #ffebe9\">-line 1</span>"));
+        assert!(html.contains(">+&lt;b&gt;&amp;&quot;x&quot;&lt;/b&gt;</span>"));
+        assert!(html.ends_with("</pre>\n"));
+    }
+
+    #[test]
+    fn test_count_changes() {
+        use ChangeTag::{Delete, Equal, Insert};
+        let diff_lines = lines_from_tags(&[Equal, Delete, Insert, Insert, Equal]);
+
+        assert_eq!(
+            count_changes(&diff_lines),
+            ChangeCounts {
+                insertions: 2,
+                deletions: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_side_by_side_column_pads_truncates_and_expands_tabs() {
+        assert_eq!(side_by_side_column("abc", 5, 8), "abc  ");
+        assert_eq!(side_by_side_column("abcdefgh", 5, 8), "abcde");
+        assert_eq!(side_by_side_column("\tx", 6, 4), "    x ");
+        // A tab that doesn't fit anymore is left out rather than overflowing
+        assert_eq!(side_by_side_column("ab\tc", 3, 4), "ab ");
+        assert_eq!(side_by_side_column("abc", 0, 8), "");
+        // Wide characters take two columns, one that would stick out is left out too
+        assert_eq!(
+            side_by_side_column("\u{65e5}\u{672c}", 5, 8),
+            "\u{65e5}\u{672c} "
+        );
+        assert_eq!(side_by_side_column("\u{65e5}\u{672c}", 3, 8), "\u{65e5} ");
+    }
+
+    #[test]
+    fn test_format_side_by_side_marks_changes_in_the_gutter() {
+        let diff_lines = generate_diff("same\nold\ngone\n", "same\nnew\n", Algorithm::Myers);
+
+        assert_eq!(
+            format_side_by_side("a.txt", "b.txt", &diff_lines, 17, 8),
+            "a.txt     b.txt\n\
+             -----------------\n\
+             same      same\n\
+             old     | new\n\
+             gone    <\n"
+        );
+    }
+
+    #[test]
+    fn test_format_stat() {
+        let entries = vec![
+            (
+                "src/main.rs".to_string(),
+                StatChange::Lines(ChangeCounts {
+                    insertions: 3,
+                    deletions: 4,
+                }),
+            ),
+            ("logo.png".to_string(), StatChange::Binary(10, 12)),
+        ];
+
+        assert_eq!(
+            format_stat(&entries, 80),
+            " src/main.rs | 7 +++----\n logo.png    | Bin 10 -> 12 bytes\n \
+             2 files changed, 3 insertions(+), 4 deletions(-)\n"
+        );
+    }
+
+    #[test]
+    fn test_format_stat_scales_bars_to_width() {
+        let entries = vec![
+            (
+                "big".to_string(),
+                StatChange::Lines(ChangeCounts {
+                    insertions: 100,
+                    deletions: 0,
+                }),
+            ),
+            (
+                "small".to_string(),
+                StatChange::Lines(ChangeCounts {
+                    insertions: 0,
+                    deletions: 1,
+                }),
+            ),
+        ];
+
+        // 30 columns leave 30 - 5 - 3 - 5 = 17 for the bar
+        let stat = format_stat(&entries, 30);
+        let lines: Vec<&str> = stat.lines().collect();
+        assert_eq!(lines[0], format!(" big   | 100 {}", "+".repeat(17)));
+        assert_eq!(lines[1], " small |   1 -");
+        assert_eq!(
+            lines[2],
+            " 2 files changed, 100 insertions(+), 1 deletion(-)"
+        );
+    }
+
+    #[test]
+    fn test_to_markdown_wraps_patch_in_diff_fence() {
+        assert_eq!(
+            to_markdown("--- a\n+++ b\n-old\n+new\n"),
+            "```diff\n--- a\n+++ b\n-old\n+new\n```\n"
+        );
+        assert_eq!(to_markdown("+no newline"), "```diff\n+no newline\n```\n");
+    }
+
+    #[test]
+    fn test_to_markdown_outgrows_backtick_runs() {
+        let patch = "-```rust\n+````\n";
+
+        assert_eq!(to_markdown(patch), format!("`````diff\n{}`````\n", patch));
+    }
+
+    #[test]
+    fn test_append_to_file_accumulates_patches() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let path = dir.path().join("series.patch");
+        let path = path.to_str().unwrap();
+
+        append_to_file(path, "--- a\n+++ b\n-x\n")?;
+        append_to_file(path, "--- c\n+++ d\n+y\n")?;
+
+        assert_eq!(
+            fs::read_to_string(path)?,
+            "--- a\n+++ b\n-x\n--- c\n+++ d\n+y\n"
+        );
         Ok(())
     }
 
     #[test]
     fn test_export_to_file() -> Result<(), Box<dyn std::error::Error>> {
-        let (source, target) = create_test_files()?;
+        let (_dir, source, target) = create_test_files()?;
         let source_content = fs::read_to_string(&source)?;
         let target_content = fs::read_to_string(&target)?;
 
-        let diff_lines = generate_diff(&source_content, &target_content);
-        let patch = generate_patch(&source, &target, &diff_lines, None);
+        let diff_lines = generate_diff(&source_content, &target_content, Algorithm::Myers);
+        let patch = generate_patch(
+            &source,
+            &target,
+            &diff_lines,
+            None,
+            PatchFormat::Plain,
+            Context::symmetric(3),
+        );
 
         // Export the patch
-        let filename = export_to_file(&patch)?;
+        let filename = export_to_file(&patch, None)?;
 
         // Verify file was created
         assert!(Path::new(&filename).exists());
@@ -203,7 +3015,6 @@ mod tests {
         assert!(line_count > 2, "Patch should have more than just headers");
 
         // Cleanup
-        cleanup_test_files(&source, &target);
         fs::remove_file(&filename)?;
 
         Ok(())
@@ -213,12 +3024,19 @@ mod tests {
     fn test_copy_to_clipboard() -> Result<(), Box<dyn std::error::Error>> {
         let _lock = CLIPBOARD_LOCK.lock().unwrap();
 
-        let (source, target) = create_test_files()?;
+        let (_dir, source, target) = create_test_files()?;
         let source_content = fs::read_to_string(&source)?;
         let target_content = fs::read_to_string(&target)?;
 
-        let diff_lines = generate_diff(&source_content, &target_content);
-        let patch = generate_patch(&source, &target, &diff_lines, None);
+        let diff_lines = generate_diff(&source_content, &target_content, Algorithm::Myers);
+        let patch = generate_patch(
+            &source,
+            &target,
+            &diff_lines,
+            None,
+            PatchFormat::Plain,
+            Context::symmetric(3),
+        );
 
         // Try to initialize clipboard, but handle gracefully if not available
         match Clipboard::new() {
@@ -242,8 +3060,6 @@ mod tests {
                 eprintln!("Clipboard not available in this environment: {}", e);
             }
         }
-
-        cleanup_test_files(&source, &target);
         Ok(())
     }
 }
@@ -0,0 +1,432 @@
+use crate::diff::{self, ChangeCounts};
+use similar::Algorithm;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How a file differs between the two directories of a recursive diff.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl ChangeKind {
+    /// The letter shown in front of the file, like `git status --short`.
+    pub fn marker(&self) -> char {
+        match self {
+            ChangeKind::Added => 'A',
+            ChangeKind::Removed => 'D',
+            ChangeKind::Modified => 'M',
+        }
+    }
+}
+
+/// A file that differs between the two directories.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileChange {
+    /// Path relative to both directories
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+    /// Inserted and deleted lines, `None` for binary files which are only flagged
+    pub counts: Option<ChangeCounts>,
+}
+
+/// A row of the directory summary, which shows the changed files as a tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreeRow {
+    /// A directory with the changed lines of every file below it added up
+    Folder {
+        path: PathBuf,
+        depth: usize,
+        counts: ChangeCounts,
+        collapsed: bool,
+    },
+    /// A changed file, indexing `DirSummary::changes`
+    File { index: usize, depth: usize },
+}
+
+/// The changed files of a recursive diff and which row of their tree is selected.
+pub struct DirSummary {
+    pub source_dir: PathBuf,
+    pub target_dir: PathBuf,
+    pub changes: Vec<FileChange>,
+    /// The visible rows, folders below a collapsed folder are left out
+    pub rows: Vec<TreeRow>,
+    /// Folders whose contents are hidden
+    collapsed: HashSet<PathBuf>,
+    pub selected_index: usize,
+    pub scroll_offset: usize,
+}
+
+impl DirSummary {
+    pub fn new(source_dir: PathBuf, target_dir: PathBuf, changes: Vec<FileChange>) -> Self {
+        let mut summary = DirSummary {
+            source_dir,
+            target_dir,
+            changes,
+            rows: Vec::new(),
+            collapsed: HashSet::new(),
+            selected_index: 0,
+            scroll_offset: 0,
+        };
+        summary.rebuild_rows();
+        summary
+    }
+
+    /// Lays out the changes as a tree, relying on them being sorted by path so the files
+    /// of a folder follow each other.
+    fn rebuild_rows(&mut self) {
+        let mut folder_counts: HashMap<&Path, ChangeCounts> = HashMap::new();
+        for change in &self.changes {
+            let counts = change.counts.unwrap_or_default();
+            for folder in change.path.ancestors().skip(1) {
+                if folder.as_os_str().is_empty() {
+                    break;
+                }
+                let total = folder_counts.entry(folder).or_default();
+                total.insertions += counts.insertions;
+                total.deletions += counts.deletions;
+            }
+        }
+
+        let mut rows = Vec::new();
+        // The folders of the previous file, outermost first
+        let mut open: Vec<&Path> = Vec::new();
+        for (index, change) in self.changes.iter().enumerate() {
+            let mut folders: Vec<&Path> = change.path.ancestors().skip(1).collect();
+            folders.pop(); // The empty root
+            folders.reverse();
+
+            let shared = open
+                .iter()
+                .zip(&folders)
+                .take_while(|(a, b)| a == b)
+                .count();
+            open.truncate(shared);
+            for folder in &folders[shared..] {
+                if !open.iter().any(|open| self.collapsed.contains(*open)) {
+                    rows.push(TreeRow::Folder {
+                        path: folder.to_path_buf(),
+                        depth: open.len(),
+                        counts: folder_counts[folder],
+                        collapsed: self.collapsed.contains(*folder),
+                    });
+                }
+                open.push(folder);
+            }
+
+            if !open.iter().any(|open| self.collapsed.contains(*open)) {
+                rows.push(TreeRow::File {
+                    index,
+                    depth: folders.len(),
+                });
+            }
+        }
+
+        self.rows = rows;
+    }
+
+    /// Collapses or expands the selected folder. Returns false when a file is selected.
+    pub fn toggle_selected_folder(&mut self) -> bool {
+        let Some(TreeRow::Folder { path, .. }) = self.rows.get(self.selected_index) else {
+            return false;
+        };
+
+        if !self.collapsed.remove(path) {
+            self.collapsed.insert(path.clone());
+        }
+        // The rows above the folder don't change, so it stays selected
+        self.rebuild_rows();
+        true
+    }
+
+    pub fn move_up(&mut self) {
+        self.selected_index = self.selected_index.saturating_sub(1);
+    }
+
+    pub fn move_down(&mut self) {
+        if self.selected_index + 1 < self.rows.len() {
+            self.selected_index += 1;
+        }
+    }
+
+    pub fn update_scroll(&mut self, viewport_height: usize) {
+        if self.selected_index >= self.scroll_offset + viewport_height {
+            self.scroll_offset = self.selected_index - viewport_height + 1;
+        } else if self.selected_index < self.scroll_offset {
+            self.scroll_offset = self.selected_index;
+        }
+    }
+
+    /// The selected file, `None` when a folder is selected.
+    pub fn selected(&self) -> Option<&FileChange> {
+        match self.rows.get(self.selected_index)? {
+            TreeRow::File { index, .. } => self.changes.get(*index),
+            TreeRow::Folder { .. } => None,
+        }
+    }
+}
+
+pub fn validate_dir(path: &str, dir_type: &str) -> Result<(), String> {
+    let dir_path = Path::new(path);
+
+    if !dir_path.exists() {
+        return Err(format!("{} directory '{}' does not exist", dir_type, path));
+    }
+
+    if !dir_path.is_dir() {
+        return Err(format!("{} path '{}' is not a directory", dir_type, path));
+    }
+
+    Ok(())
+}
+
+/// Walks both directories and lists every file that was added, removed or changed,
+/// sorted by path. `.git` directories are skipped, and symlinked files too unless
+/// `follow_symlinks` is set.
+pub fn compare_dirs(
+    source_dir: &Path,
+    target_dir: &Path,
+    algorithm: Algorithm,
+    follow_symlinks: bool,
+) -> Result<Vec<FileChange>, String> {
+    let mut paths = BTreeSet::new();
+    for dir in [source_dir, target_dir] {
+        collect_files(dir, Path::new(""), follow_symlinks, &mut paths)
+            .map_err(|e| format!("Failed to read '{}': {}", dir.display(), e))?;
+    }
+
+    let mut changes = Vec::new();
+    for path in paths {
+        let read = |dir: &Path| -> Result<Option<diff::FileContent>, String> {
+            let file = dir.join(&path);
+            // A symlink on one side only counts as a missing file there
+            if !file.is_file() || (!follow_symlinks && file.is_symlink()) {
+                return Ok(None);
+            }
+            diff::read_file(&file.display().to_string())
+                .map(Some)
+                .map_err(|e| format!("Failed to read '{}': {}", file.display(), e))
+        };
+
+        let (kind, source, target) = match (read(source_dir)?, read(target_dir)?) {
+            (Some(source), Some(target)) => (ChangeKind::Modified, source, target),
+            (None, Some(target)) => (ChangeKind::Added, diff::decode_content(b""), target),
+            (Some(source), None) => (ChangeKind::Removed, source, diff::decode_content(b"")),
+            (None, None) => continue,
+        };
+
+        let counts = if source.binary || target.binary {
+            if source.text == target.text {
+                continue;
+            }
+            None
+        } else {
+            let diff_lines = diff::generate_diff(&source.text, &target.text, algorithm);
+            if kind == ChangeKind::Modified && diff::is_identical(&diff_lines) {
+                continue;
+            }
+            Some(diff::count_changes(&diff_lines))
+        };
+
+        changes.push(FileChange { path, kind, counts });
+    }
+
+    Ok(changes)
+}
+
+/// Adds the paths of all files below `root.join(relative)`, relative to `root`.
+fn collect_files(
+    root: &Path,
+    relative: &Path,
+    follow_symlinks: bool,
+    paths: &mut BTreeSet<PathBuf>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        // Symlinked directories aren't followed, they could loop
+        if file_type.is_dir() {
+            if entry.file_name() != ".git" {
+                collect_files(root, &path, follow_symlinks, paths)?;
+            }
+        } else if entry.path().is_file() && (follow_symlinks || !file_type.is_symlink()) {
+            paths.insert(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_dirs_lists_added_removed_and_modified_files()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let old = dir.path().join("old");
+        let new = dir.path().join("new");
+        for side in [&old, &new] {
+            fs::create_dir_all(side.join("src"))?;
+            fs::write(side.join("same.txt"), "same\n")?;
+        }
+        fs::write(old.join("src").join("main.rs"), "keep\nold\n")?;
+        fs::write(new.join("src").join("main.rs"), "keep\nnew\nmore\n")?;
+        fs::write(old.join("gone.txt"), "a\nb\n")?;
+        fs::write(new.join("logo.png"), [0x89, b'P', b'N', b'G', 0x00])?;
+
+        let changes = compare_dirs(&old, &new, Algorithm::Myers, true)?;
+
+        assert_eq!(
+            changes,
+            vec![
+                FileChange {
+                    path: PathBuf::from("gone.txt"),
+                    kind: ChangeKind::Removed,
+                    counts: Some(ChangeCounts {
+                        insertions: 0,
+                        deletions: 2
+                    }),
+                },
+                FileChange {
+                    path: PathBuf::from("logo.png"),
+                    kind: ChangeKind::Added,
+                    counts: None,
+                },
+                FileChange {
+                    path: Path::new("src").join("main.rs"),
+                    kind: ChangeKind::Modified,
+                    counts: Some(ChangeCounts {
+                        insertions: 2,
+                        deletions: 1
+                    }),
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_compare_dirs_skips_symlinks_unless_following_them()
+    -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::symlink;
+
+        let dir = assert_fs::TempDir::new()?;
+        let old = dir.path().join("old");
+        let new = dir.path().join("new");
+        fs::create_dir_all(&old)?;
+        fs::create_dir_all(&new)?;
+        fs::write(dir.path().join("real.txt"), "linked\n")?;
+        symlink(dir.path().join("real.txt"), new.join("link.txt"))?;
+
+        let changes = compare_dirs(&old, &new, Algorithm::Myers, true)?;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, PathBuf::from("link.txt"));
+
+        assert_eq!(compare_dirs(&old, &new, Algorithm::Myers, false)?, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dir_summary_tree_adds_up_and_collapses_folders() {
+        let change = |path: &str, insertions| FileChange {
+            path: PathBuf::from(path),
+            kind: ChangeKind::Modified,
+            counts: Some(ChangeCounts {
+                insertions,
+                deletions: 1,
+            }),
+        };
+        let mut summary = DirSummary::new(
+            PathBuf::from("old"),
+            PathBuf::from("new"),
+            vec![
+                change("README.md", 1),
+                change("src/app.rs", 2),
+                change("src/ui/list.rs", 3),
+                change("src/ui/mod.rs", 4),
+                change("tests/cli.rs", 5),
+            ],
+        );
+        let folder = |path: &str, depth, insertions, deletions, collapsed| TreeRow::Folder {
+            path: PathBuf::from(path),
+            depth,
+            counts: ChangeCounts {
+                insertions,
+                deletions,
+            },
+            collapsed,
+        };
+        let file = |index, depth| TreeRow::File { index, depth };
+
+        assert_eq!(
+            summary.rows,
+            vec![
+                file(0, 0),
+                folder("src", 0, 9, 3, false),
+                file(1, 1),
+                folder("src/ui", 1, 7, 2, false),
+                file(2, 2),
+                file(3, 2),
+                folder("tests", 0, 5, 1, false),
+                file(4, 1),
+            ]
+        );
+
+        // Enter on a file doesn't toggle anything
+        assert!(!summary.toggle_selected_folder());
+        summary.selected_index = 1;
+        assert_eq!(summary.selected(), None);
+        assert!(summary.toggle_selected_folder());
+        assert_eq!(
+            summary.rows,
+            vec![
+                file(0, 0),
+                folder("src", 0, 9, 3, true),
+                folder("tests", 0, 5, 1, false),
+                file(4, 1),
+            ]
+        );
+        summary.move_down();
+        summary.move_down();
+        assert_eq!(summary.selected(), Some(&change("tests/cli.rs", 5)));
+
+        // Expanding brings the files back
+        summary.selected_index = 1;
+        summary.toggle_selected_folder();
+        assert_eq!(summary.rows.len(), 8);
+    }
+
+    #[test]
+    fn test_dir_summary_selection_stays_in_range() {
+        let change = |name: &str| FileChange {
+            path: PathBuf::from(name),
+            kind: ChangeKind::Modified,
+            counts: None,
+        };
+        let mut summary = DirSummary::new(
+            PathBuf::from("old"),
+            PathBuf::from("new"),
+            vec![change("a"), change("b"), change("c")],
+        );
+
+        summary.move_up();
+        assert_eq!(summary.selected_index, 0);
+        for _ in 0..5 {
+            summary.move_down();
+        }
+        assert_eq!(summary.selected_index, 2);
+
+        summary.update_scroll(2);
+        assert_eq!(summary.scroll_offset, 1);
+        assert_eq!(summary.selected(), Some(&change("c")));
+    }
+}
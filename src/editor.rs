@@ -0,0 +1,110 @@
+use crossterm::{
+    cursor,
+    event::{DisableMouseCapture, EnableMouseCapture},
+    execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+};
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// The program and arguments that open `path` in `editor`, a command like `vim` or
+/// `code --wait`. Editors that are known to take a line number open at `line`.
+pub fn editor_args(editor: &str, path: &Path, line: Option<usize>) -> Result<Vec<String>, String> {
+    let mut words: Vec<String> = editor.split_whitespace().map(str::to_string).collect();
+    let Some(program) = words.first() else {
+        return Err("$EDITOR is empty".to_string());
+    };
+
+    let name = Path::new(program)
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let path = path.display().to_string();
+    match (name.as_str(), line) {
+        (
+            "vi" | "vim" | "nvim" | "nano" | "emacs" | "emacsclient" | "micro" | "kak" | "joe",
+            Some(line),
+        ) => {
+            words.push(format!("+{}", line));
+            words.push(path);
+        }
+        ("code" | "codium", Some(line)) => {
+            words.push("--goto".to_string());
+            words.push(format!("{}:{}", path, line));
+        }
+        ("hx" | "helix" | "subl", Some(line)) => words.push(format!("{}:{}", path, line)),
+        _ => words.push(path),
+    }
+    Ok(words)
+}
+
+/// Opens `path` in `editor` (the value of `$EDITOR`) and waits for it to exit. The TUI's
+/// terminal setup is undone while the editor runs and put back afterwards, also when the
+/// editor failed to start.
+pub fn edit(editor: &str, path: &Path, line: Option<usize>) -> Result<(), String> {
+    let words = editor_args(editor, path, line)?;
+
+    suspend_terminal().map_err(|e| format!("Failed to leave the TUI: {}", e))?;
+    let status = Command::new(&words[0]).args(&words[1..]).status();
+    resume_terminal().map_err(|e| format!("Failed to restore the TUI: {}", e))?;
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("{} exited with {}", words[0], status)),
+        Err(e) => Err(format!("Failed to run {}: {}", words[0], e)),
+    }
+}
+
+fn suspend_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(
+        io::stdout(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        cursor::Show
+    )
+}
+
+fn resume_terminal() -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_args_open_at_the_line_when_supported() {
+        let path = Path::new("src/main.rs");
+
+        assert_eq!(
+            editor_args("vim", path, Some(12)),
+            Ok(vec![
+                "vim".to_string(),
+                "+12".to_string(),
+                "src/main.rs".to_string()
+            ])
+        );
+        assert_eq!(
+            editor_args("/usr/bin/code --wait", path, Some(3)),
+            Ok(vec![
+                "/usr/bin/code".to_string(),
+                "--wait".to_string(),
+                "--goto".to_string(),
+                "src/main.rs:3".to_string()
+            ])
+        );
+        // Unknown editors and unknown lines only get the file
+        assert_eq!(
+            editor_args("ed", path, Some(3)),
+            Ok(vec!["ed".to_string(), "src/main.rs".to_string()])
+        );
+        assert_eq!(
+            editor_args("vim", path, None),
+            Ok(vec!["vim".to_string(), "src/main.rs".to_string()])
+        );
+        assert!(editor_args("  ", path, None).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::source::DiffSource;
+use ansi_to_tui::IntoText;
+use ratatui::text::Line;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use tempfile::NamedTempFile;
+
+/// Runs an external diff tool like `difft` or `diff -u` on the two files and returns what
+/// it printed.
+///
+/// The command is split on whitespace, the source and target paths are appended as the
+/// last two arguments. Exit code 1 is how diff tools say the files differ, so only other
+/// failures are errors.
+fn run(command: &str, source_file: &str, target_file: &str) -> Result<String, String> {
+    let mut words = command.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| "The external diff command is empty".to_string())?;
+
+    let output = Command::new(program)
+        .args(words)
+        .arg(source_file)
+        .arg(target_file)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    match output.status.code() {
+        Some(0 | 1) => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        _ => Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+/// Runs the external diff tool on two sides. Files are passed as they are, the tool can't
+/// read the other sides (stdin, the clipboard, a git revision), so those are written to
+/// temporary files for the length of the run.
+pub fn run_sources(
+    command: &str,
+    source: &mut DiffSource,
+    target: &mut DiffSource,
+) -> Result<String, String> {
+    let source_file = SideFile::new(source, "source")?;
+    let target_file = SideFile::new(target, "target")?;
+    run(command, &source_file.path(), &target_file.path())
+}
+
+/// The path an external tool reads one side from, a temporary file that is removed on
+/// drop for sides that aren't files.
+enum SideFile {
+    File(PathBuf),
+    Temporary(NamedTempFile),
+}
+
+impl SideFile {
+    fn new(side: &mut DiffSource, name: &str) -> Result<Self, String> {
+        if let DiffSource::File(path) = side {
+            return Ok(SideFile::File(path.clone()));
+        }
+
+        let content = side.load()?;
+        // Keep the extension of a git path, tools like `difft` pick the language from it
+        let extension = match side.label().rsplit_once('.') {
+            Some((_, extension)) if !extension.contains(['/', ':']) => format!(".{}", extension),
+            _ => String::new(),
+        };
+        // A random name that is created anew and only readable by us, so nobody can plant
+        // a symlink there or read along. The tool gets the bytes as read, not the hex dump
+        let mut file = tempfile::Builder::new()
+            .prefix("lazydiff-")
+            .suffix(&format!("-{}{}", name, extension))
+            .tempfile()
+            .map_err(|e| format!("Failed to create a temporary file: {}", e))?;
+        file.write_all(content.bytes())
+            .and_then(|_| file.flush())
+            .map_err(|e| format!("Failed to write '{}': {}", file.path().display(), e))?;
+        Ok(SideFile::Temporary(file))
+    }
+
+    fn path(&self) -> String {
+        match self {
+            SideFile::File(path) => path.display().to_string(),
+            SideFile::Temporary(file) => file.path().display().to_string(),
+        }
+    }
+}
+
+/// Turns the ANSI colors of already colored output (e.g. `git diff --color`) into styled
+/// lines. Other escape sequences are dropped rather than shown as garbage.
+pub fn parse_ansi(output: &str) -> Vec<Line<'static>> {
+    match output.into_text() {
+        Ok(text) => text.lines,
+        Err(_) => output
+            .lines()
+            .map(|line| Line::from(line.to_string()))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // These run `echo`, `false` and `diff`, which only exist on Unix
+    #[cfg(unix)]
+    #[test]
+    fn test_run_appends_both_files() {
+        let output = run("echo compare", "old.txt", "new.txt").unwrap();
+
+        assert_eq!(output, "compare old.txt new.txt\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_accepts_differences_but_not_failures() {
+        assert!(
+            run("false", "a", "b").is_ok(),
+            "exit code 1 means the files differ"
+        );
+        assert!(run("diff", "missing-a.txt", "missing-b.txt").is_err());
+        assert!(run("  ", "a", "b").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_sources_writes_other_sides_to_files() {
+        let mut source = DiffSource::Memory {
+            label: "HEAD:src/main.rs".to_string(),
+            content: crate::diff::decode_content(b"from memory\n"),
+        };
+        let mut target = DiffSource::File(PathBuf::from("Cargo.toml"));
+
+        let output = run_sources("head -n 1", &mut source, &mut target).unwrap();
+
+        assert!(output.contains("from memory\n"), "{}", output);
+        assert!(output.contains("[package]\n"), "{}", output);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_side_file_is_private_and_keeps_the_original_bytes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let bytes = b"\x00binary\xff\n";
+        let mut side = DiffSource::Memory {
+            label: "HEAD:logo.png".to_string(),
+            content: crate::diff::decode_content(bytes),
+        };
+
+        let file = SideFile::new(&mut side, "source").unwrap();
+        let path = PathBuf::from(file.path());
+
+        assert!(
+            path.to_string_lossy().ends_with("-source.png"),
+            "{:?}",
+            path
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        drop(file);
+        assert!(
+            !path.exists(),
+            "the temporary file is removed after the run"
+        );
+    }
+
+    #[test]
+    fn test_parse_ansi_styles_colors_and_drops_other_escapes() {
+        use ratatui::style::{Color, Style};
+
+        let lines = parse_ansi("\x1b[31m-old\x1b[0m\n\x1b[2K+new\n");
+
+        let plain: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(plain, vec!["-old", "+new"]);
+        assert_eq!(lines[0].spans[0].style, Style::default().fg(Color::Red));
+    }
+}
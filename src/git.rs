@@ -0,0 +1,126 @@
+use crate::diff::{self, FileContent};
+use std::path::Path;
+use std::process::Command;
+
+/// Reads the content of `path` as it was committed at `rev`.
+///
+/// The path is resolved relative to the current directory, the same way git itself
+/// resolves `REV:./path`. Fails when we're not inside a repository or the file isn't
+/// tracked.
+pub fn show_file_at(rev: &str, path: &str) -> Result<FileContent, String> {
+    let (dir, file_name) = split_path(path)?;
+
+    run_git(dir, &["rev-parse", "--is-inside-work-tree"])
+        .map_err(|_| format!("'{}' is not inside a git repository", path))?;
+
+    run_git(dir, &["ls-files", "--error-unmatch", "--", file_name])
+        .map_err(|_| format!("'{}' is not tracked by git", path))?;
+
+    let bytes = run_git(dir, &["show", &format!("{}:./{}", rev, file_name)])
+        .map_err(|e| format!("Failed to read '{}' at {}: {}", path, rev, e))?;
+
+    Ok(diff::decode_content(&bytes))
+}
+
+/// Reads the content of `path` at `rev`, which can be any commit. The file is `None` when
+/// it didn't exist in that commit. Also returns the short hash of the commit.
+pub fn file_at_revision(rev: &str, path: &str) -> Result<(String, Option<FileContent>), String> {
+    let (dir, file_name) = split_path(path)?;
+
+    run_git(dir, &["rev-parse", "--is-inside-work-tree"])
+        .map_err(|_| format!("'{}' is not inside a git repository", path))?;
+
+    let hash = run_git(
+        dir,
+        &[
+            "rev-parse",
+            "--short",
+            "--verify",
+            &format!("{}^{{commit}}", rev),
+        ],
+    )
+    .map_err(|_| format!("Unknown revision '{}'", rev))?;
+    let hash = String::from_utf8_lossy(&hash).trim().to_string();
+
+    let spec = format!("{}:./{}", rev, file_name);
+    if run_git(dir, &["cat-file", "-e", &spec]).is_err() {
+        return Ok((hash, None));
+    }
+
+    let bytes = run_git(dir, &["show", &spec])
+        .map_err(|e| format!("Failed to read '{}' at {}: {}", path, rev, e))?;
+    Ok((hash, Some(diff::decode_content(&bytes))))
+}
+
+/// The directory to run git in and the name of the file in it.
+fn split_path(path: &str) -> Result<(&Path, &str), String> {
+    let file_path = Path::new(path);
+    let dir = match file_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = file_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("'{}' is not a file path", path))?;
+
+    Ok((dir, file_name))
+}
+
+fn run_git(dir: &Path, args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_file_at_revision() {
+        let (hash, content) = file_at_revision("HEAD", "Cargo.toml").unwrap();
+        assert!(hash.len() >= 7);
+        assert!(content.unwrap().text.contains("name = \"lazydiff\""));
+
+        let (_, missing) = file_at_revision("HEAD", "never-committed.txt").unwrap();
+        assert!(missing.is_none());
+
+        assert!(
+            file_at_revision("no-such-revision", "Cargo.toml")
+                .unwrap_err()
+                .contains("Unknown revision")
+        );
+    }
+
+    #[test]
+    fn test_show_file_at_head_returns_committed_content() {
+        let content = show_file_at("HEAD", "Cargo.toml").unwrap();
+
+        assert!(content.text.contains("name = \"lazydiff\""));
+    }
+
+    #[test]
+    fn test_show_file_at_rejects_untracked_file() -> Result<(), Box<dyn std::error::Error>> {
+        // A throwaway repository, so the crate's own working tree is left alone
+        let dir = assert_fs::TempDir::new()?;
+        run_git(dir.path(), &["init", "--quiet"])?;
+        let path = dir.path().join("untracked.txt");
+        fs::write(&path, "not committed\n")?;
+
+        let result = show_file_at("HEAD", path.to_str().unwrap());
+
+        assert!(result.unwrap_err().contains("not tracked"));
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+/// The non-ASCII symbols the UI draws, kept in one place so terminals that can't render
+/// them get plain ASCII instead.
+pub struct Glyphs {
+    /// The scroll keys in the status bar and the help
+    pub up_down: &'static str,
+    /// Between the two files of a tab
+    pub swap: &'static str,
+    /// Around folded lines and after notices that are still running
+    pub ellipsis: &'static str,
+    /// Before the lines left out between hunks
+    pub skip: &'static str,
+    /// Separates the `+`/`-` gutter from the line content
+    pub gutter: &'static str,
+    /// The arrows of folded and unfolded folders in the changed files tree
+    pub collapsed: char,
+    pub expanded: char,
+    /// Minimap rows with and without changes
+    pub minimap_change: &'static str,
+    pub minimap_equal: &'static str,
+    /// Marks a tab and a trailing space when whitespace is shown
+    pub tab: char,
+    pub space: &'static str,
+    pub spinner: &'static [char],
+}
+
+pub const UNICODE: Glyphs = Glyphs {
+    up_down: "↑/↓",
+    swap: "↔",
+    ellipsis: "\u{2026}",
+    skip: "\u{22ef}",
+    gutter: "│",
+    collapsed: '▸',
+    expanded: '▾',
+    minimap_change: "█",
+    minimap_equal: "░",
+    tab: '→',
+    space: "·",
+    spinner: &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'],
+};
+
+pub const ASCII: Glyphs = Glyphs {
+    up_down: "Up/Dn",
+    swap: "<->",
+    ellipsis: "...",
+    skip: "...",
+    gutter: "|",
+    collapsed: '+',
+    expanded: '-',
+    minimap_change: "#",
+    minimap_equal: ".",
+    tab: '>',
+    space: ".",
+    spinner: &['|', '/', '-', '\\'],
+};
+
+impl Glyphs {
+    pub fn get(ascii: bool) -> &'static Glyphs {
+        if ascii { &ASCII } else { &UNICODE }
+    }
+}
+
+/// Whether the locale says the terminal can't show UTF-8, like `LANG=C`. The first of
+/// `LC_ALL`, `LC_CTYPE` and `LANG` that is set decides, and without any of them the
+/// terminal is trusted to render Unicode.
+pub fn locale_is_ascii() -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty());
+    locale.is_some_and(|locale| !is_utf8_locale(&locale))
+}
+
+fn is_utf8_locale(locale: &str) -> bool {
+    let locale = locale.to_ascii_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf8_locales_are_recognized() {
+        assert!(is_utf8_locale("en_US.UTF-8"));
+        assert!(is_utf8_locale("C.utf8"));
+        assert!(!is_utf8_locale("C"));
+        assert!(!is_utf8_locale("en_US.ISO-8859-1"));
+    }
+
+    #[test]
+    fn test_ascii_glyphs_are_ascii() {
+        let text = [
+            ASCII.up_down,
+            ASCII.swap,
+            ASCII.ellipsis,
+            ASCII.skip,
+            ASCII.gutter,
+            ASCII.minimap_change,
+            ASCII.minimap_equal,
+            ASCII.space,
+        ]
+        .concat();
+        let chars = [ASCII.collapsed, ASCII.expanded, ASCII.tab];
+
+        assert!(text.is_ascii());
+        assert!(chars.iter().chain(ASCII.spinner).all(char::is_ascii));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::diff::{self, DiffLine, FileContent, LineRange};
+use serde::Serialize;
+use similar::{Algorithm, ChangeTag};
+
+/// One pair of files in the output of `--print --format json`.
+///
+/// The field names are the schema other programs rely on, so only add to them.
+#[derive(Debug, Serialize)]
+pub struct FileDiff {
+    pub source: String,
+    pub target: String,
+    /// Binary files are only compared, their `lines` are left empty
+    pub binary: bool,
+    pub identical: bool,
+    /// Every line of the diff, unchanged ones included
+    pub lines: Vec<JsonLine>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct JsonLine {
+    pub tag: Tag,
+    /// The line without its line ending
+    pub content: String,
+    /// 1-based line number in the source file, `null` for inserted lines
+    pub old_line: Option<usize>,
+    /// 1-based line number in the target file, `null` for deleted lines
+    pub new_line: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tag {
+    Equal,
+    Insert,
+    Delete,
+}
+
+impl From<ChangeTag> for Tag {
+    fn from(tag: ChangeTag) -> Self {
+        match tag {
+            ChangeTag::Equal => Tag::Equal,
+            ChangeTag::Insert => Tag::Insert,
+            ChangeTag::Delete => Tag::Delete,
+        }
+    }
+}
+
+impl FileDiff {
+    /// Diffs the two sides. With line ranges the content is the slice that was read, and
+    /// the line numbers still count from the top of each file.
+    pub fn new(
+        source_file: String,
+        target_file: String,
+        source: &FileContent,
+        target: &FileContent,
+        algorithm: Algorithm,
+        ranges: (Option<LineRange>, Option<LineRange>),
+    ) -> Self {
+        if source.binary || target.binary {
+            return FileDiff {
+                source: source_file,
+                target: target_file,
+                binary: true,
+                identical: source.text == target.text,
+                lines: Vec::new(),
+            };
+        }
+
+        let diff_lines = diff::generate_diff(&source.text, &target.text, algorithm);
+        let first_line = |range: Option<LineRange>| range.map_or(1, |range| range.start);
+        FileDiff {
+            source: source_file,
+            target: target_file,
+            binary: false,
+            identical: diff::is_identical(&diff_lines),
+            lines: json_lines(&diff_lines, first_line(ranges.0), first_line(ranges.1)),
+        }
+    }
+}
+
+/// Numbers the lines of a diff, starting at the given line of each side.
+fn json_lines(diff_lines: &[DiffLine], source_start: usize, target_start: usize) -> Vec<JsonLine> {
+    let mut old_line = source_start;
+    let mut new_line = target_start;
+
+    diff_lines
+        .iter()
+        .map(|line| {
+            let old = (line.tag != ChangeTag::Insert).then(|| {
+                old_line += 1;
+                old_line - 1
+            });
+            let new = (line.tag != ChangeTag::Delete).then(|| {
+                new_line += 1;
+                new_line - 1
+            });
+            JsonLine {
+                tag: line.tag.into(),
+                content: line.content.clone(),
+                old_line: old,
+                new_line: new,
+            }
+        })
+        .collect()
+}
+
+/// The JSON document for all pairs, an array with an object per pair.
+pub fn to_json(files: &[FileDiff]) -> Result<String, String> {
+    serde_json::to_string_pretty(files).map_err(|e| format!("Failed to write JSON: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_shape_is_stable() -> Result<(), Box<dyn std::error::Error>> {
+        let file = FileDiff::new(
+            "old.txt".to_string(),
+            "new.txt".to_string(),
+            &diff::decode_content(b"keep\nold\n"),
+            &diff::decode_content(b"keep\nnew\n"),
+            Algorithm::Myers,
+            (None, None),
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&to_json(&[file])?)?;
+
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "source": "old.txt",
+                "target": "new.txt",
+                "binary": false,
+                "identical": false,
+                "lines": [
+                    {"tag": "equal", "content": "keep", "old_line": 1, "new_line": 1},
+                    {"tag": "delete", "content": "old", "old_line": 2, "new_line": null},
+                    {"tag": "insert", "content": "new", "old_line": null, "new_line": 2},
+                ],
+            }])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_lines_count_from_the_range_start() {
+        let file = FileDiff::new(
+            "a".to_string(),
+            "b".to_string(),
+            &diff::decode_content(b"x\n"),
+            &diff::decode_content(b"x\ny\n"),
+            Algorithm::Myers,
+            (
+                Some(LineRange { start: 5, end: 5 }),
+                Some(LineRange { start: 10, end: 11 }),
+            ),
+        );
+
+        let numbers: Vec<_> = file
+            .lines
+            .iter()
+            .map(|line| (line.old_line, line.new_line))
+            .collect();
+        assert_eq!(numbers, vec![(Some(5), Some(10)), (None, Some(11))]);
+    }
+}
@@ -1,18 +1,41 @@
 mod app;
 mod browser;
+mod config;
 mod diff;
+mod dirdiff;
+mod editor;
+mod external;
+mod git;
+mod glyphs;
+mod json;
+mod source;
+mod theme;
 mod ui;
 
-use app::{App, AppMode};
+use app::{App, AppMode, AppOptions};
 use clap::Parser;
+use config::{Config, State};
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
     execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+    terminal::{
+        self, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+    },
 };
+use diff::{
+    Context, FileContent, Granularity, LineRange, OutputFormat, PatchFormat, Side, StatChange,
+};
+use dirdiff::DirSummary;
 use ratatui::{Terminal, backend::CrosstermBackend};
-use std::io;
+use similar::Algorithm;
+use source::DiffSource;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Duration;
+use theme::Theme;
 
 /// A terminal-based diff viewer
 #[derive(Parser)]
@@ -20,31 +43,547 @@ use std::process;
 #[command(version)]
 #[command(about = "A terminal-based diff viewer", long_about = None)]
 struct Cli {
-    /// Source file to compare
+    /// Source file to compare, `-` reads stdin
+    #[arg(id = "source_arg", value_name = "SOURCE", group = "source")]
     source: Option<String>,
 
-    /// Target file to compare against
+    /// Target file to compare against, `-` reads stdin
+    #[arg(id = "target_arg", value_name = "TARGET", group = "target")]
     target: Option<String>,
+
+    /// Source file to compare, instead of the first file argument
+    #[arg(long = "source", value_name = "FILE", group = "source")]
+    source_flag: Option<String>,
+
+    /// Target file to compare against, instead of the second file argument
+    #[arg(long = "target", value_name = "FILE", group = "target")]
+    target_flag: Option<String>,
+
+    /// More source and target pairs, each opened in its own tab
+    #[arg(value_name = "MORE", requires = "target")]
+    more: Vec<String>,
+
+    /// Compare a file in the working tree against its committed version at HEAD
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["source", "target"])]
+    git: Option<String>,
+
+    /// Compare a file as it was at two git revisions, e.g. --git-range v1.0 HEAD src/main.rs
+    #[arg(long, num_args = 3, value_names = ["REV1", "REV2", "FILE"], conflicts_with_all = ["source", "target", "git"])]
+    git_range: Option<Vec<String>>,
+
+    /// Compare the text on the clipboard against a file, `-` reads stdin
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["source", "target", "git", "git_range"])]
+    from_clipboard: Option<String>,
+
+    /// Review a unified diff file, showing the before and after it describes
+    #[arg(long, value_name = "PATCH", conflicts_with_all = ["source", "target", "git", "git_range", "from_clipboard"])]
+    apply: Option<String>,
+
+    /// Show the two sides of the git conflicts in a file as a diff
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["source", "target", "git", "git_range", "from_clipboard", "apply"])]
+    conflicts: Option<String>,
+
+    /// Read source and target pairs from a file, one `source<TAB>target` pair per line,
+    /// each opened in its own tab. `-` reads the list from stdin
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["source", "target", "git", "git_range", "from_clipboard", "apply", "conflicts"])]
+    list: Option<String>,
+
+    /// Compare two directories, listing the changed files first
+    #[arg(long, requires = "target", conflicts_with_all = ["more", "git", "from_clipboard", "apply", "conflicts", "print", "stat"])]
+    recursive: bool,
+
+    /// Color theme to use (default, high-contrast, mono, colorblind)
+    #[arg(long, env = "LAZYDIFF_THEME")]
+    theme: Option<String>,
+
+    /// Don't dim the +/- prefix of changed lines
+    #[arg(long)]
+    bright: bool,
+
+    /// Draw plain ASCII instead of arrows and other symbols some terminals show as boxes.
+    /// On by default when the locale isn't UTF-8
+    #[arg(long)]
+    ascii: bool,
+
+    /// Jump back to the top when scrolling past the end of the diff (and vice versa)
+    #[arg(long)]
+    wrap_around: bool,
+
+    /// Largest number of lines a held arrow key scrolls at once (default 8), 1 turns the
+    /// acceleration off
+    #[arg(long, value_name = "LINES")]
+    max_scroll_step: Option<NonZeroUsize>,
+
+    /// Cut diff lines longer than this many columns with a … marker instead of wrapping
+    /// them, Enter shows the line under the cursor in full
+    #[arg(long, value_name = "COLUMNS")]
+    max_line_length: Option<NonZeroUsize>,
+
+    /// Number of unchanged lines kept around changes when folding (default 3)
+    #[arg(long, value_name = "LINES", env = "LAZYDIFF_CONTEXT")]
+    context: Option<usize>,
+
+    /// Unchanged lines kept in front of changes when folding, overrides --context
+    #[arg(long, value_name = "LINES")]
+    context_before: Option<usize>,
+
+    /// Unchanged lines kept after changes when folding, overrides --context
+    #[arg(long, value_name = "LINES")]
+    context_after: Option<usize>,
+
+    /// Number of columns between tab stops when displaying tabs
+    #[arg(long, value_name = "COLUMNS", default_value_t = app::DEFAULT_TAB_WIDTH)]
+    tab_width: usize,
+
+    /// Show tabs as a faint → marker
+    #[arg(long)]
+    show_tabs: bool,
+
+    /// Indent the wrapped rows of long lines past the +/- gutter
+    #[arg(long)]
+    wrap_indent: bool,
+
+    /// Diff algorithm to use (myers, patience, lcs)
+    #[arg(long, value_parser = diff::parse_algorithm, default_value = "myers")]
+    algorithm: Algorithm,
+
+    /// Highlight changes by line or also the changed characters within lines (line, char)
+    #[arg(long, value_parser = diff::parse_granularity, default_value = "line")]
+    granularity: Granularity,
+
+    /// Write patches with `diff --git` and a/ b/ headers, for `git apply`
+    #[arg(long)]
+    git_format: bool,
+
+    /// Only diff these 1-based lines of the source, e.g. 100:200
+    #[arg(long, value_name = "A:B", value_parser = diff::parse_line_range)]
+    source_range: Option<LineRange>,
+
+    /// Only diff these 1-based lines of the target, e.g. 100:200
+    #[arg(long, value_name = "A:B", value_parser = diff::parse_line_range)]
+    target_range: Option<LineRange>,
+
+    /// Only diff the first N lines of each file, for huge logs
+    #[arg(long, value_name = "N", conflicts_with_all = ["print", "stat", "external", "recursive"])]
+    max_lines: Option<NonZeroUsize>,
+
+    /// Ask before quitting while a selection or patch series is in progress
+    #[arg(long)]
+    confirm_quit: bool,
+
+    /// Seconds before status messages clear on their own (errors stay three times longer)
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    status_timeout: Option<Duration>,
+
+    /// Only list files with these comma-separated extensions in the file browser, e.g. rs,toml
+    #[arg(long, value_name = "EXTENSIONS")]
+    ext: Option<String>,
+
+    /// Directory to write exported patches to, created when missing
+    #[arg(long, value_name = "PATH")]
+    export_dir: Option<PathBuf>,
+
+    /// Only look: exporting, copying and opening the editor are disabled, so nothing is
+    /// written and the clipboard is left alone
+    #[arg(long, alias = "safe", conflicts_with_all = ["from_clipboard", "export_dir"])]
+    read_only: bool,
+
+    /// Exit 1 when the files differ at the time of quitting the TUI and 0 when they are
+    /// identical, like `diff`. Errors exit 2 then, so they can't pass for a difference
+    #[arg(long, conflicts_with_all = ["print", "stat"])]
+    exit_code: bool,
+
+    /// Diff tool to run on the two files instead of the built-in diff, e.g. "difft" or
+    /// "diff -u". Its output is shown, copied and exported as it is
+    #[arg(long, value_name = "CMD", conflicts_with_all = ["git", "git_range", "from_clipboard", "apply", "conflicts", "source_range", "target_range", "stat"])]
+    external: Option<String>,
+
+    /// Refuse to diff through symlinks, in the arguments and the file browser
+    #[arg(long)]
+    no_follow_symlinks: bool,
+
+    /// Print the diff as a patch instead of starting the TUI, exiting 1 when the files differ
+    #[arg(long)]
+    print: bool,
+
+    /// What --print writes: patch (default) or json, an array with the lines of each
+    /// pair's diff and their line numbers
+    #[arg(long, value_name = "FORMAT", value_parser = diff::parse_format, default_value = "patch", requires = "print", conflicts_with = "external")]
+    format: OutputFormat,
+
+    /// Print the diff in two columns like `diff -y` instead of as a patch
+    #[arg(long, requires = "print", conflicts_with_all = ["format", "external"])]
+    side_by_side: bool,
+
+    /// Width of the --side-by-side output, the terminal's width by default
+    #[arg(long, value_name = "COLUMNS", requires = "side_by_side")]
+    width: Option<usize>,
+
+    /// Print a `git diff --stat` style summary instead of starting the TUI, exiting 1 when
+    /// the files differ
+    #[arg(long, conflicts_with = "print")]
+    stat: bool,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() {
     let args = Cli::parse();
+    // Like `diff`, trouble exits 2 with `--exit-code` so it can't pass for a difference
+    let trouble = if args.exit_code { 2 } else { 1 };
 
-    // Validate files if provided, before entering TUI mode
-    if let Some(source) = &args.source
-        && let Err(e) = diff::validate_file(source, "Source")
-    {
+    if let Err(e) = run(args, trouble) {
         eprintln!("Error: {}", e);
-        process::exit(1);
+        process::exit(trouble);
     }
+}
 
-    if let Some(target) = &args.target
-        && let Err(e) = diff::validate_file(target, "Target")
-    {
-        eprintln!("Error: {}", e);
-        process::exit(1);
+fn run(mut args: Cli, trouble: i32) -> Result<(), Box<dyn std::error::Error>> {
+    // Clap makes sure each side is given only one way
+    args.source = args.source.or(args.source_flag.take());
+    args.target = args.target.or(args.target_flag.take());
+
+    // The listed pairs take the place of file arguments
+    if let Some(list) = &args.list {
+        match source::read_manifest(list) {
+            Ok(pairs) => {
+                let mut files = pairs
+                    .into_iter()
+                    .flat_map(|(source, target)| [source, target]);
+                args.source = files.next();
+                args.target = files.next();
+                args.more = files.collect();
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(trouble);
+            }
+        }
+    }
+
+    if args.no_follow_symlinks {
+        let paths = [&args.source, &args.target, &args.git, &args.from_clipboard]
+            .into_iter()
+            .chain([&args.apply, &args.conflicts])
+            .flatten()
+            .chain(&args.more)
+            .chain(args.git_range.iter().flat_map(|range| range.last()));
+        for path in paths.filter(|path| *path != "-") {
+            if let Err(e) = diff::reject_symlink(path) {
+                eprintln!("Error: {}", e);
+                process::exit(trouble);
+            }
+        }
+    }
+
+    // Compare directories instead of files
+    let dir_summary = match (&args.source, &args.target) {
+        (Some(source), Some(target)) if args.recursive => {
+            let changes = dirdiff::validate_dir(source, "Source")
+                .and_then(|_| dirdiff::validate_dir(target, "Target"))
+                .and_then(|_| {
+                    dirdiff::compare_dirs(
+                        Path::new(source),
+                        Path::new(target),
+                        args.algorithm,
+                        !args.no_follow_symlinks,
+                    )
+                });
+            match changes {
+                Ok(changes) if changes.is_empty() => {
+                    println!("No differences between '{}' and '{}'", source, target);
+                    return Ok(());
+                }
+                Ok(changes) => Some(DirSummary::new(
+                    PathBuf::from(source),
+                    PathBuf::from(target),
+                    changes,
+                )),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(trouble);
+                }
+            }
+        }
+        _ => None,
+    };
+
+    // Validate files if provided, before entering TUI mode
+    let source = args.source.as_deref().map(DiffSource::from_arg);
+    let target = args.target.as_deref().map(DiffSource::from_arg);
+    if !args.recursive {
+        let checks = [(&source, "Source"), (&target, "Target")];
+        for (side, name) in checks {
+            if let Some(Err(e)) = side.as_ref().map(|side| side.validate(name)) {
+                eprintln!("Error: {}", e);
+                process::exit(trouble);
+            }
+        }
+        if let (Some(source), Some(target)) = (&source, &target)
+            && let Err(e) = source::check_single_stdin(source, target)
+        {
+            eprintln!("Error: {}", e);
+            process::exit(trouble);
+        }
+        // The file browser can't pick the other side of stdin
+        if source == Some(DiffSource::Stdin) && target.is_none() {
+            eprintln!("Error: Reading the source from stdin needs a target file as well");
+            process::exit(trouble);
+        }
+    }
+
+    if !args.more.len().is_multiple_of(2) {
+        eprintln!("Error: Files must be given in source and target pairs");
+        process::exit(trouble);
+    }
+
+    for pair in args.more.chunks(2) {
+        let valid = diff::validate_file(&pair[0], "Source")
+            .and_then(|_| diff::validate_file(&pair[1], "Target"));
+        if let Err(e) = valid {
+            eprintln!("Error: {}", e);
+            process::exit(trouble);
+        }
+    }
+
+    // Read the committed version up front so git errors are reported before the TUI starts
+    let git_source = match &args.git {
+        Some(path) => {
+            let mut source = DiffSource::GitObject {
+                rev: "HEAD".to_string(),
+                path: path.clone(),
+            };
+            let loaded = diff::validate_file(path, "Target").and_then(|_| source.preload());
+            match loaded {
+                Ok(()) => Some((source, DiffSource::File(PathBuf::from(path)))),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(trouble);
+                }
+            }
+        }
+        None => None,
+    };
+
+    // A file that didn't exist at one of the revisions is compared as an empty file
+    let git_range = match args.git_range.as_deref() {
+        Some([rev1, rev2, path]) => {
+            let sides = git::file_at_revision(rev1, path)
+                .and_then(|source| Ok((source, git::file_at_revision(rev2, path)?)));
+            match sides {
+                Ok(((_, None), (_, None))) => {
+                    eprintln!("Error: '{}' doesn't exist at {} or {}", path, rev1, rev2);
+                    process::exit(trouble);
+                }
+                Ok(((source_hash, source), (target_hash, target))) => {
+                    let side = |hash, content: Option<FileContent>| DiffSource::Memory {
+                        label: format!("{}:{}", hash, path),
+                        content: content.unwrap_or_else(|| diff::decode_content(b"")),
+                    };
+                    Some((side(source_hash, source), side(target_hash, target)))
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(trouble);
+                }
+            }
+        }
+        _ => None,
+    };
+
+    // Grab the clipboard contents before entering TUI mode
+    let clipboard_source = match &args.from_clipboard {
+        Some(path) => {
+            let mut source = DiffSource::Clipboard;
+            let mut target = DiffSource::from_arg(path);
+            let loaded = target
+                .validate("Target")
+                .and_then(|_| source.preload())
+                .and_then(|_| target.preload());
+            match loaded {
+                Ok(()) => Some((source, target)),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(trouble);
+                }
+            }
+        }
+        None => None,
+    };
+
+    // Parse patches up front so malformed ones are reported before the TUI starts
+    let patch = match &args.apply {
+        Some(path) => {
+            let parsed = diff::validate_file(path, "Patch")
+                .and_then(|_| fs::read_to_string(path).map_err(|e| e.to_string()))
+                .and_then(|text| diff::parse_patch(&text));
+            match parsed {
+                Ok(patch) => Some(patch),
+                Err(e) => {
+                    eprintln!("Error: Invalid patch '{}': {}", path, e);
+                    process::exit(trouble);
+                }
+            }
+        }
+        None => None,
+    };
+
+    // Split conflicted files into both sides before entering TUI mode
+    let conflict_sides = match &args.conflicts {
+        Some(path) => {
+            let sides = diff::validate_file(path, "Conflicted")
+                .and_then(|_| diff::read_file(path).map_err(|e| e.to_string()))
+                .and_then(|content| diff::split_conflict_sides(&content.text));
+            match sides {
+                Ok(Some((ours, theirs))) => Some((path.clone(), ours, theirs)),
+                Ok(None) => {
+                    println!("Nothing to resolve: '{}' has no conflict markers", path);
+                    return Ok(());
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(trouble);
+                }
+            }
+        }
+        None => None,
+    };
+
+    // The two sides to compare, when they're known up front
+    let files = if let Some((path, ours, theirs)) = conflict_sides {
+        Some((
+            DiffSource::Memory {
+                label: format!("ours:{}", path),
+                content: diff::decode_content(ours.as_bytes()),
+            },
+            DiffSource::Memory {
+                label: format!("theirs:{}", path),
+                content: diff::decode_content(theirs.as_bytes()),
+            },
+        ))
+    } else if let Some(patch) = patch {
+        // Rebuild both sides from the context and changed lines in the patch
+        let side = |label: &str, side| {
+            let text = diff::extract_side(&patch.diff_lines, side, None);
+            DiffSource::Memory {
+                label: label.to_string(),
+                content: diff::decode_content(text.as_bytes()),
+            }
+        };
+        Some((
+            side(&patch.source_file, Side::Source),
+            side(&patch.target_file, Side::Target),
+        ))
+    } else if let Some(sides) = clipboard_source.or(git_range).or(git_source) {
+        Some(sides)
+    } else {
+        match (source, target) {
+            (Some(mut source), Some(mut target)) => {
+                // Stdin is read here, files are read by the app so they can be reloaded
+                match source.preload().and_then(|_| target.preload()) {
+                    Ok(()) => Some((source, target)),
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        process::exit(trouble);
+                    }
+                }
+            }
+            _ => None,
+        }
+    };
+
+    let patch_format = if args.git_format {
+        PatchFormat::Git
+    } else {
+        PatchFormat::Plain
+    };
+
+    if args.print || args.stat {
+        let Some(first) = files else {
+            eprintln!("Error: --print and --stat need both a source and a target file");
+            process::exit(trouble);
+        };
+
+        let mut pairs = vec![first];
+        pairs.extend(args.more.chunks(2).map(|pair| {
+            (
+                DiffSource::from_arg(&pair[0]),
+                DiffSource::from_arg(&pair[1]),
+            )
+        }));
+        let ranges = (args.source_range, args.target_range);
+
+        let identical = if let Some(command) = &args.external {
+            pairs
+                .into_iter()
+                .try_fold(true, |identical, (mut source, mut target)| {
+                    let output = external::run_sources(command, &mut source, &mut target)?;
+                    print!("{}", output);
+                    Ok::<_, String>(identical && output.is_empty())
+                })
+                .map_err(io::Error::other)
+        } else if args.stat {
+            print_stat(pairs, args.algorithm, ranges)
+        } else if args.format == OutputFormat::Json {
+            print_json(pairs, args.algorithm, ranges)
+        } else if args.side_by_side {
+            let width = args.width.unwrap_or_else(terminal_width);
+            pairs
+                .into_iter()
+                .try_fold(true, |identical, (source, target)| {
+                    let columns = (width, args.tab_width);
+                    let pair_identical =
+                        print_side_by_side(source, target, args.algorithm, columns, ranges)?;
+                    Ok::<_, io::Error>(identical && pair_identical)
+                })
+        } else {
+            pairs
+                .into_iter()
+                .try_fold(true, |identical, (source, target)| {
+                    let pair_identical = print_diff(
+                        source,
+                        target,
+                        args.algorithm,
+                        (patch_format, context(&args, None)),
+                        ranges,
+                    )?;
+                    Ok::<_, io::Error>(identical && pair_identical)
+                })
+        };
+
+        match identical {
+            Ok(true) => return Ok(()),
+            Ok(false) => process::exit(1),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(trouble);
+            }
+        }
     }
 
+    // Load the optional config file and resolve the theme before entering TUI mode
+    let (theme, config) = match Config::load().and_then(|config| {
+        Theme::resolve(args.theme.as_deref(), &config.theme).map(|theme| (theme, config))
+    }) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(trouble);
+        }
+    };
+
+    let status_timeout = match (args.status_timeout, config.status_timeout) {
+        (Some(timeout), _) => Some(timeout),
+        (None, Some(seconds)) => match Duration::try_from_secs_f64(seconds) {
+            Ok(timeout) => Some(timeout),
+            Err(_) => {
+                eprintln!("Error: Invalid status_timeout {} in config", seconds);
+                process::exit(trouble);
+            }
+        },
+        (None, None) => None,
+    };
+    // Zero turns the timeout off again, e.g. to override the config file
+    let status_timeout = status_timeout.filter(|timeout| !timeout.is_zero());
+
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -52,32 +591,88 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
+    let ascii = args.ascii || glyphs::locale_is_ascii();
+    if files.is_some() {
+        terminal.draw(|f| ui::render_reading_notice(f, ascii))?;
+    }
+
+    let options = AppOptions {
+        algorithm: args.algorithm,
+        source_range: args.source_range,
+        target_range: args.target_range,
+        max_lines: args.max_lines.map(NonZeroUsize::get),
+        external_command: args.external.clone(),
+        state_path: State::default_path(),
+        read_only: args.read_only,
+    };
+
     // Create app based on provided arguments
-    let app = match (&args.source, &args.target) {
-        (Some(source), Some(target)) => {
-            // Both files provided - create app normally
-            App::new(source.clone(), target.clone())?
-        }
-        (Some(source), None) => {
-            // Source provided, need to select target
-            let mut app = App::new_empty(AppMode::SelectingTarget)?;
-            app.source_file = source.clone();
-            app.status_message = Some(format!("Source: {} - Select target file", source));
-            app
-        }
-        (None, Some(target)) => {
-            // Target provided, need to select source
-            let mut app = App::new_empty(AppMode::SelectingSource)?;
-            app.target_file = target.clone();
-            app.status_message = Some(format!("Target: {} - Select source file", target));
+    let mut app = match files {
+        _ if dir_summary.is_some() => {
+            let mut app = App::with_options(AppMode::DirSummary, options)?;
+            app.dir_summary = dir_summary;
+            app.status_message = None;
             app
         }
-        (None, None) => {
-            // No files provided - start by selecting source
-            App::new_empty(AppMode::SelectingSource)?
-        }
+        Some((source, target)) => App::with_sources(source, target, options)?,
+        None => match (&args.source, &args.target) {
+            (Some(source), _) => {
+                // Source provided, need to select target
+                let mut app = App::with_options(AppMode::SelectingTarget, options)?;
+                app.source = DiffSource::from_arg(source);
+                app.status_message = Some(format!("Source: {} - Select target file", source));
+                app
+            }
+            (None, Some(target)) => {
+                // Target provided, need to select source
+                let mut app = App::with_options(AppMode::SelectingSource, options)?;
+                app.target = DiffSource::from_arg(target);
+                app.status_message = Some(format!("Target: {} - Select source file", target));
+                app
+            }
+            (None, None) => {
+                // No files provided - start by selecting source
+                App::with_options(AppMode::SelectingSource, options)?
+            }
+        },
     };
 
+    app.theme = theme;
+    if args.bright {
+        app.theme.dim = false;
+    }
+    app.ascii = ascii;
+    app.wrap_around = args.wrap_around;
+    app.scroll_accel.max_step = args
+        .max_scroll_step
+        .or(config.max_scroll_step)
+        .map_or(app::DEFAULT_MAX_SCROLL_STEP, NonZeroUsize::get);
+    app.max_line_length = args
+        .max_line_length
+        .or(config.max_line_length)
+        .map(NonZeroUsize::get);
+    app.confirm_quit = args.confirm_quit;
+    app.osc52 = io::stdout().is_terminal();
+    app.context = context(&args, config.context);
+    app.patch_format = patch_format;
+    app.export_dir = args.export_dir.or(config.export_dir);
+    if let Some(extensions) = &args.ext {
+        app.file_browser.extensions = browser::parse_extensions(extensions);
+        app.file_browser.load_entries()?;
+    }
+    app.status_timeout = status_timeout;
+    app.tab_width = args.tab_width;
+    app.show_tabs = args.show_tabs;
+    app.wrap_indent = args.wrap_indent;
+    app.granularity = args.granularity;
+    app.file_browser.follow_symlinks = !args.no_follow_symlinks;
+
+    // Every further pair gets its own tab, the first pair stays in view
+    for pair in args.more.chunks(2) {
+        app.open_tab(pair[0].clone(), pair[1].clone())?;
+    }
+    app.switch_tab(0);
+
     let res = app::run_app(&mut terminal, app);
 
     // Restore terminal
@@ -89,10 +684,185 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     )?;
     terminal.show_cursor()?;
 
-    if let Err(err) = res {
-        eprintln!("Error: {}", err);
-        process::exit(1);
+    match res {
+        Ok(true) if args.exit_code => process::exit(1),
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The unchanged lines kept around changes. Flags and their environment variables (clap
+/// falls back to those) win over the config file, which wins over the built-in default.
+fn context(args: &Cli, configured: Option<usize>) -> Context {
+    let context = args.context.or(configured).unwrap_or(app::DEFAULT_CONTEXT);
+    Context {
+        before: args.context_before.unwrap_or(context),
+        after: args.context_after.unwrap_or(context),
+    }
+}
+
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    seconds
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("invalid duration '{}', expected seconds", seconds))
+}
+
+/// Reads both sides of a pair, narrowed to the line ranges.
+fn read_sides(
+    source: &mut DiffSource,
+    target: &mut DiffSource,
+    ranges: (Option<LineRange>, Option<LineRange>),
+) -> Result<(FileContent, FileContent), io::Error> {
+    let mut source = source.load().map_err(io::Error::other)?;
+    let mut target = target.load().map_err(io::Error::other)?;
+
+    if let Some(range) = ranges.0 {
+        source.text = diff::slice_lines(&source.text, range);
+    }
+    if let Some(range) = ranges.1 {
+        target.text = diff::slice_lines(&target.text, range);
+    }
+
+    Ok((source, target))
+}
+
+/// Prints a `git diff --stat` style summary of the pairs that differ. Returns true when
+/// all pairs are identical.
+fn print_stat(
+    pairs: Vec<(DiffSource, DiffSource)>,
+    algorithm: Algorithm,
+    ranges: (Option<LineRange>, Option<LineRange>),
+) -> Result<bool, io::Error> {
+    let mut entries = Vec::new();
+
+    for (mut source_side, mut target_side) in pairs {
+        let (source, target) = read_sides(&mut source_side, &mut target_side, ranges)?;
+        let (source_file, target_file) = (source_side.label(), target_side.label());
+
+        let change = if source.binary || target.binary {
+            if source.text == target.text {
+                continue;
+            }
+            StatChange::Binary(source.stats.bytes, target.stats.bytes)
+        } else {
+            let diff_lines = diff::generate_diff(&source.text, &target.text, algorithm);
+            if diff::is_identical(&diff_lines) {
+                continue;
+            }
+            StatChange::Lines(diff::count_changes(&diff_lines))
+        };
+
+        let name = if source_file == target_file {
+            source_file
+        } else {
+            format!("{} => {}", source_file, target_file)
+        };
+        entries.push((name, change));
+    }
+
+    if entries.is_empty() {
+        return Ok(true);
+    }
+
+    print!("{}", diff::format_stat(&entries, terminal_width()));
+    Ok(false)
+}
+
+/// Columns of the terminal the output is printed to, or 80 when it's piped.
+fn terminal_width() -> usize {
+    terminal::size()
+        .map(|(width, _)| width as usize)
+        .unwrap_or(80)
+}
+
+/// Prints the diffs of all pairs as one JSON document. Returns true when all pairs are
+/// identical.
+fn print_json(
+    pairs: Vec<(DiffSource, DiffSource)>,
+    algorithm: Algorithm,
+    ranges: (Option<LineRange>, Option<LineRange>),
+) -> Result<bool, io::Error> {
+    let mut files = Vec::new();
+    for (mut source_side, mut target_side) in pairs {
+        let (source, target) = read_sides(&mut source_side, &mut target_side, ranges)?;
+        files.push(json::FileDiff::new(
+            source_side.label(),
+            target_side.label(),
+            &source,
+            &target,
+            algorithm,
+            ranges,
+        ));
+    }
+
+    println!("{}", json::to_json(&files).map_err(io::Error::other)?);
+    Ok(files.iter().all(|file| file.identical))
+}
+
+/// Writes the two files next to each other to stdout, `columns` being the width of the
+/// output and the tab width. Returns whether the files are identical, which prints nothing.
+fn print_side_by_side(
+    mut source_side: DiffSource,
+    mut target_side: DiffSource,
+    algorithm: Algorithm,
+    columns: (usize, usize),
+    ranges: (Option<LineRange>, Option<LineRange>),
+) -> Result<bool, io::Error> {
+    let (source, target) = read_sides(&mut source_side, &mut target_side, ranges)?;
+    let (source_file, target_file) = (source_side.label(), target_side.label());
+
+    if source.binary || target.binary {
+        println!("Binary files {} and {} differ", source_file, target_file);
+        return Ok(false);
+    }
+
+    let diff_lines = diff::generate_diff(&source.text, &target.text, algorithm);
+    if diff::is_identical(&diff_lines) {
+        return Ok(true);
+    }
+
+    let (width, tab_width) = columns;
+    print!(
+        "{}",
+        diff::format_side_by_side(&source_file, &target_file, &diff_lines, width, tab_width)
+    );
+    Ok(false)
+}
+
+/// Writes the patch for the two files to stdout. Returns whether the files are identical,
+/// in which case nothing is printed, the same way `diff` stays quiet.
+fn print_diff(
+    mut source_side: DiffSource,
+    mut target_side: DiffSource,
+    algorithm: Algorithm,
+    (patch_format, context): (PatchFormat, Context),
+    ranges: (Option<LineRange>, Option<LineRange>),
+) -> Result<bool, io::Error> {
+    let (source, target) = read_sides(&mut source_side, &mut target_side, ranges)?;
+    let (source_file, target_file) = (source_side.label(), target_side.label());
+
+    if source.binary || target.binary {
+        println!("Binary files {} and {} differ", source_file, target_file);
+        return Ok(false);
+    }
+
+    let diff_lines = diff::generate_diff(&source.text, &target.text, algorithm);
+    if diff::is_identical(&diff_lines) {
+        return Ok(true);
     }
 
-    Ok(())
+    print!(
+        "{}",
+        diff::generate_patch(
+            &source_file,
+            &target_file,
+            &diff_lines,
+            None,
+            patch_format,
+            context,
+        )
+    );
+    Ok(false)
 }
@@ -0,0 +1,291 @@
+use crate::diff::{self, FileContent};
+use crate::git;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+
+/// Where one side of the diff comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffSource {
+    File(PathBuf),
+    /// Standard input, given as `-` on the command line
+    Stdin,
+    Clipboard,
+    /// A file as committed at a revision, e.g. `HEAD`
+    GitObject {
+        rev: String,
+        path: String,
+    },
+    /// Content that only exists in memory, like one side of a conflict or a patch
+    Memory {
+        label: String,
+        content: FileContent,
+    },
+}
+
+/// No file picked yet.
+impl Default for DiffSource {
+    fn default() -> Self {
+        DiffSource::File(PathBuf::new())
+    }
+}
+
+impl DiffSource {
+    /// A file path, or stdin for `-` like most command line tools.
+    pub fn from_arg(arg: &str) -> Self {
+        if arg == "-" {
+            DiffSource::Stdin
+        } else {
+            DiffSource::File(PathBuf::from(arg))
+        }
+    }
+
+    /// The name shown in the header and patch headers.
+    pub fn label(&self) -> String {
+        match self {
+            DiffSource::File(path) => path.display().to_string(),
+            DiffSource::Stdin => "<stdin>".to_string(),
+            DiffSource::Clipboard => "<clipboard>".to_string(),
+            DiffSource::GitObject { rev, path } => format!("{}:{}", rev, path),
+            DiffSource::Memory { label, .. } => label.clone(),
+        }
+    }
+
+    pub fn is_unset(&self) -> bool {
+        matches!(self, DiffSource::File(path) if path.as_os_str().is_empty())
+    }
+
+    /// Whether reading the source again is cheap and can't fail in a new way. Stdin can
+    /// only be read once, and the clipboard or git shouldn't be asked again on every reload.
+    pub fn is_rereadable(&self) -> bool {
+        matches!(self, DiffSource::File(_) | DiffSource::Memory { .. })
+    }
+
+    /// A file that was deleted or renamed since it was picked.
+    pub fn is_missing(&self) -> bool {
+        matches!(self, DiffSource::File(path) if !path.exists())
+    }
+
+    /// Checks the source can be read before the TUI starts. `side` is "Source" or "Target".
+    pub fn validate(&self, side: &str) -> Result<(), String> {
+        match self {
+            DiffSource::File(path) => diff::validate_file(&path.display().to_string(), side),
+            // A terminal on stdin would wait for typing instead of a pipe
+            DiffSource::Stdin if io::stdin().is_terminal() => {
+                Err("Nothing was piped to stdin for '-'".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn read_content(&self) -> Result<FileContent, String> {
+        match self {
+            DiffSource::File(path) => diff::read_file(&path.display().to_string())
+                .map_err(|e| format!("Failed to read '{}': {}", path.display(), e)),
+            DiffSource::Stdin => {
+                let mut bytes = Vec::new();
+                io::stdin()
+                    .read_to_end(&mut bytes)
+                    .map_err(|e| format!("Failed to read stdin: {}", e))?;
+                Ok(diff::decode_content(&bytes))
+            }
+            DiffSource::Clipboard => {
+                diff::read_clipboard().map(|text| diff::decode_content(text.as_bytes()))
+            }
+            DiffSource::GitObject { rev, path } => git::show_file_at(rev, path),
+            DiffSource::Memory { content, .. } => Ok(content.clone()),
+        }
+    }
+
+    /// Reads the content, keeping it in memory when the source isn't re-readable so later
+    /// diffs of it (e.g. a reload) use the same text.
+    pub fn load(&mut self) -> Result<FileContent, String> {
+        let content = self.read_content()?;
+        if !self.is_rereadable() {
+            *self = DiffSource::Memory {
+                label: self.label(),
+                content: content.clone(),
+            };
+        }
+        Ok(content)
+    }
+
+    /// Like `load`, but stops reading a file after `max_lines` lines, or within a line
+    /// far longer than any normal one. The flag tells whether a line was cut off.
+    pub fn load_head(&mut self, max_lines: Option<usize>) -> Result<(FileContent, bool), String> {
+        match (&*self, max_lines) {
+            (DiffSource::File(path), Some(max_lines)) => {
+                diff::read_file_head(&path.display().to_string(), max_lines)
+                    .map_err(|e| format!("Failed to read '{}': {}", path.display(), e))
+            }
+            _ => self.load().map(|content| (content, false)),
+        }
+    }
+
+    /// Loads sources that aren't re-readable right away, so their errors are reported
+    /// before the TUI starts. Files are left for the app to read.
+    pub fn preload(&mut self) -> Result<(), String> {
+        if !self.is_rereadable() {
+            self.load()?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the file pairs listed in a manifest, `-` reads the manifest from stdin. Relative
+/// paths in a manifest file are relative to the directory it's in, those read from stdin
+/// to the current directory.
+pub fn read_manifest(path: &str) -> Result<Vec<(String, String)>, String> {
+    let contents = if path == "-" {
+        let mut contents = String::new();
+        io::stdin()
+            .read_to_string(&mut contents)
+            .map_err(|e| format!("Failed to read the file list from stdin: {}", e))?;
+        contents
+    } else {
+        fs::read_to_string(path).map_err(|e| format!("Failed to read '{}': {}", path, e))?
+    };
+
+    let pairs =
+        parse_manifest(&contents).map_err(|e| format!("Invalid file list '{}': {}", path, e))?;
+    if path == "-"
+        && pairs
+            .iter()
+            .any(|(source, target)| source == "-" || target == "-")
+    {
+        return Err("Stdin is already used for the file list".to_string());
+    }
+
+    let dir = match path {
+        "-" => Path::new(""),
+        _ => Path::new(path).parent().unwrap_or(Path::new("")),
+    };
+    let resolve = |file: String| match file.as_str() {
+        "-" => file,
+        _ => dir.join(&file).display().to_string(),
+    };
+    Ok(pairs
+        .into_iter()
+        .map(|(source, target)| (resolve(source), resolve(target)))
+        .collect())
+}
+
+/// Parses a manifest with a `source<TAB>target` pair on each line. Blank lines and lines
+/// starting with `#` are skipped.
+pub fn parse_manifest(contents: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.split('\t').collect::<Vec<_>>()[..] {
+            [source, target] if !source.is_empty() && !target.is_empty() => {
+                pairs.push((source.to_string(), target.to_string()));
+            }
+            _ => {
+                return Err(format!(
+                    "line {} should be a source and a target separated by a tab",
+                    number + 1
+                ));
+            }
+        }
+    }
+
+    if pairs.is_empty() {
+        return Err("it lists no files".to_string());
+    }
+    Ok(pairs)
+}
+
+/// Stdin can only be read once, so it can't be both sides.
+pub fn check_single_stdin(source: &DiffSource, target: &DiffSource) -> Result<(), String> {
+    if *source == DiffSource::Stdin && *target == DiffSource::Stdin {
+        return Err("Only one side can be read from stdin".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_arg_reads_dash_as_stdin() {
+        assert_eq!(DiffSource::from_arg("-"), DiffSource::Stdin);
+        assert_eq!(
+            DiffSource::from_arg("notes.txt"),
+            DiffSource::File(PathBuf::from("notes.txt"))
+        );
+        assert_eq!(DiffSource::Stdin.label(), "<stdin>");
+        assert_eq!(DiffSource::Clipboard.label(), "<clipboard>");
+    }
+
+    #[test]
+    fn test_files_are_read_again_on_every_load() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "text\n")?;
+        let mut source = DiffSource::File(path.clone());
+
+        source.preload()?;
+        assert_eq!(source, DiffSource::File(path.clone()));
+        assert_eq!(source.load()?.text, "text\n");
+
+        std::fs::write(&path, "edited\n")?;
+        assert_eq!(source.load()?.text, "edited\n");
+        assert_eq!(source.label(), path.display().to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_keeps_its_label_and_content() -> Result<(), Box<dyn std::error::Error>> {
+        let content = diff::decode_content(b"ours\n");
+        let mut source = DiffSource::Memory {
+            label: "ours:a.txt".to_string(),
+            content: content.clone(),
+        };
+
+        assert!(source.is_rereadable());
+        assert!(!source.is_missing());
+        assert_eq!(source.load()?, content);
+        assert_eq!(source.label(), "ours:a.txt");
+
+        let git = DiffSource::GitObject {
+            rev: "HEAD".to_string(),
+            path: "src/main.rs".to_string(),
+        };
+        assert_eq!(git.label(), "HEAD:src/main.rs");
+        assert!(!git.is_rereadable());
+        assert!(DiffSource::default().is_unset());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let pairs = parse_manifest("# old\tnew\na.txt\tb.txt\n\nold dir/c.rs\tnew/c.rs\r\n");
+        assert_eq!(
+            pairs,
+            Ok(vec![
+                ("a.txt".to_string(), "b.txt".to_string()),
+                ("old dir/c.rs".to_string(), "new/c.rs".to_string()),
+            ])
+        );
+
+        let err = parse_manifest("a\tb\nc d\n").unwrap_err();
+        assert!(err.contains("line 2"), "{}", err);
+        assert!(parse_manifest("a\tb\tc\n").is_err());
+        assert!(parse_manifest("\tb\n").is_err());
+        assert!(parse_manifest("# nothing\n").is_err());
+    }
+
+    #[test]
+    fn test_stdin_can_only_be_one_side() {
+        let file = DiffSource::from_arg("a.txt");
+
+        assert!(check_single_stdin(&DiffSource::Stdin, &file).is_ok());
+        assert!(check_single_stdin(&DiffSource::Clipboard, &DiffSource::Stdin).is_ok());
+        assert!(check_single_stdin(&DiffSource::Stdin, &DiffSource::Stdin).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use crate::config::ThemeConfig;
+use crate::diff::{DiffLine, LineKind};
+use ratatui::style::{Color, Modifier, Style};
+use similar::ChangeTag;
+use std::str::FromStr;
+
+/// Names accepted by `--theme` and the `name` key in the config file.
+pub const THEME_NAMES: &[&str] = &["default", "high-contrast", "mono", "colorblind"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub insert: Color,
+    pub delete: Color,
+    pub equal: Color,
+    /// Deleted lines followed by the lines that replace them, and those inserted lines
+    pub modified: Color,
+    pub selection: Color,
+    pub dim: bool,
+    /// Show the `+`/`-` prefix of changed lines in bold, so they stand out without color
+    pub bold_prefix: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            insert: Color::Green,
+            delete: Color::Red,
+            equal: Color::Reset,
+            modified: Color::Yellow,
+            selection: Color::Blue,
+            dim: true,
+            bold_prefix: false,
+        }
+    }
+}
+
+impl Theme {
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Theme::default()),
+            "high-contrast" => Some(Theme {
+                insert: Color::LightGreen,
+                delete: Color::LightRed,
+                equal: Color::White,
+                modified: Color::LightYellow,
+                selection: Color::Blue,
+                dim: false,
+                bold_prefix: false,
+            }),
+            "mono" => Some(Theme {
+                insert: Color::Reset,
+                delete: Color::Reset,
+                equal: Color::Reset,
+                modified: Color::Reset,
+                selection: Color::DarkGray,
+                dim: false,
+                bold_prefix: false,
+            }),
+            // Blue and orange stay apart for red-green color blindness, the bold prefixes
+            // tell the lines apart for everyone else
+            "colorblind" => Some(Theme {
+                insert: Color::Indexed(33),
+                delete: Color::Indexed(208),
+                equal: Color::Reset,
+                modified: Color::Indexed(175),
+                selection: Color::DarkGray,
+                dim: false,
+                bold_prefix: true,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Builds the theme from a named base theme with the config file values on top.
+    ///
+    /// The `name` argument (from `--theme`) takes precedence over the name in the config.
+    pub fn resolve(name: Option<&str>, config: &ThemeConfig) -> Result<Self, String> {
+        let name = name.or(config.name.as_deref()).unwrap_or("default");
+        let mut theme = Theme::named(name).ok_or_else(|| {
+            format!(
+                "Unknown theme '{}', expected one of: {}",
+                name,
+                THEME_NAMES.join(", ")
+            )
+        })?;
+
+        if let Some(color) = &config.insert {
+            theme.insert = parse_color(color)?;
+        }
+        if let Some(color) = &config.delete {
+            theme.delete = parse_color(color)?;
+        }
+        if let Some(color) = &config.equal {
+            theme.equal = parse_color(color)?;
+        }
+        if let Some(color) = &config.modified {
+            theme.modified = parse_color(color)?;
+        }
+        if let Some(color) = &config.selection {
+            theme.selection = parse_color(color)?;
+        }
+        if let Some(dim) = config.dim {
+            theme.dim = dim;
+        }
+
+        Ok(theme)
+    }
+
+    /// Style for the diff content, never dimmed so it stays readable.
+    pub fn content_style(&self, tag: ChangeTag) -> Style {
+        match tag {
+            ChangeTag::Delete => Style::default().fg(self.delete),
+            ChangeTag::Insert => Style::default().fg(self.insert),
+            ChangeTag::Equal => Style::default().fg(self.equal),
+        }
+    }
+
+    /// Style for the `+`/`-` prefix, dimmed or bold for changes depending on the theme.
+    pub fn prefix_style(&self, tag: ChangeTag) -> Style {
+        let style = self.content_style(tag);
+        if tag == ChangeTag::Equal {
+            return style;
+        }
+        self.changed_prefix(style)
+    }
+
+    /// Like `content_style`, but in the modified color for lines that replace each other.
+    pub fn line_style(&self, line: &DiffLine) -> Style {
+        match line.kind {
+            LineKind::Modified => Style::default().fg(self.modified),
+            LineKind::Standalone => self.content_style(line.tag),
+        }
+    }
+
+    /// Like `prefix_style`, but in the modified color for lines that replace each other.
+    pub fn line_prefix_style(&self, line: &DiffLine) -> Style {
+        match line.kind {
+            LineKind::Modified => self.changed_prefix(self.line_style(line)),
+            LineKind::Standalone => self.prefix_style(line.tag),
+        }
+    }
+
+    fn changed_prefix(&self, mut style: Style) -> Style {
+        if self.dim {
+            style = style.add_modifier(Modifier::DIM);
+        }
+        if self.bold_prefix {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        style
+    }
+}
+
+fn parse_color(value: &str) -> Result<Color, String> {
+    Color::from_str(value).map_err(|_| format!("Invalid color '{}' in theme", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_without_config_is_default() {
+        let theme = Theme::resolve(None, &ThemeConfig::default()).unwrap();
+
+        assert_eq!(theme, Theme::default());
+    }
+
+    #[test]
+    fn test_resolve_applies_config_overrides() {
+        let config = ThemeConfig {
+            delete: Some("#ff5555".to_string()),
+            dim: Some(false),
+            ..ThemeConfig::default()
+        };
+
+        let theme = Theme::resolve(None, &config).unwrap();
+
+        assert_eq!(theme.delete, Color::Rgb(0xff, 0x55, 0x55));
+        assert_eq!(theme.insert, Color::Green);
+        assert!(!theme.dim);
+    }
+
+    #[test]
+    fn test_resolve_flag_overrides_config_name() {
+        let config = ThemeConfig {
+            name: Some("mono".to_string()),
+            ..ThemeConfig::default()
+        };
+
+        let theme = Theme::resolve(Some("high-contrast"), &config).unwrap();
+
+        assert_eq!(theme, Theme::named("high-contrast").unwrap());
+    }
+
+    #[test]
+    fn test_resolve_rejects_unknown_theme_and_color() {
+        assert!(Theme::resolve(Some("neon"), &ThemeConfig::default()).is_err());
+
+        let config = ThemeConfig {
+            insert: Some("not-a-color".to_string()),
+            ..ThemeConfig::default()
+        };
+        assert!(Theme::resolve(None, &config).is_err());
+    }
+
+    #[test]
+    fn test_content_style_is_never_dimmed() {
+        let theme = Theme::default();
+
+        assert_eq!(
+            theme.content_style(ChangeTag::Delete),
+            Style::default().fg(Color::Red)
+        );
+        assert_eq!(
+            theme.content_style(ChangeTag::Insert),
+            Style::default().fg(Color::Green)
+        );
+    }
+
+    #[test]
+    fn test_colorblind_theme_avoids_red_and_green() {
+        let theme = Theme::named("colorblind").unwrap();
+
+        assert_eq!(
+            theme.content_style(ChangeTag::Insert),
+            Style::default().fg(Color::Indexed(33))
+        );
+        assert_eq!(
+            theme.content_style(ChangeTag::Delete),
+            Style::default().fg(Color::Indexed(208))
+        );
+        assert_eq!(
+            theme.prefix_style(ChangeTag::Delete),
+            Style::default()
+                .fg(Color::Indexed(208))
+                .add_modifier(Modifier::BOLD)
+        );
+        assert_eq!(
+            theme.prefix_style(ChangeTag::Equal),
+            Style::default().fg(Color::Reset)
+        );
+    }
+
+    #[test]
+    fn test_prefix_style_respects_dim() {
+        let mut theme = Theme::default();
+        assert!(
+            theme
+                .prefix_style(ChangeTag::Insert)
+                .add_modifier
+                .contains(Modifier::DIM)
+        );
+        assert!(
+            !theme
+                .prefix_style(ChangeTag::Equal)
+                .add_modifier
+                .contains(Modifier::DIM)
+        );
+
+        theme.dim = false;
+        assert_eq!(
+            theme.prefix_style(ChangeTag::Insert),
+            Style::default().fg(Color::Green)
+        );
+    }
+
+    #[test]
+    fn test_modified_lines_use_the_modified_color() {
+        let theme = Theme::default();
+        let mut line = DiffLine {
+            tag: ChangeTag::Insert,
+            content: "new".to_string(),
+            new_line: Some(1),
+            missing_newline: false,
+            kind: LineKind::Modified,
+        };
+
+        assert_eq!(theme.line_style(&line), Style::default().fg(Color::Yellow));
+        assert_eq!(
+            theme.line_prefix_style(&line),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::DIM)
+        );
+
+        line.kind = LineKind::Standalone;
+        assert_eq!(
+            theme.line_style(&line),
+            theme.content_style(ChangeTag::Insert)
+        );
+    }
+}
@@ -1,117 +1,1243 @@
-use crate::app::{App, AppMode};
+use crate::app::{App, AppMode, ExportPreview, PendingDiff};
+use crate::browser;
+use crate::diff::{self, ChangeCounts, DiffLine, DiffRow, FileStats, Granularity, LineRange, Side};
+use crate::dirdiff::{ChangeKind, TreeRow};
+use crate::glyphs::{self, Glyphs};
+use crate::theme::Theme;
 use ratatui::{
     Frame,
-    layout::{Constraint, Direction, Layout},
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Margin, Position, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    widgets::{
+        Block, Borders, Clear, List, ListItem, ListState, Paragraph, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, StatefulWidget, Widget,
+    },
 };
 use similar::ChangeTag;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::SystemTime;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 pub fn render_ui(f: &mut Frame, app: &App) {
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(3), // Header with file names
-            Constraint::Min(0),    // Diff content or file browser
-            Constraint::Length(3), // Status bar
-        ])
-        .split(f.area());
+    let hide_chrome = app.chrome_hidden();
+    let chunks = main_layout(f.area(), hide_chrome);
 
     // Header with file names
-    render_header(f, app, chunks[0]);
+    if !hide_chrome {
+        render_header(f, app, chunks[0]);
+    }
 
     // Main content area - either diff view or file browser
     match app.mode {
-        AppMode::DiffView | AppMode::SelectionMode => {
+        AppMode::DiffView => {
             render_diff_view(f, app, chunks[1]);
         }
+        AppMode::SelectionMode => {
+            render_selection_view(f, app, chunks[1]);
+        }
         AppMode::SelectingSource | AppMode::SelectingTarget => {
             render_file_browser(f, app, chunks[1]);
         }
+        AppMode::DirSummary => {
+            render_dir_summary(f, app, chunks[1]);
+        }
     }
 
     // Status bar
-    render_status_bar(f, app, chunks[2]);
+    if !hide_chrome {
+        render_status_bar(f, app, chunks[2]);
+    }
+
+    if app.show_help {
+        render_help_popup(f, app);
+    }
+
+    if let Some(content) = &app.line_popup {
+        render_line_popup(f, app, content);
+    }
+
+    if let Some(preview) = &app.export_preview {
+        render_export_preview(f, app, preview);
+    }
 }
 
-fn render_header(f: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+/// Splits the terminal into the header, the main content area and the status bar. With
+/// `hide_chrome` the header and status bar are left empty and the content gets everything.
+pub fn main_layout(area: Rect, hide_chrome: bool) -> Rc<[Rect]> {
+    let bar_height = if hide_chrome { 0 } else { 3 };
+    Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(bar_height), // Header with file names
+            Constraint::Min(0),             // Diff content or file browser
+            Constraint::Length(bar_height), // Status bar
+        ])
+        .split(area)
+}
+
+/// Number of rows available inside the main content area, without its borders.
+///
+/// Rendering and scrolling both use this so they agree on what is visible.
+pub fn content_height(app: &App, terminal_area: Rect) -> usize {
+    let area = main_layout(terminal_area, app.chrome_hidden())[1];
+    area.height.saturating_sub(border_size(app)) as usize
+}
+
+/// The block around a diff pane, nothing at all while the chrome is hidden.
+fn pane_block<'a>(app: &App, title: impl Into<Line<'a>>) -> Block<'a> {
+    if app.chrome_hidden() {
+        Block::default()
+    } else {
+        Block::default().borders(Borders::ALL).title(title)
+    }
+}
+
+/// Rows and columns the borders of a diff pane take up.
+fn border_size(app: &App) -> u16 {
+    if app.chrome_hidden() { 0 } else { 2 }
+}
+
+fn inner_height(area: Rect) -> usize {
+    area.height.saturating_sub(2) as usize
+}
+
+fn render_header(f: &mut Frame, app: &App, area: Rect) {
+    // The directory summary shows both directories instead of the last opened files
+    let summary = app
+        .dir_summary
+        .as_ref()
+        .filter(|_| app.mode == AppMode::DirSummary);
+    let (source_stats, target_stats) = match summary {
+        Some(_) => (String::new(), String::new()),
+        None => (
+            app.source_stats.map(format_stats).unwrap_or_default()
+                + &format_range(app.source_range),
+            app.target_stats.map(format_stats).unwrap_or_default()
+                + &format_range(app.target_range),
+        ),
+    };
+    let line_endings = match app.line_endings {
+        Some((source, target)) if app.normalize_line_endings => format!(
+            "  line endings differ ({} vs {}), normalized",
+            source.name(),
+            target.name()
+        ),
+        Some((source, target)) => format!(
+            "  line endings differ ({} vs {})",
+            source.name(),
+            target.name()
+        ),
+        None => String::new(),
+    };
+    let truncated = match app.max_lines {
+        Some(max_lines) if app.truncated && summary.is_none() => {
+            format!("  truncated to {} lines", max_lines)
+        }
+        _ => String::new(),
+    };
+    let whitespace = match app.ignore_whitespace && summary.is_none() {
+        true => "  whitespace ignored",
+        false => "",
+    };
+    // Copying still works through the terminal with OSC 52, but it's worth knowing upfront
+    let clipboard = match app.copy_method() {
+        _ if app.clipboard.is_some() || app.read_only => "",
+        Some(_) => "  no clipboard, copying via OSC 52",
+        None => "  no clipboard",
+    };
+    let warnings = line_endings + &truncated + whitespace + clipboard;
+
+    // Paths give way to the counts when the header gets too narrow
+    let fixed_width = "Source: ".len()
+        + source_stats.chars().count()
+        + "  ".len()
+        + "Target: ".len()
+        + target_stats.chars().count()
+        + warnings.chars().count();
+    let budget = (area.width.saturating_sub(2) as usize).saturating_sub(fixed_width);
+    let (source_file, target_file) = match summary {
+        Some(summary) => (
+            summary.source_dir.display().to_string(),
+            summary.target_dir.display().to_string(),
+        ),
+        _ => (app.source.label(), app.target.label()),
+    };
+    let (source_width, target_width) = split_width(
+        source_file.chars().count(),
+        target_file.chars().count(),
+        budget,
+    );
+
+    let stats_style = Style::default().fg(Color::DarkGray);
     let header = Paragraph::new(vec![Line::from(vec![
         Span::styled("Source: ", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(&app.source_file),
+        Span::raw(truncate_path(
+            &source_file,
+            source_width,
+            app.glyphs().ellipsis,
+        )),
+        Span::styled(source_stats, stats_style),
         Span::raw("  "),
         Span::styled("Target: ", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(&app.target_file),
+        Span::raw(truncate_path(
+            &target_file,
+            target_width,
+            app.glyphs().ellipsis,
+        )),
+        Span::styled(target_stats, stats_style),
+        Span::styled(warnings, Style::default().fg(app.theme.modified)),
     ])])
-    .block(Block::default().borders(Borders::ALL).title("Files"));
+    .block(Block::default().borders(Borders::ALL).title(tab_bar(app)));
 
     f.render_widget(header, area);
 }
 
-fn render_diff_view(f: &mut Frame, app: &App, area: ratatui::layout::Rect) {
-    let content_height = area.height.saturating_sub(2) as usize;
-    let selection_range = app.get_selection_range();
+/// The header title: "Files", or the file names of every tab when several pairs are open.
+fn tab_bar(app: &App) -> Line<'_> {
+    if app.tab_count() == 1 {
+        return Line::from("Files");
+    }
 
-    let visible_lines: Vec<Line> = app
-        .diff_lines
-        .iter()
-        .enumerate()
-        .skip(app.scroll_offset)
-        .take(content_height)
-        .map(|(idx, diff_line)| {
-            let (prefix, fg_style) = match diff_line.tag {
-                ChangeTag::Delete => (
-                    "-",
-                    Style::default().fg(Color::Red).add_modifier(Modifier::DIM),
-                ),
-                ChangeTag::Insert => (
-                    "+",
-                    Style::default()
-                        .fg(Color::Green)
-                        .add_modifier(Modifier::DIM),
-                ),
-                ChangeTag::Equal => (" ", Style::default()),
+    let file_name = |path: &str| {
+        Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string())
+    };
+
+    let mut spans = Vec::new();
+    for (index, (source, target)) in app.tab_files().into_iter().enumerate() {
+        let label = format!(
+            " {}: {} {} {} ",
+            index + 1,
+            file_name(&source),
+            app.glyphs().swap,
+            file_name(&target)
+        );
+        let style = if index == app.active_tab {
+            Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        spans.push(Span::styled(label, style));
+    }
+
+    Line::from(spans)
+}
+
+fn format_stats(stats: FileStats) -> String {
+    match stats.lines {
+        // An empty file is a side of its own, with every line of the other one changed
+        _ if stats.bytes == 0 => " (empty)".to_string(),
+        Some(1) => format!(" ({}, 1 line)", diff::format_size(stats.bytes)),
+        Some(lines) => format!(" ({}, {} lines)", diff::format_size(stats.bytes), lines),
+        None => format!(" ({}, binary)", diff::format_size(stats.bytes)),
+    }
+}
+
+fn format_range(range: Option<LineRange>) -> String {
+    range
+        .map(|range| format!(" [{}]", range))
+        .unwrap_or_default()
+}
+
+/// Divides the width left for the two paths, giving a short path's leftover to the other.
+fn split_width(source_len: usize, target_len: usize, budget: usize) -> (usize, usize) {
+    let half = budget / 2;
+    if source_len <= half {
+        (source_len, budget - source_len)
+    } else if target_len <= budget - half {
+        (budget - target_len, target_len)
+    } else {
+        (half, budget - half)
+    }
+}
+
+/// Shortens a path to at most `max_width` characters by dropping directories from the middle.
+///
+/// The file name is always kept, e.g. `/home/…/project/src/main.rs`. When even that doesn't
+/// fit it falls back to `…/main.rs` and finally to the tail of the file name. The dropped
+/// parts are marked with `ellipsis`.
+fn truncate_path(path: &str, max_width: usize, ellipsis: &str) -> String {
+    let width = |s: &str| s.chars().count();
+    if width(path) <= max_width {
+        return path.to_string();
+    }
+
+    let parts: Vec<&str> = path.split('/').collect();
+    let (file_name, dirs) = parts.split_last().expect("split yields at least one part");
+
+    // Keep the first directory (including the root of absolute paths) when there's room
+    let kept = if dirs.first() == Some(&"") { 2 } else { 1 };
+    let heads = if dirs.len() > kept {
+        vec![
+            format!("{}/{}/", dirs[..kept].join("/"), ellipsis),
+            format!("{}/", ellipsis),
+        ]
+    } else {
+        vec![format!("{}/", ellipsis)]
+    };
+
+    // Then add as many trailing directories as fit in front of the file name
+    for head in heads {
+        let mut tail = file_name.to_string();
+        if width(&head) + width(&tail) > max_width {
+            continue;
+        }
+        for dir in dirs.iter().skip(kept).rev() {
+            let candidate = format!("{}/{}", dir, tail);
+            if width(&head) + width(&candidate) > max_width {
+                break;
+            }
+            tail = candidate;
+        }
+        return format!("{}{}", head, tail);
+    }
+
+    if max_width < width(ellipsis) {
+        return String::new();
+    }
+    let tail: String = file_name
+        .chars()
+        .skip(width(file_name).saturating_sub(max_width - width(ellipsis)))
+        .collect();
+    format!("{}{}", ellipsis, tail)
+}
+
+fn render_diff_view(f: &mut Frame, app: &App, area: Rect) {
+    if app.reload_requested {
+        render_notice(f, area, &reading_message(app.glyphs()));
+        return;
+    }
+    if let Some(pending) = &app.pending_diff {
+        render_pending_notice(f, area, pending, app.glyphs());
+        return;
+    }
+
+    // Two empty files would otherwise render as a blank box
+    if app.diff_lines.is_empty() {
+        render_notice(f, area, "Files are identical (both empty)");
+        return;
+    }
+
+    if app.side_by_side {
+        render_side_by_side(f, app, area);
+        return;
+    }
+
+    let name = match &app.external_command {
+        Some(command) => format!("Diff ({})", command),
+        None if app.binary => "Diff (hex)".to_string(),
+        None => "Diff".to_string(),
+    };
+    let title = if app.is_identical() {
+        Line::from(vec![
+            Span::raw(format!("{} ", name)),
+            Span::styled(
+                " Files are identical ",
+                Style::default().add_modifier(Modifier::REVERSED),
+            ),
+        ])
+    } else {
+        Line::from(name)
+    };
+
+    let area = render_minimap(f, app, area);
+    let diff_list = DiffList {
+        app,
+        title,
+        selection_mode: false,
+    };
+    let mut state = diff_list.list_state();
+    f.render_stateful_widget(diff_list, area, &mut state);
+    if !app.chrome_hidden() {
+        render_scrollbar(f, area, app.rows.len(), app.scroll_offset);
+    }
+}
+
+fn render_selection_view(f: &mut Frame, app: &App, area: Rect) {
+    if app.reload_requested {
+        render_notice(f, area, &reading_message(app.glyphs()));
+        return;
+    }
+    if let Some(pending) = &app.pending_diff {
+        render_pending_notice(f, area, pending, app.glyphs());
+        return;
+    }
+
+    let area = render_minimap(f, app, area);
+    let diff_list = DiffList {
+        app,
+        title: Line::from("Diff - SELECTION MODE"),
+        selection_mode: true,
+    };
+    let mut state = diff_list.list_state();
+    f.render_stateful_widget(diff_list, area, &mut state);
+    if !app.chrome_hidden() {
+        render_scrollbar(f, area, app.rows.len(), app.scroll_offset);
+    }
+}
+
+const MINIMAP_WIDTH: u16 = 2;
+
+/// Columns the minimap takes from the diff list, which is the full width when it's off.
+fn minimap_width(app: &App) -> u16 {
+    if app.show_minimap && app.external_output.is_none() {
+        MINIMAP_WIDTH
+    } else {
+        0
+    }
+}
+
+/// Draws the minimap on the right edge of `area` when it's on and returns what's left
+/// for the diff list.
+fn render_minimap(f: &mut Frame, app: &App, area: Rect) -> Rect {
+    let width = minimap_width(app);
+    if width == 0 {
+        return area;
+    }
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(0), Constraint::Length(width)])
+        .split(area);
+    // Line up with the rows inside the list's borders
+    let strip = columns[1].inner(Margin {
+        vertical: border_size(app) / 2,
+        horizontal: 0,
+    });
+    f.render_widget(Minimap { app }, strip);
+    columns[0]
+}
+
+/// An overview of the whole diff squeezed into the height of the view, each row colored
+/// by the changes among the lines it stands for. The left column shows deletions and the
+/// right one insertions, so a row with both is half and half.
+struct Minimap<'a> {
+    app: &'a App,
+}
+
+impl Widget for Minimap<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let lines = &self.app.diff_lines;
+        let height = area.height as usize;
+        if lines.is_empty() || height == 0 {
+            return;
+        }
+
+        let theme = &self.app.theme;
+        let equal = Style::default().fg(Color::DarkGray);
+        // Short diffs get a row per line instead of being stretched
+        let rows = height.min(lines.len());
+        for y in 0..rows {
+            let chunk = &lines[y * lines.len() / rows..(y + 1) * lines.len() / rows];
+            let deleted = chunk.iter().any(|line| line.tag == ChangeTag::Delete);
+            let inserted = chunk.iter().any(|line| line.tag == ChangeTag::Insert);
+
+            let delete = Style::default().fg(theme.delete);
+            let insert = Style::default().fg(theme.insert);
+            let (left, right) = match (deleted, inserted) {
+                (false, false) => (equal, equal),
+                (true, false) => (delete, delete),
+                (false, true) => (insert, insert),
+                (true, true) => (delete, insert),
             };
+            let glyphs = self.app.glyphs();
+            let symbol = if deleted || inserted {
+                glyphs.minimap_change
+            } else {
+                glyphs.minimap_equal
+            };
+            for x in 0..area.width {
+                let style = if x < area.width / 2 { left } else { right };
+                buf[(area.x + x, area.y + y as u16)]
+                    .set_symbol(symbol)
+                    .set_style(style);
+            }
+        }
+    }
+}
+
+/// Shows the source and target next to each other, each pane with its own scroll offset.
+fn render_side_by_side(f: &mut Frame, app: &App, area: Rect) {
+    let panes = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+
+    let sides = [
+        (
+            panes[0],
+            Side::Source,
+            app.source.label(),
+            app.scroll_offset,
+        ),
+        (
+            panes[1],
+            Side::Target,
+            app.target.label(),
+            app.target_pane_offset(),
+        ),
+    ];
+    for (pane, side, file, offset) in sides {
+        let lines: Vec<Line> = app
+            .rows
+            .iter()
+            .skip(offset)
+            .take(pane.height.saturating_sub(border_size(app)) as usize)
+            .map(|row| {
+                let (line, other) = match (*row, side) {
+                    (DiffRow::Pair { source, target }, Side::Source) => (source, target),
+                    (DiffRow::Pair { source, target }, Side::Target) => (target, source),
+                    _ => (None, None),
+                };
+                let line = match (line, other) {
+                    (Some(idx), Some(other)) if app.binary => {
+                        hex_pair_line(app, &app.diff_lines[idx], &app.diff_lines[other])
+                    }
+                    (Some(idx), Some(other))
+                        if idx != other && app.granularity == Granularity::Char =>
+                    {
+                        char_pair_line(app, &app.diff_lines[idx], &app.diff_lines[other])
+                    }
+                    (Some(idx), _) => styled_diff_line(app, &app.diff_lines[idx]),
+                    (None, _) => Line::default(),
+                };
+                cut_line(app, line)
+            })
+            .collect();
 
-            // Determine background color for full-width highlighting
-            let mut bg_style = Style::default();
+        // Point out which pane scrolls when they move independently
+        let active = !app.sync_scroll && app.active_pane == side;
+        let title_width = pane.width.saturating_sub(4) as usize;
+        let mut block = pane_block(
+            app,
+            truncate_path(&file, title_width, app.glyphs().ellipsis),
+        );
+        if active {
+            block = block
+                .border_style(Style::default().fg(Color::Cyan))
+                .title_style(Style::default().add_modifier(Modifier::BOLD));
+        }
 
-            // Highlight selected lines in selection mode
-            if let Some((start, end)) = selection_range
-                && idx >= start
-                && idx <= end
+        f.render_widget(Paragraph::new(lines).block(block), pane);
+        if !app.chrome_hidden() {
+            render_scrollbar(f, pane, app.rows.len(), offset);
+        }
+    }
+}
+
+/// The diff rows in view as a list, with the cursor as the list's selected item.
+///
+/// Only the rows from `scroll_offset` onwards become items, so the list state is relative
+/// to the top of the viewport and large diffs don't cost more per frame.
+struct DiffList<'a> {
+    app: &'a App,
+    title: Line<'a>,
+    selection_mode: bool,
+}
+
+impl DiffList<'_> {
+    fn list_state(&self) -> ListState {
+        // Plain scrolling has no cursor unless it was switched on, the folded view always has one
+        let has_cursor = self.selection_mode || self.app.cursor_driven();
+        let cursor = self
+            .app
+            .cursor_position
+            .saturating_sub(self.app.scroll_offset);
+
+        ListState::default().with_selected(has_cursor.then_some(cursor))
+    }
+
+    fn highlight_symbol(&self) -> &'static str {
+        if self.selection_mode { ">" } else { "" }
+    }
+
+    /// Columns left for the text of each row in a list this wide, inside the borders.
+    fn text_width(&self, list_width: u16) -> usize {
+        (list_width.saturating_sub(border_size(self.app)) as usize)
+            .saturating_sub(self.highlight_symbol().width())
+    }
+
+    /// A row wrapped to `width` columns. At most `max_rows` rows are wrapped, which is all
+    /// that fits on screen, so a huge single line doesn't cost more than a screenful.
+    fn item(&self, row: &DiffRow, width: usize, max_rows: usize) -> ListItem<'_> {
+        let (line, style) = self.row_line(row);
+
+        // Without wrapping the list clips long lines at the edge
+        if self.app.wrap && self.app.max_line_length.is_none() {
+            let indent = continuation_gutter(self.app, row);
+            ListItem::new(wrap_line(line, width, max_rows, &indent)).style(style)
+        } else {
+            ListItem::new(line).style(style)
+        }
+    }
+
+    /// The styled line of a row along with the selection highlight, before wrapping.
+    fn row_line(&self, row: &DiffRow) -> (Line<'_>, Style) {
+        let app = self.app;
+        let mut line = styled_row(app, row);
+        let mut style = Style::default();
+
+        if self.selection_mode {
+            let (first, last) = row.line_range();
+
+            // Highlight the selected range
+            if let Some((start, end)) = app.get_selection_range()
+                && first <= end
+                && last >= start
             {
-                bg_style = bg_style.bg(Color::Blue);
+                style = style.bg(app.theme.selection);
+            }
+
+            // Mark the anchor while the selection is still following the cursor
+            let is_pending_anchor = app.selection_pending
+                && app
+                    .selection_start
+                    .is_some_and(|anchor| anchor >= first && anchor <= last);
+            if is_pending_anchor {
+                style = style.add_modifier(Modifier::UNDERLINED);
             }
 
-            // Highlight the current line in selection mode (overrides selection)
-            if app.mode == AppMode::SelectionMode && idx == app.cursor_position {
-                bg_style = bg_style.bg(Color::DarkGray);
+            let marker = if is_pending_anchor { "*" } else { " " };
+            line.spans.insert(
+                0,
+                Span::styled(marker, Style::default().add_modifier(Modifier::BOLD)),
+            );
+        }
+
+        (line, style)
+    }
+}
+
+impl StatefulWidget for DiffList<'_> {
+    type State = ListState;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut ListState) {
+        let symbol = self.highlight_symbol();
+        let highlight_style = if self.selection_mode {
+            // The cursor overrides the selection highlight
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().bg(Color::DarkGray)
+        };
+
+        let width = self.text_width(area.width);
+        let height = area.height.saturating_sub(border_size(self.app)) as usize;
+        let items: Vec<ListItem> = self
+            .app
+            .rows
+            .iter()
+            .skip(self.app.scroll_offset)
+            .take(height)
+            .map(|row| self.item(row, width, height))
+            .collect();
+
+        let list = List::new(items)
+            .block(pane_block(self.app, self.title.clone()))
+            .highlight_style(highlight_style)
+            .highlight_symbol(symbol);
+
+        StatefulWidget::render(list, area, buf, state);
+    }
+}
+
+/// Display rows a diff row takes up in the diff view of a terminal `terminal_width`
+/// columns wide, more than one when a long line wraps.
+pub fn row_height(app: &App, row: &DiffRow, terminal_width: u16) -> usize {
+    // The side-by-side panes clip long lines, and cut lines fit a row by design
+    if !app.wrap || app.side_by_side || app.max_line_length.is_some() {
+        return 1;
+    }
+
+    let diff_list = DiffList {
+        app,
+        title: Line::default(),
+        selection_mode: app.mode == AppMode::SelectionMode,
+    };
+    let list_width = terminal_width.saturating_sub(minimap_width(app));
+    let (line, _) = diff_list.row_line(row);
+    let indent = continuation_gutter(app, row);
+    wrapped_height(&line, diff_list.text_width(list_width), span_width(&indent))
+}
+
+/// What the wrapped rows of a diff line start with under `--wrap-indent`: an empty
+/// gutter, so they hang under the line's content. Other rows wrap from the first column.
+fn continuation_gutter(app: &App, row: &DiffRow) -> Vec<Span<'static>> {
+    match row {
+        DiffRow::Line(_) if app.wrap_indent && app.external_output.is_none() => vec![
+            Span::raw(" "),
+            Span::styled(app.glyphs().gutter, Style::default().fg(Color::DarkGray)),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+/// Display columns of spans, counting wide characters twice.
+fn span_width(spans: &[Span<'_>]) -> usize {
+    spans.iter().map(|span| span.content.width()).sum()
+}
+
+/// Number of rows `wrap_line` breaks a line into, without building them.
+fn wrapped_height(line: &Line<'_>, width: usize, indent: usize) -> usize {
+    if width == 0 || span_width(&line.spans) <= width {
+        return 1;
+    }
+
+    // Rows too narrow for the indent wrap without it, like `wrap_line`
+    let indent = if indent < width { indent } else { 0 };
+    let mut rows = 1;
+    let mut current_width = 0;
+    for c in line.spans.iter().flat_map(|span| span.content.chars()) {
+        let char_width = c.width().unwrap_or(0);
+        if current_width > 0 && current_width + char_width > width {
+            rows += 1;
+            current_width = indent;
+        }
+        current_width += char_width;
+    }
+    rows
+}
+
+/// Breaks a line into rows of at most `width` columns, keeping the span styles. A wide
+/// character that doesn't fit at the end of a row starts the next one. The rows after the
+/// first start with `indent`, unless that leaves no room. Stops after `max_rows` rows.
+fn wrap_line<'a>(
+    line: Line<'a>,
+    width: usize,
+    max_rows: usize,
+    indent: &[Span<'a>],
+) -> Vec<Line<'a>> {
+    if width == 0 || span_width(&line.spans) <= width {
+        return vec![line];
+    }
+
+    let indent = if span_width(indent) < width {
+        indent
+    } else {
+        &[]
+    };
+    let mut rows: Vec<Line> = Vec::new();
+    let mut current: Vec<Span> = Vec::new();
+    let mut current_width = 0;
+
+    'spans: for span in line.spans {
+        let mut chunk = String::new();
+        for c in span.content.chars() {
+            let char_width = c.width().unwrap_or(0);
+            if current_width > 0 && current_width + char_width > width {
+                if !chunk.is_empty() {
+                    current.push(Span::styled(std::mem::take(&mut chunk), span.style));
+                }
+                rows.push(Line::from(std::mem::take(&mut current)).style(line.style));
+                if rows.len() == max_rows {
+                    break 'spans;
+                }
+                current.extend_from_slice(indent);
+                current_width = span_width(indent);
             }
+            chunk.push(c);
+            current_width += char_width;
+        }
+        if !chunk.is_empty() {
+            current.push(Span::styled(chunk, span.style));
+        }
+    }
+    if !current.is_empty() {
+        rows.push(Line::from(current).style(line.style));
+    }
 
-            Line::from(vec![
-                Span::styled(prefix, fg_style),
-                Span::styled(&diff_line.content, fg_style),
-            ])
-            .style(bg_style)
+    rows
+}
+
+/// Styled rows kept between frames. Styling a row, above all a changed line highlighted by
+/// character, costs more than drawing it, and the same rows are drawn over and over while
+/// the view sits still or scrolls a line at a time.
+///
+/// `App::rebuild_rows` empties the cache for a new diff, the settings rows are styled
+/// with are compared on every lookup.
+#[derive(Default)]
+pub struct RowCache {
+    settings: Option<StyleSettings>,
+    rows: HashMap<DiffRow, Line<'static>>,
+}
+
+impl RowCache {
+    pub fn clear(&mut self) {
+        self.rows.clear();
+    }
+}
+
+#[derive(PartialEq)]
+struct StyleSettings {
+    theme: Theme,
+    tab_width: usize,
+    show_tabs: bool,
+    show_whitespace: bool,
+    granularity: Granularity,
+    ascii: bool,
+    max_line_length: Option<usize>,
+    binary: bool,
+}
+
+impl StyleSettings {
+    fn of(app: &App) -> Self {
+        StyleSettings {
+            theme: app.theme.clone(),
+            tab_width: app.tab_width,
+            show_tabs: app.show_tabs,
+            show_whitespace: app.show_whitespace,
+            granularity: app.granularity,
+            ascii: app.ascii,
+            max_line_length: app.max_line_length,
+            binary: app.binary,
+        }
+    }
+}
+
+/// The styled line of a row, from the cache when it was styled before.
+fn styled_row(app: &App, row: &DiffRow) -> Line<'static> {
+    let settings = StyleSettings::of(app);
+    let mut cache = app.row_cache.borrow_mut();
+    if cache.settings.as_ref() != Some(&settings) {
+        cache.rows.clear();
+        cache.settings = Some(settings);
+    }
+
+    cache
+        .rows
+        .entry(*row)
+        .or_insert_with(|| {
+            let line = style_row(app, row);
+            Line {
+                spans: line
+                    .spans
+                    .into_iter()
+                    .map(|span| Span::styled(span.content.into_owned(), span.style))
+                    .collect(),
+                ..line
+            }
         })
-        .collect();
+        .clone()
+}
 
-    let title = if app.mode == AppMode::SelectionMode {
-        "Diff - SELECTION MODE"
+fn style_row<'a>(app: &'a App, row: &DiffRow) -> Line<'a> {
+    if let (Some(output), DiffRow::Line(idx)) = (&app.external_output, row) {
+        let spans = output[*idx].spans.iter().flat_map(|span| {
+            content_spans(
+                &span.content,
+                span.style,
+                app.tab_width,
+                app.show_tabs,
+                app.show_whitespace,
+                app.glyphs(),
+            )
+        });
+        return Line::from(spans.collect::<Vec<_>>());
+    }
+
+    match *row {
+        DiffRow::Line(idx) => {
+            let partner = match app.granularity {
+                Granularity::Char if !app.binary => diff::partner_line(&app.diff_lines, idx),
+                _ => None,
+            };
+            let line = match partner {
+                Some(other) => char_pair_line(app, &app.diff_lines[idx], &app.diff_lines[other]),
+                None => styled_diff_line(app, &app.diff_lines[idx]),
+            };
+            cut_line(app, line)
+        }
+        // Only the side-by-side view uses pairs, it renders each side on its own
+        DiffRow::Pair { source, target } => target
+            .or(source)
+            .map(|idx| cut_line(app, styled_diff_line(app, &app.diff_lines[idx])))
+            .unwrap_or_default(),
+        DiffRow::Fold { start, end } => Line::from(Span::styled(
+            format!(
+                " {ellipsis} {} unchanged lines {ellipsis}",
+                end - start + 1,
+                ellipsis = app.glyphs().ellipsis
+            ),
+            Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::ITALIC),
+        )),
+        DiffRow::Skip { start, end } => Line::from(Span::styled(
+            format!("{} {} lines skipped", app.glyphs().skip, end - start + 1),
+            Style::default().fg(Color::DarkGray),
+        )),
+    }
+}
+
+/// Columns of the gutter in front of each diff line, see `gutter_spans`.
+const GUTTER_WIDTH: usize = 2;
+
+/// The gutter in front of a diff line: the `+`/`-` marker in the theme's prefix style and
+/// a faint separator. It's styled apart from the content, so the marker still stands out
+/// when the content isn't colored.
+fn gutter_spans(app: &App, diff_line: &DiffLine) -> Vec<Span<'static>> {
+    let marker = match diff_line.tag {
+        ChangeTag::Delete => "-",
+        ChangeTag::Insert => "+",
+        ChangeTag::Equal => " ",
+    };
+
+    vec![
+        Span::styled(marker, app.theme.line_prefix_style(diff_line)),
+        Span::styled(app.glyphs().gutter, Style::default().fg(Color::DarkGray)),
+    ]
+}
+
+/// A styled diff line cut to `max_line_length` columns of content, when that's set.
+fn cut_line<'a>(app: &App, mut line: Line<'a>) -> Line<'a> {
+    if let Some(max_line_length) = app.max_line_length {
+        line.spans = cut_spans(
+            line.spans,
+            GUTTER_WIDTH + max_line_length,
+            app.glyphs().ellipsis,
+        );
+    }
+    line
+}
+
+/// Cuts spans wider than `max_width` columns, ending them with a `marker` within that
+/// width in the dimmed style of the text it stands for, so it follows the theme. Cuts fall
+/// between graphemes, so characters made of several code points stay whole.
+fn cut_spans<'a>(spans: Vec<Span<'a>>, max_width: usize, marker: &'static str) -> Vec<Span<'a>> {
+    if span_width(&spans) <= max_width {
+        return spans;
+    }
+
+    let budget = max_width.saturating_sub(marker.width());
+    let mut width = 0;
+    let mut cut = Vec::new();
+    let mut marker_style = Style::default();
+    for span in spans {
+        marker_style = span.style.add_modifier(Modifier::DIM);
+        let mut end = 0;
+        for grapheme in span.content.graphemes(true) {
+            if width + grapheme.width() > budget {
+                break;
+            }
+            width += grapheme.width();
+            end += grapheme.len();
+        }
+
+        let full = end == span.content.len();
+        if end > 0 {
+            let text = match span.content {
+                Cow::Borrowed(text) => Cow::Borrowed(&text[..end]),
+                Cow::Owned(text) => Cow::Owned(text[..end].to_string()),
+            };
+            cut.push(Span::styled(text, span.style));
+        }
+        if !full {
+            break;
+        }
+    }
+    cut.push(Span::styled(marker, marker_style));
+    cut
+}
+
+/// Whether `max_line_length` cuts a line with this content, with its tabs expanded.
+pub fn is_cut(content: &str, max_line_length: usize, tab_width: usize) -> bool {
+    let tab_width = tab_width.max(1);
+    let width = content.graphemes(true).fold(0, |column, grapheme| {
+        if grapheme == "\t" {
+            column + tab_width - column % tab_width
+        } else {
+            column + grapheme.width()
+        }
+    });
+    width > max_line_length
+}
+
+fn styled_diff_line<'a>(app: &App, diff_line: &'a DiffLine) -> Line<'a> {
+    let mut spans = gutter_spans(app, diff_line);
+    spans.extend(content_spans(
+        &diff_line.content,
+        app.theme.line_style(diff_line),
+        app.tab_width,
+        app.show_tabs,
+        app.show_whitespace,
+        app.glyphs(),
+    ));
+    Line::from(spans)
+}
+
+/// A changed hex dump row with the characters that differ from the other side's row
+/// reversed, so the changed bytes stand out.
+fn hex_pair_line<'a>(app: &App, diff_line: &'a DiffLine, other: &DiffLine) -> Line<'a> {
+    let mut line = styled_diff_line(app, diff_line);
+    if diff_line.tag == ChangeTag::Equal {
+        return line;
+    }
+
+    let style = app.theme.line_style(diff_line);
+    let other: Vec<char> = other.content.chars().collect();
+    line.spans = gutter_spans(app, diff_line);
+
+    let mut run = String::new();
+    let mut run_differs = false;
+    for (column, c) in diff_line.content.chars().enumerate() {
+        let differs = other.get(column) != Some(&c);
+        if differs != run_differs && !run.is_empty() {
+            line.spans
+                .push(hex_run(std::mem::take(&mut run), style, run_differs));
+        }
+        run_differs = differs;
+        run.push(c);
+    }
+    if !run.is_empty() {
+        line.spans.push(hex_run(run, style, run_differs));
+    }
+
+    line
+}
+
+/// A changed line with the characters that differ from the line it replaces (or is
+/// replaced by) reversed, for `--granularity char`.
+fn char_pair_line<'a>(app: &App, diff_line: &'a DiffLine, other: &DiffLine) -> Line<'a> {
+    let mut line = Line::from(gutter_spans(app, diff_line));
+    let style = app.theme.line_style(diff_line);
+
+    for (run, changed) in diff::char_runs(&diff_line.content, &other.content, diff_line.tag) {
+        let style = if changed {
+            style.add_modifier(Modifier::REVERSED)
+        } else {
+            style
+        };
+        let spans = content_spans(
+            &run,
+            style,
+            app.tab_width,
+            app.show_tabs,
+            app.show_whitespace,
+            app.glyphs(),
+        );
+        line.spans.extend(
+            spans
+                .into_iter()
+                .map(|span| Span::styled(span.content.into_owned(), span.style)),
+        );
+    }
+
+    line
+}
+
+fn hex_run(text: String, style: Style, differs: bool) -> Span<'static> {
+    if differs {
+        Span::styled(text, style.add_modifier(Modifier::REVERSED))
     } else {
-        "Diff"
+        Span::styled(text, style)
+    }
+}
+
+/// Splits line content into spans with tabs expanded to the next tab stop.
+///
+/// This only affects the display, the content used for patches keeps its whitespace. With
+/// `show_tabs` each tab starts with a faint `→` so it can be told apart from spaces, with
+/// `show_whitespace` trailing spaces and tabs are marked as `·` and `→` (or their `glyphs`).
+fn content_spans<'a>(
+    content: &'a str,
+    style: Style,
+    tab_width: usize,
+    show_tabs: bool,
+    show_whitespace: bool,
+    glyphs: &Glyphs,
+) -> Vec<Span<'a>> {
+    let trailing_start = if show_whitespace {
+        content.trim_end_matches([' ', '\t']).len()
+    } else {
+        content.len()
     };
+    if !content.contains('\t') && trailing_start == content.len() {
+        return vec![Span::styled(content, style)];
+    }
+
+    let tab_width = tab_width.max(1);
+    let whitespace_style = Style::default().fg(Color::DarkGray);
+    let mut spans = Vec::new();
+    let mut column = 0;
+    let mut segment_start = 0;
+
+    for (idx, c) in content.char_indices() {
+        let trailing = idx >= trailing_start;
+        let replacement = match c {
+            '\t' => {
+                let width = tab_width - column % tab_width;
+                column += width;
+                let text = if show_tabs || trailing {
+                    format!("{}{}", glyphs.tab, " ".repeat(width - 1))
+                } else {
+                    " ".repeat(width)
+                };
+                let tab_style = if trailing {
+                    whitespace_style
+                } else if show_tabs {
+                    style.add_modifier(Modifier::DIM)
+                } else {
+                    style
+                };
+                Span::styled(text, tab_style)
+            }
+            ' ' if trailing => {
+                column += 1;
+                Span::styled(glyphs.space, whitespace_style)
+            }
+            _ => {
+                column += c.width().unwrap_or(0);
+                continue;
+            }
+        };
 
-    let diff_widget = Paragraph::new(visible_lines)
-        .block(Block::default().borders(Borders::ALL).title(title))
-        .wrap(Wrap { trim: false });
+        if segment_start < idx {
+            spans.push(Span::styled(&content[segment_start..idx], style));
+        }
+        spans.push(replacement);
+        segment_start = idx + c.len_utf8();
+    }
+
+    if segment_start < content.len() {
+        spans.push(Span::styled(&content[segment_start..], style));
+    }
 
-    f.render_widget(diff_widget, area);
+    spans
+}
+
+fn render_pending_notice(f: &mut Frame, area: Rect, pending: &PendingDiff, glyphs: &Glyphs) {
+    let frame = (pending.started.elapsed().as_millis() / 100) as usize % glyphs.spinner.len();
+    render_notice(
+        f,
+        area,
+        &format!(
+            "{} Computing diff{}",
+            glyphs.spinner[frame], glyphs.ellipsis
+        ),
+    );
+}
+
+/// Drawn before the files are read at startup, so big files don't leave a blank screen.
+pub fn render_reading_notice(f: &mut Frame, ascii: bool) {
+    render_notice(
+        f,
+        main_layout(f.area(), false)[1],
+        &reading_message(Glyphs::get(ascii)),
+    );
+}
+
+/// Reading happens between two frames, so the spinner stands still on its first frame.
+fn reading_message(glyphs: &Glyphs) -> String {
+    format!("{} Reading files{}", glyphs.spinner[0], glyphs.ellipsis)
+}
+
+/// Shows a single italic message in place of the diff.
+fn render_notice(f: &mut Frame, area: Rect, message: &str) {
+    let notice = Paragraph::new(Line::from(Span::styled(
+        message,
+        Style::default().add_modifier(Modifier::ITALIC),
+    )))
+    .block(Block::default().borders(Borders::ALL).title("Diff"));
+
+    f.render_widget(notice, area);
+}
+
+/// Lists the changed files of a directory diff as a tree, with the inserted and deleted
+/// lines of each file and added up for each folder.
+fn render_dir_summary(f: &mut Frame, app: &App, area: Rect) {
+    let Some(summary) = &app.dir_summary else {
+        return;
+    };
+
+    let title = format!(
+        "Changed files ({}) - Enter opens the diff or folds a folder",
+        summary.changes.len()
+    );
+    let width = area.width.saturating_sub(2) as usize;
+    let format_counts =
+        |counts: ChangeCounts| format!("+{} -{}", counts.insertions, counts.deletions);
+    let file_name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let items: Vec<ListItem> = summary
+        .rows
+        .iter()
+        .enumerate()
+        .skip(summary.scroll_offset)
+        .take(inner_height(area))
+        .map(|(idx, row)| {
+            let (marker, path, counts) = match row {
+                TreeRow::Folder {
+                    path,
+                    depth,
+                    counts,
+                    collapsed,
+                } => {
+                    let arrow = if *collapsed {
+                        app.glyphs().collapsed
+                    } else {
+                        app.glyphs().expanded
+                    };
+                    let name = format!("{}{} {}/", "  ".repeat(*depth), arrow, file_name(path));
+                    (Span::raw("  "), name, format_counts(*counts))
+                }
+                TreeRow::File { index, depth } => {
+                    let change = &summary.changes[*index];
+                    let marker_color = match change.kind {
+                        ChangeKind::Added => app.theme.insert,
+                        ChangeKind::Removed => app.theme.delete,
+                        ChangeKind::Modified => app.theme.modified,
+                    };
+                    let marker = Span::styled(
+                        format!("{} ", change.kind.marker()),
+                        Style::default().fg(marker_color),
+                    );
+                    // Files line up with the name of their folder, past its arrow
+                    let indent = "  ".repeat(*depth);
+                    let name = format!("{}{}", indent, file_name(&change.path));
+                    let counts = change.counts.map_or("binary".to_string(), format_counts);
+                    (marker, name, counts)
+                }
+            };
+            // Right-align the counts, keeping at least a space after the path
+            let path_width = width
+                .saturating_sub(2 + counts.chars().count() + 1)
+                .max(path.chars().count());
+
+            let style = if idx == summary.selected_index {
+                Style::default()
+                    .bg(Color::DarkGray)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            ListItem::new(Line::from(vec![
+                marker,
+                Span::raw(format!("{:<path_width$} ", path)),
+                Span::styled(counts, Style::default().fg(Color::DarkGray)),
+            ]))
+            .style(style)
+        })
+        .collect();
+
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(list, area);
+    render_scrollbar(f, area, summary.rows.len(), summary.scroll_offset);
 }
 
-fn render_file_browser(f: &mut Frame, app: &App, area: ratatui::layout::Rect) {
-    let title = if app.mode == AppMode::SelectingSource {
+fn render_file_browser(f: &mut Frame, app: &App, area: Rect) {
+    let mut title = if app.mode == AppMode::SelectingSource {
         format!(
             "Select Source File - {}",
             app.file_browser.current_dir.display()
@@ -122,8 +1248,13 @@ fn render_file_browser(f: &mut Frame, app: &App, area: ratatui::layout::Rect) {
             app.file_browser.current_dir.display()
         )
     };
+    if let Some(filter) = app.file_browser.filter_label() {
+        title.push_str(&format!(" [{}]", filter));
+    }
 
-    let content_height = area.height.saturating_sub(2) as usize;
+    let content_height = inner_height(area);
+    let width = area.width.saturating_sub(2) as usize;
+    let now = SystemTime::now();
     let items: Vec<ListItem> = app
         .file_browser
         .entries
@@ -132,7 +1263,19 @@ fn render_file_browser(f: &mut Frame, app: &App, area: ratatui::layout::Rect) {
         .skip(app.file_browser.scroll_offset)
         .take(content_height)
         .map(|(idx, entry)| {
-            let display_name = app.file_browser.get_display_name(entry);
+            let name = app.file_browser.get_display_name(entry);
+            let modified = app.file_browser.modified.get(idx).copied().flatten();
+            let display_name = match modified {
+                Some(modified) => {
+                    // Right-align the time, keeping at least a space after the name
+                    let time = browser::format_modified(modified, now);
+                    let name_width = width
+                        .saturating_sub(time.chars().count() + 1)
+                        .max(name.chars().count());
+                    format!("{:<name_width$} {}", name, time)
+                }
+                None => name,
+            };
             let style = if idx == app.file_browser.selected_index {
                 Style::default()
                     .bg(Color::DarkGray)
@@ -148,9 +1291,300 @@ fn render_file_browser(f: &mut Frame, app: &App, area: ratatui::layout::Rect) {
     let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
 
     f.render_widget(list, area);
+    render_scrollbar(
+        f,
+        area,
+        app.file_browser.entries.len(),
+        app.file_browser.scroll_offset,
+    );
+}
+
+/// Draws a scrollbar on the right border of a bordered `area`.
+///
+/// The thumb covers the visible part of `total` rows, starting at `position`.
+fn render_scrollbar(f: &mut Frame, area: Rect, total: usize, position: usize) {
+    let visible = inner_height(area);
+    if visible == 0 || total <= visible {
+        return;
+    }
+
+    // Positions run from 0 until the last line sits at the bottom of the viewport
+    let mut state = ScrollbarState::new(total - visible + 1)
+        .position(position)
+        .viewport_content_length(visible);
+    let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+        .begin_symbol(None)
+        .end_symbol(None);
+
+    f.render_stateful_widget(
+        scrollbar,
+        area.inner(Margin {
+            vertical: 1,
+            horizontal: 0,
+        }),
+        &mut state,
+    );
 }
 
-fn render_status_bar(f: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+/// Keybindings listed in the help popup, grouped by the mode they apply to.
+const HELP_SECTIONS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "Diff view",
+        &[
+            ("q", "Quit"),
+            ("s", "Select source file"),
+            ("t", "Select target file"),
+            ("u", "Undo the last source/target selection"),
+            ("v", "Enter selection mode"),
+            ("c", "Copy diff to clipboard"),
+            ("e", "Export diff as patch file"),
+            ("d", "Preview the export without writing it"),
+            ("M", "Copy diff as a Markdown ```diff block"),
+            ("H", "Copy diff as colored HTML, for emails"),
+            ("p", "Start/stop a patch series that collects exports"),
+            ("N", "Normalize CRLF line endings to LF before diffing"),
+            ("W", "Ignore whitespace changes when diffing"),
+            ("r", "Reload both files, keeping the scroll position"),
+            (
+                "Tab/S-Tab",
+                "Next / previous file pair when several are open",
+            ),
+            ("o", "Edit the target file in $EDITOR"),
+            ("S", "Copy the absolute path of the source file"),
+            ("T", "Copy the absolute path of the target file"),
+            ("y", "Copy the target text without diff prefixes"),
+            ("Y", "Copy the source text without diff prefixes"),
+            ("z", "Fold unchanged regions"),
+            ("f", "Show only changes (toggles)"),
+            ("h", "Show changed hunks with dividers (toggles)"),
+            ("w", "Toggle line wrapping"),
+            ("m", "Show a minimap of where the changes are"),
+            ("F", "Hide borders, header and status bar (toggles)"),
+            ("|", "Toggle the side-by-side view"),
+            ("=", "Scroll side-by-side panes independently"),
+            ("Tab", "Switch the scrolled pane when independent"),
+            ("Ctrl-w", "Show trailing whitespace"),
+            ("Enter", "Expand a fold or show a cut line in full"),
+            ("i", "Show a cursor on the current line (toggles)"),
+            ("l", "Copy the line under the cursor"),
+            ("L", "Copy the line under the cursor with its +/- prefix"),
+            ("↑/↓ j/k", "Scroll (move the cursor when shown or folded)"),
+            ("gg/G", "Jump to the top/bottom"),
+            (":", "Jump to a line of the target file"),
+            ("?", "Show this help"),
+        ],
+    ),
+    (
+        "Selection mode",
+        &[
+            ("Space", "Mark selection start/end"),
+            ("a", "Select all lines"),
+            ("x", "Clear the selection"),
+            ("}/{", "Extend the selection by the next/previous hunk"),
+            ("↑/↓ j/k", "Move cursor and adjust selection"),
+            ("gg/G", "Jump to the top/bottom"),
+            ("c", "Copy selection to clipboard"),
+            (
+                "e",
+                "Export selection as patch file (appends to an active series)",
+            ),
+            ("d", "Preview the selection export without writing it"),
+            ("y/Y", "Copy target/source text of the selection"),
+            ("Ctrl-w", "Show trailing whitespace"),
+            ("v", "Exit selection mode, keeping the selection for later"),
+            ("q", "Quit"),
+        ],
+    ),
+    (
+        "Directory summary",
+        &[
+            ("↑/↓ j/k", "Pick a changed file or folder"),
+            ("Enter", "Open the diff of the file, or fold the folder"),
+            ("Esc", "Back to the summary (from the diff)"),
+            ("q", "Quit"),
+        ],
+    ),
+    (
+        "File browser",
+        &[
+            ("↑/↓", "Navigate files and directories"),
+            ("Enter", "Select file or enter directory"),
+            ("g", "Type a directory to jump to (Tab completes)"),
+            (
+                "f",
+                "Only list files with the selected file's extension (toggles)",
+            ),
+            ("Esc/q", "Cancel selection"),
+        ],
+    ),
+];
+
+/// The help text, with the arrow keys drawn as `glyphs.up_down`.
+fn help_lines(glyphs: &Glyphs) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+
+    for (idx, (section, bindings)) in HELP_SECTIONS.iter().enumerate() {
+        if idx > 0 {
+            lines.push(Line::default());
+        }
+        lines.push(Line::from(Span::styled(
+            *section,
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )));
+        for (key, description) in bindings.iter() {
+            lines.push(Line::from(vec![
+                Span::styled(
+                    format!("  {:<10}", key.replace("↑/↓", glyphs.up_down)),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(*description),
+            ]));
+        }
+    }
+
+    lines
+}
+
+/// The centered area the help popup is drawn in.
+fn help_popup_area(area: Rect) -> Rect {
+    popup_area(area, 60, help_lines(&glyphs::UNICODE).len())
+}
+
+/// A bordered popup of up to `width` columns centered in `area`, tall enough for `lines`.
+fn popup_area(area: Rect, width: u16, lines: usize) -> Rect {
+    let width = area.width.min(width);
+    let height = (lines as u16 + 2).min(area.height.saturating_sub(2));
+
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// The largest scroll offset for the help popup, so the last line stays reachable.
+pub fn help_max_scroll(terminal_area: Rect) -> usize {
+    help_lines(&glyphs::UNICODE)
+        .len()
+        .saturating_sub(inner_height(help_popup_area(terminal_area)))
+}
+
+fn render_help_popup(f: &mut Frame, app: &App) {
+    let area = help_popup_area(f.area());
+    let lines = help_lines(app.glyphs());
+    let total = lines.len();
+
+    let help = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "Help - {} to scroll, any other key to close",
+            app.glyphs().up_down
+        )))
+        .scroll((app.help_scroll as u16, 0));
+
+    f.render_widget(Clear, area);
+    f.render_widget(help, area);
+    render_scrollbar(f, area, total, app.help_scroll);
+}
+
+/// The line under the cursor in full, wrapped to the popup, for lines that were cut.
+fn render_line_popup(f: &mut Frame, app: &App, content: &str) {
+    let width = f.area().width.saturating_sub(4);
+    let line = Line::from(content_spans(
+        content,
+        Style::default(),
+        app.tab_width,
+        app.show_tabs,
+        false,
+        app.glyphs(),
+    ));
+    let rows = wrap_line(line, width.saturating_sub(2) as usize, usize::MAX, &[]);
+
+    let area = popup_area(f.area(), width, rows.len());
+    let popup = Paragraph::new(rows).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Full line - any key to close"),
+    );
+
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+fn render_export_preview(f: &mut Frame, app: &App, preview: &ExportPreview) {
+    let action = if preview.appends { "append" } else { "write" };
+    let mut lines = vec![
+        Line::from(format!(
+            "Would {} {} lines to {}",
+            action, preview.total_lines, preview.path
+        )),
+        Line::from(""),
+    ];
+    lines.extend(preview.excerpt.iter().map(|line| {
+        let style = match line.chars().next() {
+            Some('+') => Style::default().fg(app.theme.insert),
+            Some('-') => Style::default().fg(app.theme.delete),
+            _ if line.starts_with(app.glyphs().ellipsis) => {
+                Style::default().add_modifier(Modifier::DIM)
+            }
+            _ => Style::default(),
+        };
+        Line::styled(line.clone(), style)
+    }));
+
+    let area = popup_area(f.area(), 80, lines.len());
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Export preview - nothing written, any key to close"),
+    );
+
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+fn render_status_bar(f: &mut Frame, app: &App, area: Rect) {
+    if let Some(input) = &app.line_prompt {
+        let prompt = Paragraph::new(format!(":{}", input))
+            .block(Block::default().borders(Borders::ALL).title("Go to line"));
+        f.render_widget(prompt, area);
+        f.set_cursor_position(Position::new(area.x + 2 + input.len() as u16, area.y + 1));
+        return;
+    }
+
+    if let Some(input) = &app.path_prompt {
+        // Long paths scroll so the end being typed stays visible
+        let width = area.width.saturating_sub(3) as usize;
+        let skip = input.chars().count().saturating_sub(width);
+        let visible: String = input.chars().skip(skip).collect();
+        let cursor = visible.chars().count() as u16;
+
+        let prompt = Paragraph::new(visible).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Go to directory (Tab completes)"),
+        );
+        f.render_widget(prompt, area);
+        f.set_cursor_position(Position::new(area.x + 1 + cursor, area.y + 1));
+        return;
+    }
+
+    let export_label = match &app.patch_series {
+        Some(series) => format!(" Export (series: {} hunks)  ", series.hunks),
+        None => " Export  ".to_string(),
+    };
+    let arrows = format!("[{}]", app.glyphs().up_down);
+    // The header says when there's no clipboard, only offer copying when it can work
+    let copy_hint = match app.copy_method() {
+        Some(_) => vec![
+            Span::styled("[c]", Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(" Copy  "),
+        ],
+        None => Vec::new(),
+    };
+
     let status_text = if let Some(ref msg) = app.status_message {
         vec![Line::from(Span::styled(
             msg,
@@ -160,41 +1594,71 @@ fn render_status_bar(f: &mut Frame, app: &App, area: ratatui::layout::Rect) {
         ))]
     } else {
         match app.mode {
-            AppMode::DiffView => vec![Line::from(vec![
-                Span::raw("Commands: "),
+            AppMode::DiffView => vec![Line::from(
+                [
+                    Span::raw("Commands: "),
+                    Span::styled("[q]", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" Quit  "),
+                    Span::styled("[s]", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" Select source  "),
+                    Span::styled("[t]", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" Select target  "),
+                    Span::styled("[v]", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" Selection mode  "),
+                    Span::styled("[z]", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" Fold  "),
+                    Span::styled("[w]", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(if app.wrap {
+                        " Wrap: on  "
+                    } else {
+                        " Wrap: off  "
+                    }),
+                ]
+                .into_iter()
+                .chain(copy_hint)
+                .chain([
+                    Span::styled("[e]", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(export_label.clone()),
+                    Span::styled(arrows, Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" Scroll  "),
+                    Span::styled("[?]", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" Help"),
+                ])
+                .collect::<Vec<_>>(),
+            )],
+            AppMode::SelectionMode => vec![Line::from(
+                [
+                    Span::raw("Commands: "),
+                    Span::styled("[v]", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" Exit selection  "),
+                    Span::styled("[Space]", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" Mark start/end  "),
+                ]
+                .into_iter()
+                .chain(copy_hint)
+                .chain([
+                    Span::styled("[e]", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(export_label),
+                    Span::styled(arrows, Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" Adjust selection"),
+                ])
+                .collect::<Vec<_>>(),
+            )],
+            AppMode::DirSummary => vec![Line::from(vec![
+                Span::styled(arrows, Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" Navigate  "),
+                Span::styled("[Enter]", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" Open diff / fold folder  "),
                 Span::styled("[q]", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" Quit  "),
-                Span::styled("[s]", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" Select source  "),
-                Span::styled("[t]", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" Select target  "),
-                Span::styled("[v]", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" Selection mode  "),
-                Span::styled("[c]", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" Copy  "),
-                Span::styled("[e]", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" Export  "),
-                Span::styled("[↑/↓]", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" Scroll"),
-            ])],
-            AppMode::SelectionMode => vec![Line::from(vec![
-                Span::raw("Commands: "),
-                Span::styled("[v]", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" Exit selection  "),
-                Span::styled("[Space]", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" Mark start/end  "),
-                Span::styled("[c]", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" Copy  "),
-                Span::styled("[e]", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" Export  "),
-                Span::styled("[↑/↓]", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" Adjust selection"),
+                Span::raw(" Quit"),
             ])],
             AppMode::SelectingSource | AppMode::SelectingTarget => vec![Line::from(vec![
-                Span::styled("[↑/↓]", Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(arrows, Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(" Navigate  "),
                 Span::styled("[Enter]", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(" Select  "),
+                Span::styled("[g]", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" Go to directory  "),
                 Span::styled("[Esc]", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(" Cancel"),
             ])],
@@ -205,3 +1669,544 @@ fn render_status_bar(f: &mut Frame, app: &App, area: ratatui::layout::Rect) {
 
     f.render_widget(status_bar, area);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::LineKind;
+    use crate::theme::Theme;
+    use similar::Algorithm;
+
+    const PATH: &str = "/home/user/projects/lazydiff/src/main.rs";
+
+    #[test]
+    fn test_dir_summary_marks_modified_files_in_the_theme_color()
+    -> Result<(), Box<dyn std::error::Error>> {
+        use crate::dirdiff::{ChangeKind, DirSummary, FileChange};
+        use ratatui::{Terminal, backend::TestBackend};
+        use std::path::PathBuf;
+
+        let mut app = App::new_empty(AppMode::DirSummary)?;
+        app.theme.modified = Color::Magenta;
+        app.dir_summary = Some(DirSummary::new(
+            PathBuf::from("old"),
+            PathBuf::from("new"),
+            vec![FileChange {
+                path: PathBuf::from("a.txt"),
+                kind: ChangeKind::Modified,
+                counts: None,
+            }],
+        ));
+
+        let mut terminal = Terminal::new(TestBackend::new(40, 10))?;
+        terminal.draw(|f| render_ui(f, &app))?;
+
+        let buffer = terminal.backend().buffer();
+        let marker = buffer
+            .content()
+            .iter()
+            .find(|cell| cell.symbol() == "M")
+            .expect("the modified file is listed");
+        assert_eq!(marker.fg, Color::Magenta);
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_path_keeps_short_paths() {
+        assert_eq!(truncate_path(PATH, 100, "…"), PATH);
+        assert_eq!(truncate_path(PATH, PATH.len(), "…"), PATH);
+    }
+
+    #[test]
+    fn test_truncate_path_drops_middle_directories() {
+        assert_eq!(truncate_path(PATH, 30, "…"), "/home/…/lazydiff/src/main.rs");
+        assert_eq!(truncate_path(PATH, 20, "…"), "/home/…/src/main.rs");
+        assert_eq!(
+            truncate_path("src/very/deep/module/file.rs", 20, "…"),
+            "src/…/module/file.rs"
+        );
+    }
+
+    #[test]
+    fn test_truncate_path_falls_back_to_file_name() {
+        assert_eq!(
+            truncate_path("src/very/deep/module/file.rs", 11, "…"),
+            "…/file.rs"
+        );
+        assert_eq!(truncate_path(PATH, 6, "…"), "…in.rs");
+        assert_eq!(truncate_path(PATH, 0, "…"), "");
+        assert_eq!(truncate_path(PATH, 8, "..."), "...in.rs");
+        assert_eq!(truncate_path(PATH, 2, "..."), "");
+    }
+
+    #[test]
+    fn test_cursor_highlights_every_wrapped_row() -> Result<(), Box<dyn std::error::Error>> {
+        let mut app = App::new_empty(AppMode::DiffView)?;
+        app.diff_lines = vec![DiffLine {
+            tag: ChangeTag::Insert,
+            content: "x".repeat(28),
+            new_line: Some(1),
+            missing_newline: false,
+            kind: LineKind::Standalone,
+        }];
+        app.rebuild_rows();
+        app.show_cursor = true;
+
+        let area = Rect::new(0, 0, 12, 6);
+        let mut buf = Buffer::empty(area);
+        let diff_list = DiffList {
+            app: &app,
+            title: Line::default(),
+            selection_mode: false,
+        };
+        let mut state = diff_list.list_state();
+        StatefulWidget::render(diff_list, area, &mut buf, &mut state);
+
+        for y in 1..=3 {
+            assert_eq!(buf[(1, y)].bg, Color::DarkGray, "row {} is highlighted", y);
+        }
+        assert_ne!(buf[(1, 4)].bg, Color::DarkGray);
+        Ok(())
+    }
+
+    #[test]
+    fn test_colorblind_theme_styles_diff_line_spans() -> Result<(), Box<dyn std::error::Error>> {
+        let mut app = App::new_empty(AppMode::DiffView)?;
+        app.theme = Theme::named("colorblind").unwrap();
+        let diff_line = DiffLine {
+            tag: ChangeTag::Delete,
+            content: "old".to_string(),
+            new_line: None,
+            missing_newline: false,
+            kind: LineKind::Standalone,
+        };
+
+        let line = styled_diff_line(&app, &diff_line);
+
+        let orange = Style::default().fg(Color::Indexed(208));
+        assert_eq!(line.spans[0].style, orange.add_modifier(Modifier::BOLD));
+        assert_eq!(line.spans[1].style, Style::default().fg(Color::DarkGray));
+        assert_eq!(line.spans[2].style, orange);
+        Ok(())
+    }
+
+    #[test]
+    fn test_char_granularity_reverses_changed_characters() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let mut app = App::new_empty(AppMode::DiffView)?;
+        app.diff_lines = diff::generate_diff("teh end\n", "the end\n", Algorithm::Myers);
+        app.granularity = Granularity::Char;
+
+        let line = styled_row(&app, &DiffRow::Line(0));
+
+        let reversed: Vec<&str> = line
+            .spans
+            .iter()
+            .filter(|span| span.style.add_modifier.contains(Modifier::REVERSED))
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert_eq!(reversed, vec!["h"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimap_compresses_the_whole_diff() -> Result<(), Box<dyn std::error::Error>> {
+        let mut app = App::new_empty(AppMode::DiffView)?;
+        app.diff_lines =
+            diff::generate_diff("a\nb\nc\nd\ne\nf\n", "a\nB\nc\nd\ne\nf\n", Algorithm::Myers);
+
+        let area = Rect::new(0, 0, 2, 3);
+        let mut buf = Buffer::empty(area);
+        Minimap { app: &app }.render(area, &mut buf);
+
+        // Seven lines in three rows of two: the deleted line falls in the first row and
+        // the inserted line in the second
+        assert_eq!(buf[(0, 0)].fg, app.theme.delete);
+        assert_eq!(buf[(1, 0)].fg, app.theme.delete);
+        assert_eq!(buf[(0, 1)].fg, app.theme.insert);
+        assert_eq!(buf[(0, 2)].symbol(), "░");
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrap_line_stops_at_max_rows() {
+        let line = Line::from("abcdefghij");
+
+        let rows = wrap_line(line.clone(), 3, 2, &[]);
+
+        assert_eq!(rows, vec![Line::from("abc"), Line::from("def")]);
+        assert_eq!(wrapped_height(&line, 3, 0), 4);
+    }
+
+    #[test]
+    fn test_wrap_line_indents_continuation_rows() {
+        let line = Line::from("abcdefghij");
+        let indent = [Span::raw("  ")];
+
+        let rows = wrap_line(line.clone(), 5, usize::MAX, &indent);
+
+        let text: Vec<String> = rows.iter().map(|row| row.to_string()).collect();
+        assert_eq!(text, vec!["abcde", "  fgh", "  ij"]);
+        assert_eq!(wrapped_height(&line, 5, 2), rows.len());
+        // Without room for text after the indent the rows aren't indented
+        assert_eq!(wrap_line(line.clone(), 2, usize::MAX, &indent).len(), 5);
+        assert_eq!(wrapped_height(&line, 2, 2), 5);
+    }
+
+    #[test]
+    fn test_huge_single_line_renders_a_screenful() -> Result<(), Box<dyn std::error::Error>> {
+        // Like a minified file: one line of a megabyte that changed at the end
+        let source = "x".repeat(1 << 20);
+        let target = format!("{}y", source);
+        let mut app = App::new_empty(AppMode::DiffView)?;
+        app.diff_lines = diff::generate_diff(&source, &target, Algorithm::Myers);
+        app.rebuild_rows();
+        app.view_width = 80;
+
+        // Only the rows that fit on screen are built, not the whole line's
+        let diff_list = DiffList {
+            app: &app,
+            title: Line::default(),
+            selection_mode: false,
+        };
+        assert_eq!(diff_list.item(&app.rows[0], 78, 22).height(), 22);
+        assert_eq!(
+            row_height(&app, &app.rows[0], 80),
+            ((1 << 20) + 1usize).div_ceil(78)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_reading_notice_fills_the_blank_screen() -> Result<(), Box<dyn std::error::Error>> {
+        use ratatui::{Terminal, backend::TestBackend};
+
+        let mut terminal = Terminal::new(TestBackend::new(40, 10))?;
+        terminal.draw(|f| render_reading_notice(f, false))?;
+
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("\u{280b} Reading files\u{2026}"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_row_cache_follows_the_diff_and_settings() -> Result<(), Box<dyn std::error::Error>> {
+        let mut app = App::new_empty(AppMode::DiffView)?;
+        app.diff_lines = diff::generate_diff("a\tb\n", "c\n", Algorithm::Myers);
+        app.rebuild_rows();
+        let text = |line: Line<'_>| line.to_string();
+
+        assert_eq!(
+            text(styled_row(&app, &DiffRow::Line(0))),
+            "-\u{2502}a       b"
+        );
+        // A setting the rows are styled with restyles them
+        app.show_tabs = true;
+        assert_eq!(
+            text(styled_row(&app, &DiffRow::Line(0))),
+            "-\u{2502}a\u{2192}      b"
+        );
+
+        // A new diff does too
+        app.diff_lines = diff::generate_diff("x\n", "y\n", Algorithm::Myers);
+        app.rebuild_rows();
+        assert_eq!(text(styled_row(&app, &DiffRow::Line(0))), "-\u{2502}x");
+        Ok(())
+    }
+
+    #[test]
+    fn test_whitespace_markers_show_on_lines_compared_without_whitespace()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let mut app = App::new_empty(AppMode::DiffView)?;
+        app.diff_lines =
+            diff::generate_diff_ignoring_whitespace("a  \nb\n", "a\nc\n", Algorithm::Myers);
+        app.show_whitespace = true;
+        app.rebuild_rows();
+        let text = |line: Line<'_>| line.to_string();
+
+        // The line counts as unchanged but still shows the whitespace that was ignored
+        assert_eq!(
+            text(styled_row(&app, &DiffRow::Line(0))),
+            " \u{2502}a\u{b7}\u{b7}"
+        );
+        assert_eq!(app.diff_lines[0].content, "a  ");
+        Ok(())
+    }
+
+    #[test]
+    fn test_reload_shows_the_reading_notice() -> Result<(), Box<dyn std::error::Error>> {
+        use ratatui::{Terminal, backend::TestBackend};
+
+        let mut app = App::new_empty(AppMode::DiffView)?;
+        app.diff_lines = diff::generate_diff("a\n", "b\n", Algorithm::Myers);
+        app.rebuild_rows();
+        app.ascii = true;
+        app.reload_requested = true;
+
+        let mut terminal = Terminal::new(TestBackend::new(40, 10))?;
+        terminal.draw(|f| render_ui(f, &app))?;
+
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("| Reading files..."), "{}", screen);
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_stats_calls_out_empty_files() {
+        let stats = |bytes: &[u8]| diff::decode_content(bytes).stats;
+
+        assert_eq!(format_stats(stats(b"")), " (empty)");
+        assert_eq!(format_stats(stats(b"\n")), " (1 B, 1 line)");
+        assert_eq!(format_stats(stats(b"a\nb")), " (3 B, 2 lines)");
+    }
+
+    #[test]
+    fn test_hidden_chrome_gives_the_diff_the_whole_screen() -> Result<(), Box<dyn std::error::Error>>
+    {
+        use ratatui::{Terminal, backend::TestBackend};
+
+        let mut app = App::new_empty(AppMode::DiffView)?;
+        app.diff_lines = diff::generate_diff("a\n", "b\n", Algorithm::Myers);
+        app.rebuild_rows();
+        app.toggle_chrome();
+
+        let mut terminal = Terminal::new(TestBackend::new(20, 4))?;
+        terminal.draw(|f| render_ui(f, &app))?;
+
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| -> String { (0..20).map(|x| buffer[(x, y)].symbol()).collect() };
+        assert_eq!(row(0).trim_end(), "-│a");
+        assert_eq!(row(1).trim_end(), "+│b");
+        assert!(!row(3).contains('─'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ascii_mode_spells_out_the_arrows() -> Result<(), Box<dyn std::error::Error>> {
+        use ratatui::{Terminal, backend::TestBackend};
+
+        let mut app = App::new_empty(AppMode::DiffView)?;
+        app.diff_lines = diff::generate_diff("a\n", "b\n", Algorithm::Myers);
+        app.rebuild_rows();
+        app.status_message = None;
+        app.ascii = true;
+
+        let mut terminal = Terminal::new(TestBackend::new(160, 8))?;
+        terminal.draw(|f| render_ui(f, &app))?;
+
+        let buffer = terminal.backend().buffer();
+        let status: String = (0..160).map(|x| buffer[(x, 6)].symbol()).collect();
+        assert!(status.contains("[Up/Dn] Scroll"), "{}", status);
+        assert!(!status.contains('↑'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_clipboard_is_shown_upfront() -> Result<(), Box<dyn std::error::Error>> {
+        use ratatui::{Terminal, backend::TestBackend};
+
+        let mut app = App::new_empty(AppMode::DiffView)?;
+        app.diff_lines = diff::generate_diff("a\n", "b\n", Algorithm::Myers);
+        app.rebuild_rows();
+        app.status_message = None;
+        app.clipboard = None;
+        app.osc52 = false;
+
+        let mut terminal = Terminal::new(TestBackend::new(200, 8))?;
+        terminal.draw(|f| render_ui(f, &app))?;
+
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| -> String { (0..200).map(|x| buffer[(x, y)].symbol()).collect() };
+        assert!(row(1).contains("no clipboard"), "{}", row(1));
+        assert!(!row(6).contains("[c] Copy"), "{}", row(6));
+        assert!(row(6).contains("[e] Export"));
+
+        // A terminal to send OSC 52 to is enough to copy
+        app.osc52 = true;
+        terminal.draw(|f| render_ui(f, &app))?;
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| -> String { (0..200).map(|x| buffer[(x, y)].symbol()).collect() };
+        assert!(row(1).contains("copying via OSC 52"), "{}", row(1));
+        assert!(row(6).contains("[c] Copy"), "{}", row(6));
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_warnings_use_the_theme() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::diff::LineEnding;
+        use ratatui::{Terminal, backend::TestBackend};
+
+        let mut app = App::new_empty(AppMode::DiffView)?;
+        app.theme.modified = Color::Magenta;
+        app.line_endings = Some((LineEnding::Crlf, LineEnding::Lf));
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 8))?;
+        terminal.draw(|f| render_ui(f, &app))?;
+
+        let buffer = terminal.backend().buffer();
+        let row: String = (0..100).map(|x| buffer[(x, 1)].symbol()).collect();
+        let start = row
+            .find("line endings differ")
+            .expect("the warning is shown");
+        let x = row[..start].chars().count() as u16;
+        assert_eq!(buffer[(x, 1)].fg, Color::Magenta);
+        assert_eq!(buffer[(x - 1, 1)].fg, Color::Magenta);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cut_spans_keeps_graphemes_whole() {
+        let spans = vec![Span::raw("ab"), Span::raw("ce\u{301}de")];
+
+        let cut = cut_spans(spans.clone(), 5, "…");
+
+        let text: Vec<&str> = cut.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, vec!["ab", "ce\u{301}", "…"]);
+        assert_eq!(cut_spans(spans.clone(), 6, "…"), spans);
+        // A wide character that doesn't fit is left out instead of split
+        let text: Vec<String> = cut_spans(vec![Span::raw("日本語")], 4, "…")
+            .iter()
+            .map(|span| span.content.to_string())
+            .collect();
+        assert_eq!(text, vec!["日", "…"]);
+        // The marker takes the color of the text it replaces
+        let green = Style::default().fg(Color::Green);
+        let cut = cut_spans(vec![Span::raw("-"), Span::styled("abcdef", green)], 4, "…");
+        assert_eq!(cut[2], Span::styled("…", green.add_modifier(Modifier::DIM)));
+    }
+
+    #[test]
+    fn test_is_cut_counts_tabs_and_wide_characters() {
+        assert!(!is_cut("abcd", 4, 8));
+        assert!(is_cut("abcde", 4, 8));
+        assert!(is_cut("a\tb", 4, 4));
+        assert!(is_cut("日本語", 5, 8));
+    }
+
+    #[test]
+    fn test_wrap_line_keeps_short_lines() {
+        let line = Line::from("short");
+
+        assert_eq!(wrap_line(line.clone(), 10, usize::MAX, &[]), vec![line]);
+
+        // Wrapping and the row height measure the same way, bytes don't count
+        let line = Line::from("\u{e9}t\u{e9}");
+        assert_eq!(wrap_line(line.clone(), 3, usize::MAX, &[]).len(), 1);
+        assert_eq!(wrapped_height(&line, 3, 0), 1);
+    }
+
+    #[test]
+    fn test_wrap_line_counts_wide_characters_twice() {
+        // Each of these takes two columns, the third doesn't fit after two
+        let line = Line::from("\u{65e5}\u{672c}\u{8a9e}a");
+
+        let rows = wrap_line(line.clone(), 5, usize::MAX, &[]);
+
+        let text: Vec<String> = rows.iter().map(|row| row.to_string()).collect();
+        assert_eq!(text, vec!["\u{65e5}\u{672c}", "\u{8a9e}a"]);
+        assert_eq!(wrapped_height(&line, 5, 0), rows.len());
+        assert_eq!(span_width(&line.spans), 7);
+    }
+
+    #[test]
+    fn test_wrap_line_splits_across_spans() {
+        let red = Style::default().fg(Color::Red);
+        let line = Line::from(vec![Span::styled("-", red), Span::styled("abcdefgh", red)]);
+
+        let rows = wrap_line(line, 4, usize::MAX, &[]);
+
+        assert_eq!(
+            rows,
+            vec![
+                Line::from(vec![Span::styled("-", red), Span::styled("abc", red)]),
+                Line::from(vec![Span::styled("defg", red)]),
+                Line::from(vec![Span::styled("h", red)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_content_spans_expands_tabs_to_tab_stops() {
+        let style = Style::default();
+
+        let spans = content_spans("a\tbc\td", style, 4, false, false, &glyphs::UNICODE);
+
+        let text: String = spans.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, "a   bc  d");
+    }
+
+    #[test]
+    fn test_content_spans_marks_tabs() {
+        let style = Style::default();
+
+        let spans = content_spans("\tx", style, 8, true, false, &glyphs::UNICODE);
+
+        assert_eq!(
+            spans,
+            vec![
+                Span::styled("→       ", style.add_modifier(Modifier::DIM)),
+                Span::styled("x", style),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_content_spans_without_tabs_borrows() {
+        let spans = content_spans("plain", Style::default(), 8, true, true, &glyphs::UNICODE);
+
+        assert_eq!(spans, vec![Span::raw("plain")]);
+    }
+
+    #[test]
+    fn test_content_spans_counts_wide_characters_towards_tab_stops() {
+        let style = Style::default();
+
+        let spans = content_spans(
+            "\u{65e5}\u{672c}\tx",
+            style,
+            8,
+            false,
+            false,
+            &glyphs::UNICODE,
+        );
+
+        // The two characters take four columns, so the tab fills the other four
+        assert_eq!(
+            spans,
+            vec![
+                Span::styled("\u{65e5}\u{672c}", style),
+                Span::styled("    ", style),
+                Span::styled("x", style),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_content_spans_marks_trailing_whitespace() {
+        let style = Style::default().fg(Color::Green);
+        let faint = Style::default().fg(Color::DarkGray);
+
+        let spans = content_spans("a b \t", style, 4, false, true, &glyphs::UNICODE);
+
+        assert_eq!(
+            spans,
+            vec![
+                Span::styled("a b", style),
+                Span::styled("·", faint),
+                Span::styled("→   ", faint),
+            ]
+        );
+    }
+}
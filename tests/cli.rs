@@ -0,0 +1,772 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+#[test]
+fn test_missing_source_file_is_reported() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["does-not-exist.txt", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("does not exist"));
+}
+
+#[test]
+fn test_git_mode_rejects_untracked_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let file = dir.child("untracked.txt");
+    file.write_str("hello\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .arg("--git")
+        .arg(file.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Error:"));
+
+    Ok(())
+}
+
+#[test]
+fn test_git_mode_conflicts_with_positional_files() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["--git", "Cargo.toml", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_conflicts_mode_without_markers_has_nothing_to_resolve()
+-> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let file = dir.child("clean.txt");
+    file.write_str("no conflicts here\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .arg("--conflicts")
+        .arg(file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing to resolve"));
+
+    Ok(())
+}
+
+#[test]
+fn test_conflicts_mode_rejects_unterminated_conflict() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let file = dir.child("broken.txt");
+    file.write_str("<<<<<<< HEAD\nours\n=======\ntheirs\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .arg("--conflicts")
+        .arg(file.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not terminated"));
+
+    Ok(())
+}
+
+#[test]
+fn test_print_identical_files_exits_zero() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let source = dir.child("source.txt");
+    let target = dir.child("target.txt");
+    source.write_str("same\ncontent\n")?;
+    target.write_str("same\ncontent\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .arg("--print")
+        .arg(source.path())
+        .arg(target.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_print_empty_files_exits_zero() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let source = dir.child("source.txt");
+    let target = dir.child("target.txt");
+    source.touch()?;
+    target.touch()?;
+
+    cargo_bin_cmd!("lazydiff")
+        .arg("--print")
+        .arg(source.path())
+        .arg(target.path())
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn test_print_different_files_prints_patch() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let source = dir.child("source.txt");
+    let target = dir.child("target.txt");
+    source.write_str("old\n")?;
+    target.write_str("new\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .arg("--print")
+        .arg(source.path())
+        .arg(target.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("-old\n+new\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_print_json_lists_the_lines_of_each_pair() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let source = dir.child("source.txt");
+    let target = dir.child("target.txt");
+    source.write_str("old\n")?;
+    target.write_str("new\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .args(["--print", "--format", "json"])
+        .arg(source.path())
+        .arg(target.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::starts_with("[\n"))
+        .stdout(predicate::str::contains(r#""identical": false"#))
+        .stdout(predicate::str::contains(r#""tag": "delete""#));
+
+    // The format only applies to --print
+    cargo_bin_cmd!("lazydiff")
+        .args(["--format", "json"])
+        .arg(source.path())
+        .arg(target.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--print"));
+
+    Ok(())
+}
+
+#[test]
+fn test_print_side_by_side_writes_two_columns() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let source = dir.child("source.txt");
+    let target = dir.child("target.txt");
+    source.write_str("same\nold\n")?;
+    target.write_str("same\nnew\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .args(["--print", "--side-by-side", "--width", "21"])
+        .arg(source.path())
+        .arg(target.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "same        same\nold       | new\n",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_files_can_be_given_as_flags() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let source = dir.child("source.txt");
+    let target = dir.child("target.txt");
+    source.write_str("old\n")?;
+    target.write_str("new\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .arg("--print")
+        .arg("--target")
+        .arg(target.path())
+        .arg("--source")
+        .arg(source.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("-old\n+new\n"));
+
+    // The named target leaves the positional argument for the source, here stdin
+    cargo_bin_cmd!("lazydiff")
+        .arg("--print")
+        .arg("--target")
+        .arg(target.path())
+        .arg("-")
+        .write_stdin("old\n")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("--- <stdin>"));
+
+    // Each side is given one way or the other
+    cargo_bin_cmd!("lazydiff")
+        .arg("--print")
+        .arg("--source")
+        .arg(source.path())
+        .arg(source.path())
+        .arg(target.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+
+    Ok(())
+}
+
+#[test]
+fn test_list_reads_pairs_from_a_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    for (name, content) in [
+        ("a1", "one\n"),
+        ("a2", "two\n"),
+        ("b1", "same\n"),
+        ("b2", "same\n"),
+    ] {
+        dir.child(name).write_str(content)?;
+    }
+    let path = |name: &str| dir.child(name).path().display().to_string();
+    let manifest = format!(
+        "# batch\n{}\t{}\n{}\t{}\n",
+        path("a1"),
+        path("a2"),
+        path("b1"),
+        path("b2")
+    );
+
+    cargo_bin_cmd!("lazydiff")
+        .args(["--print", "--list", "-"])
+        .write_stdin(manifest)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("-one\n+two\n"));
+
+    let list = dir.child("list.txt");
+    list.write_str(&format!(
+        "{}\t{}\n{} {}\n",
+        path("a1"),
+        path("a2"),
+        path("b1"),
+        path("b2")
+    ))?;
+    cargo_bin_cmd!("lazydiff")
+        .args(["--print", "--list"])
+        .arg(list.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("line 2"));
+
+    Ok(())
+}
+
+#[test]
+fn test_list_resolves_paths_against_its_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("lists/a.txt").write_str("one\n")?;
+    dir.child("lists/b.txt").write_str("two\n")?;
+    let list = dir.child("lists/list.txt");
+    list.write_str("a.txt\tb.txt\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .current_dir(dir.path())
+        .args(["--print", "--list", "lists/list.txt"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("-one\n+two\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_print_against_an_empty_target_deletes_everything() -> Result<(), Box<dyn std::error::Error>>
+{
+    let dir = assert_fs::TempDir::new()?;
+    let source = dir.child("source.txt");
+    let target = dir.child("target.txt");
+    source.write_str("one\ntwo\n")?;
+    target.touch()?;
+
+    cargo_bin_cmd!("lazydiff")
+        .arg("--print")
+        .arg(source.path())
+        .arg(target.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::ends_with(
+            "target.txt\n@@ -1,2 +0,0 @@\n-one\n-two\n",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_unknown_algorithm_is_rejected() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["--algorithm", "histogram", "Cargo.toml", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "expected one of: myers, patience, lcs",
+        ));
+}
+
+#[test]
+fn test_from_clipboard_conflicts_with_positional_files() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["--from-clipboard", "Cargo.toml", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_print_git_format_headers() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("old.txt").write_str("old\n")?;
+    dir.child("new.txt").write_str("new\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .current_dir(dir.path())
+        .args(["--print", "--git-format", "old.txt", "new.txt"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::starts_with(
+            "diff --git a/old.txt b/new.txt\nindex 0000000..0000000\n--- a/old.txt\n+++ b/new.txt\n",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_apply_prints_patch_sides() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let patch = dir.child("change.patch");
+    patch.write_str("--- a/x.txt\n+++ b/x.txt\n@@ -1,2 +1,2 @@\n keep\n-old\n+new\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .arg("--print")
+        .arg("--apply")
+        .arg(patch.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(" keep\n-old\n+new\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_apply_rejects_malformed_patch() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let patch = dir.child("broken.patch");
+    patch.write_str("this is not a diff\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .arg("--apply")
+        .arg(patch.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid patch"));
+
+    Ok(())
+}
+
+#[test]
+fn test_print_line_ranges() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let source = dir.child("source.log");
+    let target = dir.child("target.log");
+    source.write_str("a\nb\nsame\n")?;
+    target.write_str("x\ny\nz\nsame\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .args(["--print", "--source-range", "3:9", "--target-range", "4:4"])
+        .arg(source.path())
+        .arg(target.path())
+        .assert()
+        .success()
+        .stdout("");
+
+    Ok(())
+}
+
+#[test]
+fn test_inverted_line_range_is_rejected() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["--source-range", "20:10", "Cargo.toml", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("inverted"));
+}
+
+#[test]
+fn test_print_several_pairs() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("a1.txt").write_str("same\n")?;
+    dir.child("a2.txt").write_str("same\n")?;
+    dir.child("b1.txt").write_str("old\n")?;
+    dir.child("b2.txt").write_str("new\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .current_dir(dir.path())
+        .args(["--print", "a1.txt", "a2.txt", "b1.txt", "b2.txt"])
+        .assert()
+        .code(1)
+        .stdout("--- b1.txt\n+++ b2.txt\n@@ -1,1 +1,1 @@\n-old\n+new\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_print_uses_the_context() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("old.txt").write_str("a\nb\nc\nd\ne\n")?;
+    dir.child("new.txt").write_str("a\nb\nC\nd\ne\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .current_dir(dir.path())
+        .args(["--print", "--context", "1", "--context-after", "0"])
+        .args(["old.txt", "new.txt"])
+        .assert()
+        .code(1)
+        .stdout("--- old.txt\n+++ new.txt\n@@ -2,2 +2,2 @@\n b\n-c\n+C\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_unpaired_file_is_rejected() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["Cargo.toml", "Cargo.toml", "README.md"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("source and target pairs"));
+}
+
+#[test]
+fn test_stat_prints_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("old.txt").write_str("keep\nold\ngone\n")?;
+    dir.child("new.txt").write_str("keep\nnew\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .current_dir(dir.path())
+        .args(["--stat", "old.txt", "new.txt"])
+        .assert()
+        .code(1)
+        .stdout(" old.txt => new.txt | 3 +--\n 1 file changed, 1 insertion(+), 2 deletions(-)\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_stat_identical_files_prints_nothing() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["--stat", "Cargo.toml", "Cargo.toml"])
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[test]
+fn test_exit_code_conflicts_with_print() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["--exit-code", "--print", "Cargo.toml", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_print_with_external_command() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("old.txt").write_str("old\n")?;
+    dir.child("new.txt").write_str("new\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .current_dir(dir.path())
+        .args([
+            "--print",
+            "--external",
+            "echo compared",
+            "old.txt",
+            "new.txt",
+        ])
+        .assert()
+        .code(1)
+        .stdout("compared old.txt new.txt\n");
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_print_with_external_command_reads_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("new.txt").write_str("new\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .current_dir(dir.path())
+        .args(["--print", "--external", "diff -u", "-", "new.txt"])
+        .write_stdin("old\n")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("-old\n+new\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_recursive_identical_directories() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("old/a.txt").write_str("same\n")?;
+    dir.child("new/a.txt").write_str("same\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .current_dir(dir.path())
+        .args(["--recursive", "old", "new"])
+        .assert()
+        .success()
+        .stdout("No differences between 'old' and 'new'\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_recursive_rejects_files() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["--recursive", "Cargo.toml", "README.md"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a directory"));
+}
+
+#[test]
+fn test_git_range_same_revision_prints_nothing() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["--print", "--git-range", "HEAD", "HEAD", "Cargo.toml"])
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[test]
+fn test_git_range_rejects_unknown_revision() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["--git-range", "no-such-revision", "HEAD", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Unknown revision 'no-such-revision'",
+        ));
+}
+
+#[test]
+fn test_max_lines_rejects_zero() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["--max-lines", "0", "Cargo.toml", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--max-lines"));
+}
+
+#[test]
+fn test_print_reads_stdin_for_dash() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("new.txt").write_str("new\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .current_dir(dir.path())
+        .args(["--print", "-", "new.txt"])
+        .write_stdin("old\n")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("--- <stdin>"))
+        .stdout(predicate::str::contains("-old\n+new\n"));
+    Ok(())
+}
+
+#[test]
+fn test_stdin_cannot_be_both_sides() {
+    cargo_bin_cmd!("lazydiff")
+        .args(["--print", "-", "-"])
+        .write_stdin("text\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Only one side can be read from stdin",
+        ));
+}
+
+#[test]
+fn test_context_is_read_from_the_environment() {
+    cargo_bin_cmd!("lazydiff")
+        .env("LAZYDIFF_CONTEXT", "lots")
+        .args(["--print", "Cargo.toml", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value 'lots'"));
+
+    // The flag wins, so the variable isn't even parsed
+    cargo_bin_cmd!("lazydiff")
+        .env("LAZYDIFF_CONTEXT", "lots")
+        .args(["--print", "--context", "2", "Cargo.toml", "Cargo.toml"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_theme_variable_overrides_config_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("lazydiff/config.toml")
+        .write_str("[theme]\nname = \"from-config\"\n")?;
+
+    cargo_bin_cmd!("lazydiff")
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env_remove("LAZYDIFF_THEME")
+        .args(["Cargo.toml", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown theme 'from-config'"));
+
+    cargo_bin_cmd!("lazydiff")
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("LAZYDIFF_THEME", "from-env")
+        .args(["Cargo.toml", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown theme 'from-env'"));
+    Ok(())
+}
+
+#[test]
+fn test_theme_flag_overrides_variable() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+
+    // Unknown names make the theme that was picked show up in the error
+    cargo_bin_cmd!("lazydiff")
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("LAZYDIFF_THEME", "from-env")
+        .args(["--theme", "from-flag", "Cargo.toml", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown theme 'from-flag'"));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_no_follow_symlinks_rejects_symlinked_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("real.txt").write_str("text\n")?;
+    std::os::unix::fs::symlink("real.txt", dir.path().join("link.txt"))?;
+
+    cargo_bin_cmd!("lazydiff")
+        .current_dir(dir.path())
+        .args(["--print", "real.txt", "link.txt"])
+        .assert()
+        .success();
+
+    cargo_bin_cmd!("lazydiff")
+        .current_dir(dir.path())
+        .args(["--print", "--no-follow-symlinks", "real.txt", "link.txt"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("'link.txt' is a symlink"));
+    Ok(())
+}
+
+#[test]
+fn test_read_only_disables_export() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+    let export_dir = temp.child("patches");
+
+    // There's nowhere to export to, so asking for an export directory is a mistake
+    for flag in ["--read-only", "--safe"] {
+        cargo_bin_cmd!("lazydiff")
+            .arg(flag)
+            .arg("--export-dir")
+            .arg(export_dir.path())
+            .args(["Cargo.toml", "Cargo.toml"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("cannot be used with"));
+    }
+    export_dir.assert(predicate::path::missing());
+
+    cargo_bin_cmd!("lazydiff")
+        .args(["--read-only", "--from-clipboard", "Cargo.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+
+    Ok(())
+}
+
+/// Runs the TUI in a pseudo terminal with util-linux `script`, typing `keys` into it.
+/// `script -e` exits with the exit code of lazydiff.
+#[cfg(target_os = "linux")]
+fn run_in_terminal(dir: &std::path::Path, args: &str, keys: &str) -> assert_cmd::assert::Assert {
+    let command = format!(
+        "{} {}",
+        assert_cmd::cargo::cargo_bin!("lazydiff").display(),
+        args
+    );
+    assert_cmd::Command::new("script")
+        .args(["-qec", &command, "/dev/null"])
+        .current_dir(dir)
+        .env("HOME", dir)
+        .env("XDG_CONFIG_HOME", dir)
+        .write_stdin(keys)
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_read_only_export_writes_nothing() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("old.txt").write_str("old\n")?;
+    dir.child("new.txt").write_str("new\n")?;
+    let patches = || -> Vec<_> {
+        std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "patch"))
+            .collect()
+    };
+
+    // Press e to export, then q to quit
+    run_in_terminal(dir.path(), "--read-only old.txt new.txt", "eq").success();
+    assert!(patches().is_empty());
+
+    // The same keys without --read-only do export
+    run_in_terminal(dir.path(), "old.txt new.txt", "eq").success();
+    assert_eq!(patches().len(), 1);
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_exit_code_after_quitting() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("old.txt").write_str("old\n")?;
+    dir.child("new.txt").write_str("new\n")?;
+    dir.child("same.txt").write_str("old\n")?;
+
+    run_in_terminal(dir.path(), "--exit-code old.txt new.txt", "q").code(1);
+    run_in_terminal(dir.path(), "--exit-code old.txt same.txt", "q").code(0);
+    // Without the flag quitting is always a success
+    run_in_terminal(dir.path(), "old.txt new.txt", "q").code(0);
+    // Trouble isn't mistaken for a difference
+    run_in_terminal(dir.path(), "--exit-code old.txt missing.txt", "q").code(2);
+    run_in_terminal(dir.path(), "old.txt missing.txt", "q").code(1);
+
+    Ok(())
+}